@@ -0,0 +1,156 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+use webcrawler::config::Limits;
+use webcrawler::state::CrawlState;
+use webcrawler::storage::PageAnnotations;
+use webcrawler::{Crawler, Scraper, Storage, TaskContext};
+
+mod support;
+
+use support::SyntheticSite;
+
+const BASE: &str = "http://127.0.0.1:8000";
+
+fn site(n_pages: usize, links_per_page: usize) -> SyntheticSite {
+    SyntheticSite {
+        n_pages,
+        links_per_page,
+        text_size: 4096,
+    }
+}
+
+fn link_extraction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("link_extraction");
+    for links_per_page in [10, 100, 1000] {
+        let page = site(10_000, links_per_page).page(BASE, 0);
+        group.throughput(Throughput::Bytes(page.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(links_per_page),
+            page.as_bytes(),
+            |b, page| b.iter(|| Scraper::scrape(page)),
+        );
+    }
+    group.finish();
+}
+
+fn url_normalization(c: &mut Criterion) {
+    let hrefs: Vec<_> = (0..1000)
+        .map(|i| format!("{}/section/{}/page/{}?q={}#top", BASE, i % 7, i, i))
+        .collect();
+    let storage = Storage::new("unused".into());
+    let mut group = c.benchmark_group("url_normalization");
+    group.throughput(Throughput::Elements(hrefs.len() as u64));
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            hrefs
+                .iter()
+                .filter_map(|href| url::Url::parse(href).ok())
+                .count()
+        })
+    });
+    let urls: Vec<_> = hrefs.iter().map(|h| url::Url::parse(h).unwrap()).collect();
+    group.bench_function("url_to_path", |b| {
+        b.iter(|| {
+            urls.iter()
+                .map(|url| storage.url_to_path(url))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.finish();
+}
+
+fn frontier_operations(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let urls: Vec<_> = (0..10_000)
+        .map(|i| url::Url::parse(&format!("{}/page/{}", BASE, i)).unwrap())
+        .collect();
+    let (visited, frontier) = urls.split_at(urls.len() / 2);
+    let contexts = frontier.iter().cloned().map(TaskContext::seed).collect();
+    let state = CrawlState::new(urls[0].clone(), visited.to_vec(), contexts);
+
+    let mut group = c.benchmark_group("frontier");
+    group.throughput(Throughput::Elements(urls.len() as u64));
+    group.bench_function("dedup", |b| {
+        b.iter(|| {
+            let mut seen = std::collections::HashSet::new();
+            urls.iter()
+                .chain(frontier)
+                .filter(|url| seen.insert(*url))
+                .count()
+        })
+    });
+    group.bench_function("save", |b| b.to_async(&rt).iter(|| state.save(dir.path())));
+    group.bench_function("load", |b| {
+        b.to_async(&rt).iter(|| CrawlState::load(dir.path()))
+    });
+    group.finish();
+}
+
+fn storage_writes(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::new(dir.path().to_path_buf());
+    rt.block_on(storage.setup()).unwrap();
+    let page = site(1000, 50).page(BASE, 0);
+    let urls: Vec<_> = (0..100)
+        .map(|i| url::Url::parse(&format!("{}/page/{}", BASE, i)).unwrap())
+        .collect();
+
+    let mut group = c.benchmark_group("storage");
+    group.throughput(Throughput::Bytes((page.len() * urls.len()) as u64));
+    group.bench_function("serialize", |b| {
+        b.to_async(&rt).iter(|| async {
+            for url in &urls {
+                storage
+                    .serialize(&page, &PageAnnotations::new(url.clone()))
+                    .await
+                    .unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn crawl(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let n_pages = 200;
+    let base = rt.block_on(site(n_pages, 20).serve());
+
+    let mut group = c.benchmark_group("crawl");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(n_pages as u64));
+    for max_tasks in [1, 8, 32] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(max_tasks),
+            &max_tasks,
+            |b, &max_tasks| {
+                b.to_async(&rt).iter(|| async {
+                    let dir = tempfile::tempdir().unwrap();
+                    let storage = Storage::new(dir.path().to_path_buf());
+                    Crawler::new(format!("{}/page/0", base), Some(storage), None)
+                        .unwrap()
+                        .with_limits(Limits {
+                            max_tasks,
+                            max_pages: n_pages,
+                            ..Limits::default()
+                        })
+                        .run()
+                        .await
+                        .unwrap();
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    link_extraction,
+    url_normalization,
+    frontier_operations,
+    storage_writes,
+    crawl
+);
+criterion_main!(benches);
@@ -0,0 +1,85 @@
+//! Synthetic local website for benchmarking crawls.
+//!
+//! The site consists of `n_pages` pages, each linking to
+//! `links_per_page` other pages of the site, and is served over
+//! a minimal HTTP/1.1 server bound to a random local port.
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// The shape of a generated website
+#[derive(Debug, Clone, Copy)]
+pub struct SyntheticSite {
+    pub n_pages: usize,
+    pub links_per_page: usize,
+    /// Bytes of filler text per page
+    pub text_size: usize,
+}
+
+impl SyntheticSite {
+    /// The HTML of the `index`-th page, linking to
+    /// other pages under `base`
+    pub fn page(&self, base: &str, index: usize) -> String {
+        let links: String = (1..=self.links_per_page)
+            .map(|k| {
+                let target = (index * self.links_per_page + k) % self.n_pages;
+                format!(
+                    "<li><a href=\"{}/page/{}\">Page {}</a></li>",
+                    base, target, target
+                )
+            })
+            .collect();
+        format!(
+            "<!DOCTYPE html><html><head><title>Page {index}</title></head>\
+             <body><h1>Page {index}</h1><p>{}</p><ul>{}</ul></body></html>",
+            "lorem ipsum ".repeat(self.text_size / 12),
+            links,
+        )
+    }
+
+    /// Serve the site on a local port, returning its base url
+    pub async fn serve(self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let site_base = base.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    break;
+                };
+                let base = site_base.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+                    let index = path
+                        .strip_prefix("/page/")
+                        .and_then(|index| index.parse::<usize>().ok())
+                        .filter(|index| *index < self.n_pages);
+                    let response = match index {
+                        Some(index) => {
+                            let body = self.page(&base, index);
+                            format!(
+                                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\
+                                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                body.len(),
+                                body
+                            )
+                        }
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\
+                                 Connection: close\r\n\r\n"
+                            .to_string(),
+                    };
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        base
+    }
+}
@@ -0,0 +1,37 @@
+/* C interface of the webcrawler library, enabled by the `capi` feature */
+#ifndef WEBCRAWLER_H
+#define WEBCRAWLER_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A running crawl */
+typedef struct WebcrawlerHandle WebcrawlerHandle;
+
+/* Start a crawl configured by a JSON object with the `root_url` to start
+ * from, and optionally the `limits`, `policy`, `scraper` and `storage`
+ * sections of the crawl configuration. Returns NULL on error. */
+WebcrawlerHandle *webcrawler_start(const char *config);
+
+/* The next event of the crawl as a JSON object, or NULL if none is pending.
+ * The last event is of `"type": "finished"`. */
+char *webcrawler_poll(WebcrawlerHandle *handle);
+
+/* Stop queueing pages, completing the visits in progress */
+void webcrawler_stop(WebcrawlerHandle *handle);
+
+/* Stop the crawl, wait for it to end, and free the handle */
+void webcrawler_free(WebcrawlerHandle *handle);
+
+/* The description of the last error on the calling thread, or NULL */
+char *webcrawler_last_error(void);
+
+/* Free a string returned by the interface */
+void webcrawler_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* WEBCRAWLER_H */
@@ -0,0 +1,317 @@
+//! Audit extractors.
+//!
+//! Audits check every visited page for common issues, and report the
+//! findings of each page with any in their own JSONL report.
+use reqwest::header::{self, HeaderMap};
+use scraper::{ElementRef, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::extract::Extractor;
+use crate::page::CrawledPage;
+use crate::parse::Document;
+
+/// Name of the security headers report
+pub const SECURITY_FILE: &str = "security.jsonl";
+
+/// Name of the mixed content report
+pub const MIXED_CONTENT_FILE: &str = "mixed_content.jsonl";
+
+/// Name of the accessibility report
+pub const ACCESSIBILITY_FILE: &str = "accessibility.jsonl";
+
+/// Min `max-age` of a `Strict-Transport-Security` header, of 180 days
+const MIN_HSTS_MAX_AGE: u64 = 180 * 24 * 60 * 60;
+
+/// The kind of a finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The header is missing
+    Missing,
+    /// The header is present but offers little protection
+    Weak,
+}
+
+/// An issue with a security header of a response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderFinding {
+    /// The lowercase name of the header
+    pub header: String,
+    pub severity: Severity,
+    pub detail: String,
+}
+
+impl HeaderFinding {
+    fn new(header: &header::HeaderName, severity: Severity, detail: &str) -> Self {
+        Self {
+            header: header.as_str().to_string(),
+            severity,
+            detail: detail.to_string(),
+        }
+    }
+}
+
+/// The security header findings of a page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityFindings {
+    pub url: url::Url,
+    pub findings: Vec<HeaderFinding>,
+}
+
+/// Checks the responses for missing or weak security headers:
+/// `Content-Security-Policy`, `Strict-Transport-Security` on https
+/// pages, `X-Frame-Options` and `X-Content-Type-Options`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SecurityHeadersAudit;
+
+impl SecurityHeadersAudit {
+    /// The findings of a response of `url` with `headers`
+    pub fn check(url: &url::Url, headers: &HeaderMap) -> Vec<HeaderFinding> {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_ascii_lowercase())
+        };
+        let mut findings = Vec::new();
+        let csp = get(header::CONTENT_SECURITY_POLICY);
+        match &csp {
+            None => findings.push(HeaderFinding::new(
+                &header::CONTENT_SECURITY_POLICY,
+                Severity::Missing,
+                "no content security policy",
+            )),
+            Some(csp) if csp.contains("'unsafe-inline'") || csp.contains("'unsafe-eval'") => {
+                findings.push(HeaderFinding::new(
+                    &header::CONTENT_SECURITY_POLICY,
+                    Severity::Weak,
+                    "allows unsafe inline scripts or eval",
+                ))
+            }
+            Some(_) => {}
+        }
+        if url.scheme() == "https" {
+            match get(header::STRICT_TRANSPORT_SECURITY) {
+                None => findings.push(HeaderFinding::new(
+                    &header::STRICT_TRANSPORT_SECURITY,
+                    Severity::Missing,
+                    "https is not enforced",
+                )),
+                Some(hsts) => {
+                    let max_age = hsts
+                        .split(';')
+                        .filter_map(|directive| directive.trim().strip_prefix("max-age="))
+                        .find_map(|secs| secs.trim_matches('"').parse::<u64>().ok())
+                        .unwrap_or_default();
+                    if max_age < MIN_HSTS_MAX_AGE {
+                        findings.push(HeaderFinding::new(
+                            &header::STRICT_TRANSPORT_SECURITY,
+                            Severity::Weak,
+                            "max-age is shorter than 180 days",
+                        ));
+                    }
+                }
+            }
+        }
+        let frame_ancestors = csp
+            .as_deref()
+            .is_some_and(|csp| csp.contains("frame-ancestors"));
+        match get(header::X_FRAME_OPTIONS) {
+            None if !frame_ancestors => findings.push(HeaderFinding::new(
+                &header::X_FRAME_OPTIONS,
+                Severity::Missing,
+                "the page can be framed by any site",
+            )),
+            Some(options) if options != "deny" && options != "sameorigin" => {
+                findings.push(HeaderFinding::new(
+                    &header::X_FRAME_OPTIONS,
+                    Severity::Weak,
+                    "expected `DENY` or `SAMEORIGIN`",
+                ))
+            }
+            _ => {}
+        }
+        if get(header::X_CONTENT_TYPE_OPTIONS).as_deref() != Some("nosniff") {
+            findings.push(HeaderFinding::new(
+                &header::X_CONTENT_TYPE_OPTIONS,
+                Severity::Missing,
+                "content types can be sniffed",
+            ));
+        }
+        findings
+    }
+}
+
+impl Extractor for SecurityHeadersAudit {
+    fn report_file(&self) -> &str {
+        SECURITY_FILE
+    }
+
+    fn extract(&self, _url: &url::Url, _document: &Document) -> Option<Value> {
+        None
+    }
+
+    fn extract_page(&self, page: &CrawledPage, _document: &Document) -> Option<Value> {
+        let findings = Self::check(&page.final_url, &page.headers);
+        if findings.is_empty() {
+            return None;
+        }
+        let record = SecurityFindings {
+            url: page.url.clone(),
+            findings,
+        };
+        serde_json::to_value(record).ok()
+    }
+}
+
+/// The insecure references of an https page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MixedContent {
+    pub url: url::Url,
+    /// The subresources loaded over http
+    pub subresources: Vec<url::Url>,
+    /// The links to http pages
+    pub links: Vec<url::Url>,
+}
+
+/// Detects the http subresources and links of https pages
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MixedContentAudit;
+
+impl Extractor for MixedContentAudit {
+    fn report_file(&self) -> &str {
+        MIXED_CONTENT_FILE
+    }
+
+    fn extract(&self, url: &url::Url, document: &Document) -> Option<Value> {
+        if url.scheme() != "https" {
+            return None;
+        }
+        let insecure = |urls: Vec<url::Url>| {
+            urls.into_iter()
+                .filter(|url| url.scheme() == "http")
+                .collect::<Vec<_>>()
+        };
+        let record = MixedContent {
+            url: url.clone(),
+            subresources: insecure(document.subresources()),
+            links: insecure(document.links()),
+        };
+        if record.subresources.is_empty() && record.links.is_empty() {
+            return None;
+        }
+        serde_json::to_value(record).ok()
+    }
+
+    fn extract_page(&self, page: &CrawledPage, document: &Document) -> Option<Value> {
+        // Pages redirected to https are checked as served
+        let mut record = self.extract(&page.final_url, document)?;
+        record["url"] = Value::String(page.url.to_string());
+        Some(record)
+    }
+}
+
+/// The accessibility issues of a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessibilityFindings {
+    pub url: url::Url,
+    /// The root element declares no `lang`
+    pub missing_lang: bool,
+    /// The `src` of the images with no `alt` text
+    pub images_without_alt: Vec<String>,
+    /// The `href` of the links with no accessible name
+    pub empty_links: Vec<String>,
+    /// Number of the buttons with no accessible name
+    pub empty_buttons: usize,
+}
+
+impl AccessibilityFindings {
+    fn is_empty(&self) -> bool {
+        !self.missing_lang
+            && self.images_without_alt.is_empty()
+            && self.empty_links.is_empty()
+            && self.empty_buttons == 0
+    }
+}
+
+/// Checks basic accessibility signals of the pages: images missing alt
+/// text, links and buttons with no accessible name, and a missing `lang`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AccessibilityAudit;
+
+impl AccessibilityAudit {
+    /// Whether `element` has a text, a label or an image with alt text
+    /// that assistive technologies can announce
+    fn has_accessible_name(element: &ElementRef) -> bool {
+        let value = element.value();
+        let labelled = ["aria-label", "aria-labelledby", "title"]
+            .iter()
+            .any(|attr| {
+                value
+                    .attr(attr)
+                    .is_some_and(|label| !label.trim().is_empty())
+            });
+        let image = Selector::parse("img[alt]").unwrap();
+        labelled
+            || element.text().any(|text| !text.trim().is_empty())
+            || element.select(&image).any(|img| {
+                !img.value()
+                    .attr("alt")
+                    .unwrap_or_default()
+                    .trim()
+                    .is_empty()
+            })
+    }
+
+    /// The findings of the page at `url`
+    pub fn check(url: &url::Url, document: &Document) -> AccessibilityFindings {
+        let html = Selector::parse("html").unwrap();
+        let images = Selector::parse("img:not([alt])").unwrap();
+        let links = Selector::parse("a[href]").unwrap();
+        let buttons =
+            Selector::parse(r#"button, input[type="button"], input[type="submit"]"#).unwrap();
+        AccessibilityFindings {
+            url: url.clone(),
+            missing_lang: !document.select(&html).any(|root| {
+                root.value()
+                    .attr("lang")
+                    .is_some_and(|lang| !lang.trim().is_empty())
+            }),
+            images_without_alt: document
+                .select(&images)
+                .map(|img| img.value().attr("src").unwrap_or_default().to_string())
+                .collect(),
+            empty_links: document
+                .select(&links)
+                .filter(|link| !Self::has_accessible_name(link))
+                .filter_map(|link| link.value().attr("href").map(str::to_string))
+                .collect(),
+            empty_buttons: document
+                .select(&buttons)
+                .filter(|button| match button.value().name() {
+                    "button" => !Self::has_accessible_name(button),
+                    // Inputs without a value are labelled by the browser
+                    _ => button
+                        .value()
+                        .attr("value")
+                        .is_some_and(|value| value.trim().is_empty()),
+                })
+                .count(),
+        }
+    }
+}
+
+impl Extractor for AccessibilityAudit {
+    fn report_file(&self) -> &str {
+        ACCESSIBILITY_FILE
+    }
+
+    fn extract(&self, url: &url::Url, document: &Document) -> Option<Value> {
+        let findings = Self::check(url, document);
+        if findings.is_empty() {
+            return None;
+        }
+        serde_json::to_value(findings).ok()
+    }
+}
@@ -0,0 +1,78 @@
+//! Inbound links of the pages of a crawl.
+//!
+//! The anchors of the latest stored version of every HTML page are
+//! inverted into the list of the pages of the crawl linking to each
+//! page, with the text of their anchors, for internal linking analysis:
+//! the pages linked from few others, or by vague anchor texts, are the
+//! ones to link better. Links to urls that were not stored, and links of
+//! a page to itself, are left out.
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::manifest::Manifest;
+use crate::parse::Document;
+use crate::Storage;
+
+/// A link to a page from another page of the crawl
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Backlink {
+    pub source: url::Url,
+    /// The text of the anchor, empty if it has none
+    pub text: String,
+}
+
+/// The inbound links of a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageBacklinks {
+    pub url: url::Url,
+    pub backlinks: Vec<Backlink>,
+}
+
+/// The inbound links of every page stored in `dir`, the most linked
+/// pages first. The pages that cannot be read, e.g. encrypted ones,
+/// are skipped with a warning.
+pub async fn backlinks(dir: &Path) -> Result<Vec<PageBacklinks>> {
+    let manifest = Manifest::load(dir).await?;
+    let storage = Storage::new(dir.to_path_buf());
+    let latest = manifest.latest();
+    let mut inbound: HashMap<&url::Url, BTreeSet<Backlink>> =
+        latest.keys().map(|url| (*url, BTreeSet::new())).collect();
+    for entry in latest.values().filter(|entry| !entry.binary) {
+        let body = match storage.read(entry).await {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::warn!("skipping {}: {}", entry.url, err);
+                continue;
+            }
+        };
+        for (mut target, text) in Document::parse(&body).with_url(&entry.url).anchors() {
+            target.set_fragment(None);
+            if target == entry.url {
+                continue;
+            }
+            if let Some(backlinks) = inbound.get_mut(&target) {
+                backlinks.insert(Backlink {
+                    source: entry.url.clone(),
+                    text,
+                });
+            }
+        }
+    }
+    let mut pages: Vec<PageBacklinks> = inbound
+        .into_iter()
+        .map(|(url, backlinks)| PageBacklinks {
+            url: url.clone(),
+            backlinks: backlinks.into_iter().collect(),
+        })
+        .collect();
+    pages.sort_by(|a, b| {
+        b.backlinks
+            .len()
+            .cmp(&a.backlinks.len())
+            .then_with(|| a.url.cmp(&b.url))
+    });
+    Ok(pages)
+}
@@ -0,0 +1,172 @@
+//! Batches of crawls of several sites.
+//!
+//! A batch crawls any number of sites at once within the process, each one
+//! from its root url with the same [`CrawlConfig`], but isolated from the
+//! others: every site has its own frontier and visited urls, and is stored
+//! in its own directory, named by the host of its root url, under the batch
+//! root. The sites share a number of visit slots, so that the batch visits
+//! at most as many pages at a time as there are slots, whatever the number
+//! of sites, on top of the limit of concurrent visits of each crawl.
+//!
+//! Once every crawl is over, the batch root records a report of each site,
+//! with the error its crawl failed with, if any, and the stats of its run.
+//! A failed crawl does not stop the other ones.
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+use crate::config::CrawlConfig;
+use crate::error::{CrawlerError, Result};
+use crate::executor::{self, Executor};
+use crate::run::{RunMetadata, RunStats};
+use crate::Crawler;
+
+/// Name of the file reporting the crawl of every site of a batch
+pub const BATCH_FILE: &str = "batch.jsonl";
+
+/// Default number of pages visited at a time across the sites of a batch
+pub const DEFAULT_VISIT_SLOTS: usize = 32;
+
+/// The outcome of the crawl of a site of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteReport {
+    pub root_url: url::Url,
+    /// The storage directory of the site
+    pub dir: PathBuf,
+    /// The error the crawl failed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The stats of the run, if it got to start
+    pub stats: Option<RunStats>,
+}
+
+/// Crawls several sites at once, storing each one under its root
+pub struct Batch {
+    root: PathBuf,
+    config: CrawlConfig,
+    sites: Vec<url::Url>,
+    visit_slots: usize,
+    executor: Arc<dyn Executor>,
+}
+
+impl Batch {
+    /// A batch crawling its sites with `config`, whatever its root url,
+    /// seeds and storage directory, and visiting at most `visit_slots`
+    /// pages at a time across the sites
+    pub fn new(root: PathBuf, config: CrawlConfig, visit_slots: usize) -> Self {
+        Self {
+            root,
+            config,
+            sites: Vec::new(),
+            visit_slots,
+            executor: executor::default_executor(),
+        }
+    }
+
+    /// Spawn the crawls, and their tasks, on `executor`
+    pub fn with_executor(mut self, executor: Arc<dyn Executor>) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    /// Crawl the site of `root_url`
+    pub fn with_site(mut self, root_url: url::Url) -> Self {
+        self.sites.push(root_url);
+        self
+    }
+
+    /// Crawl the sites until every crawl is over, returning the report
+    /// of each site, in order.
+    ///
+    /// Fails before crawling any site if the config is invalid, or two
+    /// sites would be stored in the same directory.
+    pub async fn run(self) -> Result<Vec<SiteReport>> {
+        executor::compat(self.crawl()).await
+    }
+
+    async fn crawl(self) -> Result<Vec<SiteReport>> {
+        let mut names = HashSet::new();
+        for site in &self.sites {
+            let name = site_name(site)?;
+            if !names.insert(name.clone()) {
+                return Err(CrawlerError::InvalidConfig(vec![format!(
+                    "two sites of the batch are stored as `{}`",
+                    name
+                )]));
+            }
+        }
+        let slots = Arc::new(Semaphore::new(self.visit_slots.max(1)));
+        let mut crawls = Vec::with_capacity(self.sites.len());
+        for site in self.sites {
+            let dir = self.root.join(site_name(&site)?);
+            let mut config = self.config.clone();
+            config.root_url = site.to_string();
+            config.seeds.clear();
+            config.storage.dir = Some(dir.clone());
+            let crawler = Crawler::from_config(config)?
+                .with_visit_slots(Arc::clone(&slots))
+                .with_executor(Arc::clone(&self.executor));
+            crawls.push((site, dir, crawler));
+        }
+        tracing::info!(
+            "==> Crawling {} sites with {} visit slots",
+            crawls.len(),
+            slots.available_permits()
+        );
+        let mut runs = Vec::with_capacity(crawls.len());
+        for (site, dir, crawler) in crawls {
+            tracing::info!("==> Starting the crawl of {}", site);
+            let run = executor::spawn(self.executor.as_ref(), crawler.run());
+            runs.push((site, dir, run));
+        }
+        let mut reports = Vec::with_capacity(runs.len());
+        for (site, dir, run) in runs {
+            let error = match run.await {
+                Ok(Ok(())) => {
+                    tracing::info!("==> Completed the crawl of {}", site);
+                    None
+                }
+                Ok(Err(err)) => {
+                    tracing::warn!("==> The crawl of {} failed: {}", site, err);
+                    Some(err.to_string())
+                }
+                Err(payload) => {
+                    let message = executor::panic_message(payload.as_ref());
+                    tracing::warn!("==> The crawl of {} panicked: {}", site, message);
+                    Some(message)
+                }
+            };
+            let stats = match RunMetadata::load(&dir).await {
+                Ok(run) => run.stats,
+                Err(_) => None,
+            };
+            reports.push(SiteReport {
+                root_url: site,
+                dir,
+                error,
+                stats,
+            });
+        }
+        tokio::fs::create_dir_all(&self.root).await?;
+        let mut lines = String::new();
+        for report in &reports {
+            lines.push_str(&serde_json::to_string(report)?);
+            lines.push('\n');
+        }
+        tokio::fs::write(self.root.join(BATCH_FILE), lines).await?;
+        Ok(reports)
+    }
+}
+
+/// The name of the directory of the site of `url`, its host and the
+/// port of the url, if any
+fn site_name(url: &url::Url) -> Result<String> {
+    let host = url.host_str().ok_or(CrawlerError::NoUrlHost)?;
+    Ok(match url.port() {
+        Some(port) => format!("{}_{}", host, port),
+        None => host.to_string(),
+    })
+}
@@ -0,0 +1,140 @@
+//! Bounds on the memory held by in-flight page bodies.
+//!
+//! A [`ByteBudget`] is a semaphore sized in bytes and shared by all
+//! the crawl tasks. A task reserves its share of the budget while the
+//! body of a page is being downloaded, and releases it once the page
+//! is stored and scraped. Tasks exceeding the remaining budget wait,
+//! which throttles the crawl instead of exhausting the memory.
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A number of bytes shared by the crawl tasks
+#[derive(Debug, Clone)]
+pub struct ByteBudget {
+    semaphore: Arc<Semaphore>,
+    capacity: usize,
+}
+
+impl ByteBudget {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.clamp(1, Semaphore::MAX_PERMITS);
+        Self {
+            semaphore: Arc::new(Semaphore::new(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of bytes currently not reserved
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+}
+
+/// A share of a [`ByteBudget`], released on drop
+#[derive(Debug)]
+pub struct Reservation {
+    budget: Option<ByteBudget>,
+    permit: Option<OwnedSemaphorePermit>,
+    reserved: usize,
+}
+
+impl Reservation {
+    /// An empty reservation of `budget`; a missing
+    /// budget is treated as unbounded
+    pub fn new(budget: Option<ByteBudget>) -> Self {
+        Self {
+            budget,
+            permit: None,
+            reserved: 0,
+        }
+    }
+
+    /// Reserve `n` more bytes, waiting for them to be released by
+    /// other reservations if they are not available.
+    ///
+    /// A reservation that has to wait releases its bytes and waits for
+    /// its whole new size at once, so that no task ever holds part of
+    /// the budget while waiting for more, and two growing reservations
+    /// cannot deadlock. The bytes already received by the waiting task
+    /// are not read further until the reservation is granted.
+    ///
+    /// A single reservation never exceeds the capacity of the budget, so
+    /// that bodies larger than the budget are let through one at a time.
+    pub async fn grow(&mut self, n: usize) {
+        let Some(budget) = &self.budget else {
+            return;
+        };
+        let n = n
+            .min(budget.capacity - self.reserved)
+            .min(u32::MAX as usize - self.reserved);
+        if n == 0 {
+            return;
+        }
+        let semaphore = Arc::clone(&budget.semaphore);
+        if let Ok(permit) = Arc::clone(&semaphore).try_acquire_many_owned(n as u32) {
+            match &mut self.permit {
+                Some(current) => current.merge(permit),
+                None => self.permit = Some(permit),
+            }
+            self.reserved += n;
+            return;
+        }
+        tracing::debug!("in-flight bytes over budget, waiting for {}", n);
+        let total = self.reserved + n;
+        self.permit = None;
+        self.reserved = 0;
+        self.permit = semaphore.acquire_many_owned(total as u32).await.ok();
+        self.reserved = match self.permit {
+            Some(_) => total,
+            None => 0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn growth_waits_for_released_bytes() {
+        let budget = ByteBudget::new(100);
+        let mut first = Reservation::new(Some(budget.clone()));
+        first.grow(60).await;
+        first.grow(30).await;
+        assert_eq!(budget.available(), 10);
+
+        let mut second = Reservation::new(Some(budget.clone()));
+        second.grow(5).await;
+        let growth = tokio::time::timeout(Duration::from_millis(50), second.grow(20)).await;
+        assert!(growth.is_err(), "a chunk over budget is let through");
+
+        drop(first);
+        second.grow(20).await;
+        assert_eq!(budget.available(), 80);
+    }
+
+    #[tokio::test]
+    async fn growing_reservations_do_not_deadlock() {
+        let budget = ByteBudget::new(100);
+        let mut first = Reservation::new(Some(budget.clone()));
+        let mut second = Reservation::new(Some(budget.clone()));
+        first.grow(50).await;
+        second.grow(50).await;
+        let grow_first = async {
+            first.grow(50).await;
+            drop(first);
+        };
+        let both = futures::future::join(grow_first, second.grow(50));
+        tokio::time::timeout(Duration::from_secs(1), both)
+            .await
+            .expect("the reservations deadlocked");
+        assert_eq!(budget.available(), 0);
+    }
+}
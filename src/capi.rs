@@ -0,0 +1,210 @@
+//! C-compatible interface for embedding the crawler.
+//!
+//! A crawl is started from a JSON configuration, its events are polled
+//! as JSON strings, and it can be stopped at any time:
+//!
+//! ```c
+//! WebcrawlerHandle *crawl = webcrawler_start("{\"root_url\": \"https://example.com\"}");
+//! for (;;) {
+//!     char *event = webcrawler_poll(crawl);
+//!     if (event == NULL) { sleep(1); continue; }
+//!     int finished = strstr(event, "\"finished\"") != NULL;
+//!     webcrawler_string_free(event);
+//!     if (finished) break;
+//! }
+//! webcrawler_free(crawl);
+//! ```
+//!
+//! The declarations are found in `include/webcrawler.h`.
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use futures::channel::mpsc::{self, TryRecvError, UnboundedReceiver};
+use serde::Serialize;
+
+use crate::config::CrawlConfig;
+use crate::error::{CrawlerError, Result};
+use crate::page::CrawledPage;
+use crate::Crawler;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// An event of a running crawl
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Event {
+    /// A page was visited
+    Page {
+        url: url::Url,
+        final_url: url::Url,
+        status: u16,
+        depth: usize,
+    },
+    /// The crawl ended, with an error if it failed
+    Finished { error: Option<String> },
+}
+
+/// A running crawl
+pub struct WebcrawlerHandle {
+    pages: UnboundedReceiver<CrawledPage>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl WebcrawlerHandle {
+    fn start(config: &str) -> Result<Self> {
+        let config: CrawlConfig = serde_json::from_str(config)?;
+        let (tx, pages) = mpsc::unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let crawler = Crawler::from_config(config)?
+            .with_pages(tx)
+            .with_stop(Arc::clone(&stop));
+        let thread = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?
+                .block_on(crawler.run())
+        });
+        Ok(Self {
+            pages,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    fn poll(&mut self) -> Option<Event> {
+        match self.pages.try_recv() {
+            Ok(page) => Some(Event::Page {
+                url: page.url,
+                final_url: page.final_url,
+                status: page.status.as_u16(),
+                depth: page.depth,
+            }),
+            // All the pages are consumed once the crawl has ended
+            Err(TryRecvError::Closed) => {
+                let result = self
+                    .thread
+                    .take()?
+                    .join()
+                    .unwrap_or(Err(CrawlerError::TaskPanicked));
+                Some(Event::Finished {
+                    error: result.err().map(|err| err.to_string()),
+                })
+            }
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+}
+
+fn set_last_error(error: String) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    CString::new(s).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Start a crawl configured by the JSON object `config`, the
+/// serialization of a [`CrawlConfig`].
+///
+/// Returns `NULL` on error, described by [`webcrawler_last_error`].
+///
+/// # Safety
+///
+/// `config` must be a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn webcrawler_start(config: *const c_char) -> *mut WebcrawlerHandle {
+    if config.is_null() {
+        set_last_error("null config".into());
+        return std::ptr::null_mut();
+    }
+    let result = CStr::from_ptr(config)
+        .to_str()
+        .map_err(|err| err.to_string())
+        .and_then(|config| WebcrawlerHandle::start(config).map_err(|err| err.to_string()));
+    match result {
+        Ok(handle) => Box::into_raw(Box::new(handle)),
+        Err(err) => {
+            set_last_error(err);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// The next event of the crawl as a JSON object, or `NULL` if none is
+/// pending. The last event is of `"type": "finished"`, and carries the
+/// `error` of the crawl, if any.
+///
+/// The returned string must be freed with [`webcrawler_string_free`].
+///
+/// # Safety
+///
+/// `handle` must be returned by [`webcrawler_start`] and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn webcrawler_poll(handle: *mut WebcrawlerHandle) -> *mut c_char {
+    let Some(handle) = handle.as_mut() else {
+        return std::ptr::null_mut();
+    };
+    handle
+        .poll()
+        .and_then(|event| serde_json::to_string(&event).ok())
+        .map_or(std::ptr::null_mut(), into_c_string)
+}
+
+/// Stop queueing pages. The visits in progress are completed, and the
+/// crawl state is saved, before the `finished` event.
+///
+/// # Safety
+///
+/// `handle` must be returned by [`webcrawler_start`] and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn webcrawler_stop(handle: *mut WebcrawlerHandle) {
+    if let Some(handle) = handle.as_ref() {
+        handle.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Stop the crawl, wait for it to end, and free the handle
+///
+/// # Safety
+///
+/// `handle` must be returned by [`webcrawler_start`] and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn webcrawler_free(handle: *mut WebcrawlerHandle) {
+    if handle.is_null() {
+        return;
+    }
+    let mut handle = Box::from_raw(handle);
+    handle.stop.store(true, Ordering::Relaxed);
+    handle.pages.close();
+    if let Some(thread) = handle.thread.take() {
+        let _ = thread.join();
+    }
+}
+
+/// The description of the last error on the calling thread, or `NULL`.
+///
+/// The returned string must be freed with [`webcrawler_string_free`].
+#[no_mangle]
+pub extern "C" fn webcrawler_last_error() -> *mut c_char {
+    LAST_ERROR
+        .with(|last| last.borrow_mut().take())
+        .map_or(std::ptr::null_mut(), into_c_string)
+}
+
+/// Free a string returned by the interface
+///
+/// # Safety
+///
+/// `s` must be returned by the interface and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn webcrawler_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
@@ -0,0 +1,124 @@
+//! Catalog of the crawl runs of several storage directories.
+//!
+//! Every run appends a record to a shared catalog file when it starts and
+//! again when it finishes, with its id, tag, seeds, storage directory and
+//! final stats. The last record of each run describes it, so that the
+//! runs that never finished remain listed as such. With dozens of crawls,
+//! the catalog tells where each one is stored and how they compare,
+//! without opening their storage directories.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::run::RunMetadata;
+
+/// Default path of the catalog, next to the default storage directories
+pub const DEFAULT_CATALOG: &str = "webpages/catalog.jsonl";
+
+/// The record of a run in the catalog
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub run_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    pub seeds: Vec<url::Url>,
+    /// The storage directory of the run
+    pub storage: PathBuf,
+    pub resumed: bool,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    /// Pages fetched and scraped, once finished
+    pub visited: Option<usize>,
+    /// Pages that could not be fetched, once finished
+    pub failed: Option<usize>,
+    /// Pages written to the storage, once finished
+    pub stored: Option<usize>,
+    /// Distinct urls left in the frontier, once finished
+    pub pending: Option<usize>,
+}
+
+impl CatalogEntry {
+    /// The record of the run of `metadata`, stored in `storage`
+    pub fn new(metadata: &RunMetadata, storage: PathBuf) -> Self {
+        let stats = metadata.stats.as_ref();
+        Self {
+            run_id: metadata.run_id,
+            tag: metadata.tag.clone(),
+            seeds: metadata.seeds.clone(),
+            storage,
+            resumed: metadata.resumed,
+            started_at: metadata.started_at,
+            finished_at: metadata.finished_at,
+            visited: stats.map(|stats| stats.visited),
+            failed: stats.map(|stats| stats.failed),
+            stored: stats.map(|stats| stats.stored),
+            pending: stats.map(|stats| stats.pending),
+        }
+    }
+}
+
+/// A catalog file shared by crawl runs
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    path: PathBuf,
+}
+
+impl Catalog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append the record of the run of `metadata`, stored in `storage`
+    pub async fn record(&self, metadata: &RunMetadata, storage: &Path) -> Result<()> {
+        let storage = tokio::fs::canonicalize(storage)
+            .await
+            .unwrap_or_else(|_| storage.to_path_buf());
+        let mut line = serde_json::to_vec(&CatalogEntry::new(metadata, storage))?;
+        line.push(b'\n');
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // A single append of the whole line, so that concurrent runs do
+        // not interleave their records
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// The last record of every run, in the order the runs started.
+    ///
+    /// A missing catalog lists no run.
+    pub async fn runs(&self) -> Result<Vec<CatalogEntry>> {
+        let contents = match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut runs: Vec<CatalogEntry> = Vec::new();
+        let mut positions = HashMap::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let entry: CatalogEntry = serde_json::from_str(line)?;
+            match positions.get(&entry.run_id) {
+                Some(&position) => runs[position] = entry,
+                None => {
+                    positions.insert(entry.run_id, runs.len());
+                    runs.push(entry);
+                }
+            }
+        }
+        runs.sort_by_key(|entry| entry.started_at);
+        Ok(runs)
+    }
+}
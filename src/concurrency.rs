@@ -0,0 +1,92 @@
+//! Adaptive concurrency of the visits of a crawl.
+//!
+//! The number of concurrent visits is tuned with an additive increase,
+//! multiplicative decrease (AIMD) scheme: every successful visit grows
+//! the limit by one over a window of as many visits as the limit, while
+//! a congestion signal halves it, at most once per window. Congestion is
+//! signalled by a failed visit, a `429 Too Many Requests` or server error
+//! response, or a smoothed latency exceeding twice the lowest smoothed
+//! latency observed during the crawl.
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+/// Number of concurrent visits to start with, if allowed
+const INITIAL_LIMIT: f64 = 2.0;
+
+/// Weight of a new latency in the smoothed latency
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Ratio of the smoothed to the lowest latency signalling congestion
+const LATENCY_TOLERANCE: f64 = 2.0;
+
+/// The limit on the concurrent visits of a crawl
+#[derive(Debug, Clone)]
+pub struct AdaptiveConcurrency {
+    limit: f64,
+    max: usize,
+    /// Smoothed latency of the visits, in seconds
+    latency: Option<f64>,
+    /// Lowest smoothed latency, in seconds
+    min_latency: f64,
+    /// Number of visits since the last decrease
+    since_decrease: usize,
+}
+
+impl AdaptiveConcurrency {
+    /// A limit growing up to `max` concurrent visits
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            limit: INITIAL_LIMIT.min(max as f64),
+            max,
+            latency: None,
+            min_latency: f64::INFINITY,
+            since_decrease: 0,
+        }
+    }
+
+    /// The current number of concurrent visits
+    pub fn limit(&self) -> usize {
+        self.limit as usize
+    }
+
+    /// Adapt the limit to a visit answered with `status` in `latency`
+    pub fn record_visit(&mut self, status: StatusCode, latency: Duration) {
+        let latency = latency.as_secs_f64();
+        let smoothed = match self.latency {
+            Some(smoothed) => smoothed + LATENCY_SMOOTHING * (latency - smoothed),
+            None => latency,
+        };
+        self.latency = Some(smoothed);
+        self.min_latency = self.min_latency.min(smoothed);
+        let throttled = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+        if throttled || smoothed > LATENCY_TOLERANCE * self.min_latency {
+            self.decrease();
+        } else {
+            self.since_decrease += 1;
+            self.limit = (self.limit + 1.0 / self.limit).min(self.max as f64);
+        }
+    }
+
+    /// Adapt the limit to a failed visit
+    pub fn record_failure(&mut self) {
+        self.decrease();
+    }
+
+    fn decrease(&mut self) {
+        self.since_decrease += 1;
+        if self.since_decrease < self.limit() {
+            return;
+        }
+        self.since_decrease = 0;
+        let limit = (self.limit / 2.0).max(1.0);
+        if limit.floor() < self.limit.floor() {
+            tracing::debug!(
+                "  -> Decreasing the concurrent visits to {}",
+                limit as usize
+            );
+        }
+        self.limit = limit;
+    }
+}
@@ -0,0 +1,1239 @@
+//! Configuration of a crawl.
+//!
+//! All the configuration types can be serialized, so that a crawl can be
+//! described by a single [`CrawlConfig`], e.g. stored as JSON and replayed
+//! later on. Omitted fields take their default values.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{CrawlerError, Result};
+pub use crate::frontier::{FrontierOverflow, UrlLimits};
+use crate::naming::NamingTemplate;
+use crate::sample::Probability;
+use crate::seed::Seed;
+use crate::simhash::MAX_DISTANCE;
+use crate::store::StorePolicy;
+
+/// Default number of concurrent storage writers
+pub const DEFAULT_WRITERS: usize = 2;
+
+/// Default number of bytes to leave free on the storage disk
+pub const DEFAULT_MIN_FREE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The complete configuration of a crawl
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlConfig {
+    /// The root url to start the crawling from
+    pub root_url: String,
+    /// More seeds to start the crawling from, or the root url
+    /// itself, with the metadata of the pages reached from them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub seeds: Vec<Seed>,
+    pub limits: Limits,
+    pub policy: CrawlPolicy,
+    pub scraper: ScraperConfig,
+    pub storage: StorageConfig,
+    pub report: ReportConfig,
+    pub webhooks: WebhookConfig,
+    /// Visit one page at a time with a single storage writer, recording
+    /// sequence numbers instead of fetch times, so that two crawls of the
+    /// same site record identical manifests
+    pub deterministic: bool,
+    /// A tag to find the crawl by in the catalog of the runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// The catalog file to record the run in, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub catalog: Option<PathBuf>,
+    /// The scrapers of some hosts, in place of `scraper`; the
+    /// first override matching the host of a page applies
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<HostOverride>,
+}
+
+impl CrawlConfig {
+    /// The contradictory settings of the config, each one naming its
+    /// field and why the crawl cannot run as configured
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = self.limits.problems();
+        if let Some(focus) = &self.policy.focus {
+            if focus.keywords.is_empty() {
+                problems.push(
+                    "`policy.focus.keywords` is empty, so no page is relevant; \
+                     give some keywords or drop the focus"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(sampling) = &self.policy.sampling {
+            if sampling.rate.value() == 0.0 && sampling.patterns.is_empty() {
+                problems.push(
+                    "`policy.sampling.rate` is 0, so only the seeds are visited; \
+                     give a rate or some patterns"
+                        .to_string(),
+                );
+            }
+            for (i, pattern) in sampling.patterns.iter().enumerate() {
+                if let Err(err) = regex::Regex::new(&pattern.pattern) {
+                    problems.push(format!("`policy.sampling.patterns[{}]`: {}", i, err));
+                }
+            }
+        }
+        self.scraper.check("scraper", &mut problems);
+        for (i, host) in self.hosts.iter().enumerate() {
+            if host.host.trim_start_matches("*.").is_empty() {
+                problems.push(format!("`hosts[{}].host` matches no host", i));
+            } else if let Some(j) = self.hosts[..i].iter().position(|h| h.shadows(host)) {
+                problems.push(format!(
+                    "`hosts[{}]` (`{}`) never applies, its hosts match `hosts[{}]` (`{}`) \
+                     first; list it before",
+                    i, host.host, j, self.hosts[j].host
+                ));
+            }
+            host.scraper
+                .check(&format!("hosts[{}].scraper", i), &mut problems);
+        }
+        self.storage.check(&mut problems);
+        self.report.check(&mut problems);
+        self.webhooks.check(&mut problems);
+        if self.deterministic && self.limits.adaptive_concurrency {
+            problems.push(
+                "`limits.adaptive_concurrency` is set but a `deterministic` crawl \
+                 visits one page at a time; drop either"
+                    .to_string(),
+            );
+        }
+        problems
+    }
+
+    /// Fail with all the problems of the config at once, if any
+    pub fn validate(&self) -> Result<()> {
+        match self.problems() {
+            problems if problems.is_empty() => Ok(()),
+            problems => Err(CrawlerError::InvalidConfig(problems)),
+        }
+    }
+}
+
+/// Which of the links found in a page are followed, besides its anchors
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlPolicy {
+    /// The links followed, relative to the seeds
+    pub scope: CrawlScope,
+    /// Follow the sources of frames and iframes within the scope, if any
+    pub frames: Option<FrameScope>,
+    pub hreflang: HreflangPolicy,
+    /// Follow the areas of image maps
+    pub areas: bool,
+    /// Follow the actions of the `GET` forms
+    pub form_actions: bool,
+    /// Follow the urls navigated to by simple `onclick` handlers,
+    /// e.g. `location.href='...'`
+    pub onclick: bool,
+    /// Extract the links, images and frames of the `<noscript>` fallbacks
+    pub noscript: bool,
+    /// Extract the images and frames loaded lazily from `data-src` and
+    /// similar attributes, and follow the `data-href` links
+    pub lazy_load: bool,
+    /// Upgrade the `http://` urls of the hosts declaring HTTP Strict
+    /// Transport Security, and the links of the pages declaring
+    /// `upgrade-insecure-requests`, to `https://`
+    pub hsts: bool,
+    /// Collapse the AMP and mobile variants of pages to their canonical page
+    pub collapse_variants: bool,
+    /// Store and follow the pages regardless of their `noindex` and
+    /// `nofollow` directives
+    pub ignore_robots_tags: bool,
+    /// Focus the crawl on the pages relevant to a topic, if any
+    pub focus: Option<FocusConfig>,
+    /// Visit a sample of the discovered links only, if any
+    pub sampling: Option<SamplingConfig>,
+}
+
+/// The topic of a focused crawl
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FocusConfig {
+    /// The keywords of the topic
+    pub keywords: Vec<String>,
+    /// Min number of keywords in the text of a relevant page
+    pub min_score: u32,
+    /// Drop the links of irrelevant pages, instead of following
+    /// them after the links of the relevant pages
+    pub drop_irrelevant: bool,
+}
+
+impl Default for FocusConfig {
+    fn default() -> Self {
+        Self {
+            keywords: Vec::new(),
+            min_score: 1,
+            drop_irrelevant: false,
+        }
+    }
+}
+
+/// The [sample](crate::sample) of the discovered links visited by a crawl
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplingConfig {
+    /// The probability of visiting a link found in a seed
+    pub rate: Probability,
+    /// The factor the probability is multiplied by at every
+    /// further depth, e.g. 0.5 to halve it at every level
+    pub depth_decay: Probability,
+    /// The probabilities of the links matching url patterns, in place
+    /// of the depth-weighted one; the first matching pattern applies
+    pub patterns: Vec<SampledPattern>,
+    /// The seed of the draws; the same seed draws the same sample
+    pub seed: u64,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            rate: Probability::ALWAYS,
+            depth_decay: Probability::ALWAYS,
+            patterns: Vec::new(),
+            seed: 0,
+        }
+    }
+}
+
+/// The probability of visiting the links matching a regular
+/// expression, e.g. `/products/=0.01`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SampledPattern {
+    pub pattern: String,
+    pub rate: Probability,
+}
+
+impl FromStr for SampledPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (pattern, rate) = s.rsplit_once('=').ok_or_else(|| {
+            format!(
+                "unsupported sampled pattern `{}`, expected `<regex>=<probability>`",
+                s
+            )
+        })?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            rate: rate.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for SampledPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.pattern, self.rate)
+    }
+}
+
+/// How pages are fetched and what is extracted from them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScraperConfig {
+    /// The `User-Agent` of the requests
+    pub user_agent: Option<String>,
+    /// The `Accept` header of the requests, instead of `*/*`
+    pub accept: Option<String>,
+    /// The compressed encodings of the responses accepted, in order of
+    /// preference, and decoded before the pages are scraped and stored
+    pub accept_encoding: Vec<ContentEncoding>,
+    /// Record the forms of the pages
+    pub forms: bool,
+    /// Report the missing or weak security headers of the pages
+    pub security_headers: bool,
+    /// Report the http subresources and links of the https pages
+    pub mixed_content: bool,
+    /// Report the basic accessibility issues of the pages
+    pub accessibility: bool,
+    /// Record the e-mail addresses and phone numbers of the pages
+    pub contacts: bool,
+    /// Record the JSON-LD and microdata of the pages
+    pub structured_data: bool,
+    /// Validate the structured data items of common schema.org types
+    pub validate_schema: bool,
+    /// Record the title, description, headings and canonical url of the pages
+    pub seo: bool,
+    /// Record the `integrity` attributes of the scripts and stylesheets
+    pub integrity: bool,
+    /// Which of the fetched pages are stored
+    pub store: StoreFilter,
+    /// The IP family of the addresses to connect to
+    pub ip_family: IpFamily,
+    /// The addresses to connect to for some hosts, instead of resolving them
+    pub pinned_hosts: BTreeMap<String, Vec<IpAddr>>,
+    /// Min number of milliseconds between the requests to a host
+    pub delay_ms: Option<u64>,
+    /// The login performed before the crawl, if any
+    pub login: Option<LoginConfig>,
+}
+
+/// A form login performed before the crawl, whose session
+/// cookies are sent with the requests of the crawl
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginConfig {
+    /// The url the login form is posted to
+    pub url: String,
+    /// The page of the login form, whose hidden inputs, e.g. a CSRF
+    /// token, are posted along with the fields
+    pub form_page: Option<String>,
+    /// The fields posted, e.g. `{"username": "crawler"}`
+    pub fields: BTreeMap<String, String>,
+    /// The fields posted with the value of an environment variable,
+    /// e.g. `{"password": "SITE_PASSWORD"}`, keeping secrets out of
+    /// the config
+    pub env_fields: BTreeMap<String, String>,
+    /// A text of the response to a failed login, e.g. `Invalid password`
+    pub failure_text: Option<String>,
+}
+
+impl fmt::Debug for LoginConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoginConfig")
+            .field("url", &self.url)
+            .field("form_page", &self.form_page)
+            .field("fields", &self.fields.keys().collect::<Vec<_>>())
+            .field("env_fields", &self.env_fields)
+            .field("failure_text", &self.failure_text)
+            .finish()
+    }
+}
+
+impl ScraperConfig {
+    /// Record the problems of the scraper config at `field`
+    fn check(&self, field: &str, problems: &mut Vec<String>) {
+        if let Err(err) = StorePolicy::from_filter(&self.store) {
+            problems.push(format!("`{}.store`: {}", field, err));
+        }
+        for (i, encoding) in self.accept_encoding.iter().enumerate() {
+            if self.accept_encoding[..i].contains(encoding) {
+                problems.push(format!(
+                    "`{}.accept_encoding` lists `{}` twice",
+                    field, encoding
+                ));
+            }
+        }
+        if let Some(login) = &self.login {
+            if let Err(err) = url::Url::parse(&login.url) {
+                problems.push(format!("`{}.login.url`: {}", field, err));
+            }
+            if let Some(Err(err)) = login.form_page.as_deref().map(url::Url::parse) {
+                problems.push(format!("`{}.login.form_page`: {}", field, err));
+            }
+        }
+        for (host, addresses) in &self.pinned_hosts {
+            if addresses.is_empty() {
+                problems.push(format!(
+                    "`{}.pinned_hosts` pins `{}` to no address, so it is never reached",
+                    field, host
+                ));
+            }
+        }
+    }
+}
+
+/// The scraper of the pages of some hosts, in place of the scraper
+/// of the crawl, e.g. with other extractors, store filter or delay
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HostOverride {
+    /// The host of the pages, e.g. `example.com`, or a pattern
+    /// matching a domain and its subdomains, e.g. `*.example.com`
+    pub host: String,
+    pub scraper: ScraperConfig,
+}
+
+impl HostOverride {
+    /// Whether the pages of `host` are scraped by the override
+    pub fn matches(&self, host: &str) -> bool {
+        matches_host(&self.host, host)
+    }
+
+    /// Whether the override matches every host of `other`,
+    /// which then never applies if listed after
+    fn shadows(&self, other: &HostOverride) -> bool {
+        let wildcard = |host: &HostOverride| host.host.starts_with("*.");
+        self.matches(other.host.trim_start_matches("*.")) && (wildcard(self) || !wildcard(other))
+    }
+}
+
+/// Whether `host` matches `pattern`, either a host, e.g. `example.com`,
+/// or a domain and its subdomains, e.g. `*.example.com`
+pub fn matches_host(pattern: &str, host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+        None => host == pattern,
+    }
+}
+
+/// Which of the fetched pages are stored. The pages that are
+/// not stored are still followed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreFilter {
+    /// Store every page
+    #[default]
+    All,
+    /// Store the pages with an element matching a CSS selector
+    Selector(String),
+    /// Store the pages whose body matches a regular expression
+    Regex(String),
+}
+
+/// A compressed encoding of the response bodies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+    Zstd,
+}
+
+impl FromStr for ContentEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(Self::Gzip),
+            "deflate" => Ok(Self::Deflate),
+            "zstd" => Ok(Self::Zstd),
+            other => Err(format!(
+                "unsupported content encoding `{}`, expected `gzip`, `deflate` or `zstd`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ContentEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gzip => write!(f, "gzip"),
+            Self::Deflate => write!(f, "deflate"),
+            Self::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// The IP family of the addresses connected to. When both families
+/// are allowed, the addresses of the first family are tried first, and
+/// those of the other family if they do not connect within a few
+/// hundred milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpFamily {
+    /// Both families, in the order of the system resolver
+    #[default]
+    Any,
+    /// IPv4 addresses only
+    Ipv4,
+    /// IPv6 addresses only
+    Ipv6,
+    /// Both families, IPv4 first
+    PreferIpv4,
+    /// Both families, IPv6 first
+    PreferIpv6,
+}
+
+impl FromStr for IpFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(Self::Any),
+            "ipv4" => Ok(Self::Ipv4),
+            "ipv6" => Ok(Self::Ipv6),
+            "prefer-ipv4" => Ok(Self::PreferIpv4),
+            "prefer-ipv6" => Ok(Self::PreferIpv6),
+            other => Err(format!(
+                "unsupported IP family `{}`, expected `any`, `ipv4`, `ipv6`, \
+                 `prefer-ipv4` or `prefer-ipv6`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => write!(f, "any"),
+            Self::Ipv4 => write!(f, "ipv4"),
+            Self::Ipv6 => write!(f, "ipv6"),
+            Self::PreferIpv4 => write!(f, "prefer-ipv4"),
+            Self::PreferIpv6 => write!(f, "prefer-ipv6"),
+        }
+    }
+}
+
+/// Where and how pages are stored
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// The storage directory. By default a new directory
+    /// is created under `webpages` for every crawl.
+    pub dir: Option<PathBuf>,
+    pub layout: StorageLayout,
+    /// Number of concurrent storage writers
+    pub n_writers: usize,
+    /// The response headers recorded with each page
+    pub headers: HeaderCapture,
+    /// Number of bytes to leave free on the storage disk,
+    /// below which the crawl is aborted
+    pub min_free_bytes: u64,
+    /// What to store when a url already stored is visited again
+    pub overwrite: OverwritePolicy,
+    /// Max number of bytes of a stored page
+    pub max_page_bytes: Option<usize>,
+    /// What to store of the pages beyond `max_page_bytes`
+    pub oversize: OversizePolicy,
+    /// Where the pages of some media types are stored, in place of the
+    /// storage root. The pages of other types are stored in the root.
+    pub routes: Vec<StorageRoute>,
+    /// The [naming template](crate::naming) of the stored pages.
+    /// By default they are named by the hash of their url.
+    pub naming: Option<String>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            layout: StorageLayout::default(),
+            n_writers: DEFAULT_WRITERS,
+            headers: HeaderCapture::default(),
+            min_free_bytes: DEFAULT_MIN_FREE_BYTES,
+            overwrite: OverwritePolicy::default(),
+            max_page_bytes: None,
+            oversize: OversizePolicy::default(),
+            routes: Vec::new(),
+            naming: None,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// The parsed naming template, if any
+    pub fn naming(&self) -> Result<Option<NamingTemplate>> {
+        self.naming
+            .as_deref()
+            .map(|naming| {
+                naming
+                    .parse()
+                    .map_err(|err| CrawlerError::InvalidConfig(vec![err]))
+            })
+            .transpose()
+    }
+
+    fn check(&self, problems: &mut Vec<String>) {
+        if self.n_writers == 0 {
+            problems.push(
+                "`storage.n_writers` is 0, so no page is ever stored; \
+                 use at least 1 writer"
+                    .to_string(),
+            );
+        }
+        match self.max_page_bytes {
+            Some(0) if self.oversize == OversizePolicy::Skip => problems.push(
+                "`storage.max_page_bytes` is 0 and `storage.oversize` is `skip`, \
+                 so no page is ever stored"
+                    .to_string(),
+            ),
+            None if self.oversize != OversizePolicy::Skip => problems.push(format!(
+                "`storage.oversize` is `{}` but no `storage.max_page_bytes` is set",
+                self.oversize
+            )),
+            _ => {}
+        }
+        for (i, route) in self.routes.iter().enumerate() {
+            if !route.content_type.contains('/') {
+                problems.push(format!(
+                    "`storage.routes[{}]`: `{}` is not a media type, e.g. `image/*`",
+                    i, route.content_type
+                ));
+            } else if self.routes[..i]
+                .iter()
+                .any(|other| other.content_type == route.content_type)
+            {
+                problems.push(format!(
+                    "`storage.routes[{}]` never applies, `{}` is routed before",
+                    i, route.content_type
+                ));
+            }
+            if !route.target.is_within_root() {
+                problems.push(format!(
+                    "`storage.routes[{}]`: `{}` leaves the storage root, expected a \
+                     relative path without `..`",
+                    i,
+                    route.target.path().display()
+                ));
+            }
+        }
+        if let Some(Err(err)) = self.naming.as_deref().map(NamingTemplate::from_str) {
+            problems.push(format!("`storage.naming`: {}", err));
+        } else if self.naming.is_some() && self.layout == StorageLayout::ContentAddressed {
+            problems.push(
+                "`storage.naming` does not apply to a `content-addressed` storage".to_string(),
+            );
+        }
+    }
+}
+
+/// What the crawl reports besides its stats
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportConfig {
+    /// Number of the slowest pages to list in `slow_pages.jsonl`
+    pub slow_pages: usize,
+    /// Whether to write an `index.html` of the stored pages
+    pub index: bool,
+    /// The max Hamming distance of the fingerprints of the pages listed
+    /// as near-duplicates in `near_duplicates.jsonl`, if any
+    pub near_duplicates: Option<u32>,
+}
+
+impl ReportConfig {
+    fn check(&self, problems: &mut Vec<String>) {
+        if let Some(distance) = self.near_duplicates.filter(|d| *d > MAX_DISTANCE) {
+            problems.push(format!(
+                "`report.near_duplicates` is {}, above the max distance {} \
+                 of near-duplicate fingerprints",
+                distance, MAX_DISTANCE
+            ));
+        }
+    }
+}
+
+/// The webhooks notified of the lifecycle of the crawl
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// The urls posted the notifications
+    pub urls: Vec<url::Url>,
+    /// The percentages of the max number of pages whose
+    /// visit is notified, e.g. `[50, 90]`
+    pub milestones: Vec<u8>,
+}
+
+impl WebhookConfig {
+    fn check(&self, problems: &mut Vec<String>) {
+        if !self.milestones.is_empty() && self.urls.is_empty() {
+            problems.push("`webhooks.milestones` are set but no `webhooks.urls`".to_string());
+        }
+        for milestone in self.milestones.iter().filter(|&&m| m == 0 || m > 100) {
+            problems.push(format!(
+                "`webhooks.milestones`: {}% is never reached, expected 1 to 100",
+                milestone
+            ));
+        }
+    }
+}
+
+/// The layout of the pages in the storage root
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageLayout {
+    /// A single file per url, overwritten on every visit
+    #[default]
+    Flat,
+    /// A directory per url holding a timestamped file per visit
+    Versioned,
+    /// A file per distinct content, named by its hash, shared by the
+    /// visits and urls with that content
+    #[serde(rename = "content-addressed")]
+    ContentAddressed,
+}
+
+/// What to store when a url already stored, by the crawl or a previous
+/// crawl of the same storage, is visited again
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverwritePolicy {
+    /// Store the new page in place of the stored one
+    #[default]
+    Overwrite,
+    /// Store the new page as a version next to the stored one
+    KeepBoth,
+    /// Keep the stored page, discarding the new one
+    Skip,
+    /// Keep the stored page if the new one has the same `ETag` or, failing
+    /// that, the same content hash, and store the new page otherwise
+    CompareHash,
+}
+
+impl FromStr for OverwritePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(Self::Overwrite),
+            "keep-both" => Ok(Self::KeepBoth),
+            "skip" => Ok(Self::Skip),
+            "compare-hash" => Ok(Self::CompareHash),
+            other => Err(format!(
+                "unsupported overwrite policy `{}`, expected `overwrite`, \
+                 `keep-both`, `skip` or `compare-hash`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OverwritePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overwrite => write!(f, "overwrite"),
+            Self::KeepBoth => write!(f, "keep-both"),
+            Self::Skip => write!(f, "skip"),
+            Self::CompareHash => write!(f, "compare-hash"),
+        }
+    }
+}
+
+/// What to store of a page beyond the max size of a stored page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OversizePolicy {
+    /// Store nothing, counting the page as a failed write
+    #[default]
+    Skip,
+    /// Store the first bytes of the page, up to the max size
+    Truncate,
+    /// Store an empty page, recording its headers in the manifest
+    HeadersOnly,
+}
+
+impl FromStr for OversizePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "truncate" => Ok(Self::Truncate),
+            "headers-only" => Ok(Self::HeadersOnly),
+            other => Err(format!(
+                "unsupported oversize policy `{}`, expected `skip`, `truncate` or `headers-only`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OversizePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Skip => write!(f, "skip"),
+            Self::Truncate => write!(f, "truncate"),
+            Self::HeadersOnly => write!(f, "headers-only"),
+        }
+    }
+}
+
+/// Where the pages of a media type are stored, in place of the
+/// storage root, e.g. `image/*=dir:images`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageRoute {
+    /// The media type of the routed pages, e.g. `application/json`,
+    /// or a whole type, e.g. `image/*`
+    pub content_type: String,
+    #[serde(flatten)]
+    pub target: RouteTarget,
+}
+
+/// The destination of the pages of a [`StorageRoute`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteTarget {
+    /// A subdirectory of the storage root, laid out as the root
+    Dir(PathBuf),
+    /// A JSON lines file of the storage root, holding a record with the
+    /// body of every page instead of a file per page
+    Jsonl(String),
+}
+
+impl RouteTarget {
+    /// The path of the target, relative to the storage root
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Dir(path) => path,
+            Self::Jsonl(file) => Path::new(file),
+        }
+    }
+
+    /// Whether the target is a relative path that stays within the
+    /// storage root, as [rendered names](crate::naming) do
+    pub fn is_within_root(&self) -> bool {
+        let components = self.path().components();
+        components
+            .clone()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+            && components
+                .clone()
+                .any(|component| matches!(component, Component::Normal(_)))
+    }
+}
+
+impl FromStr for StorageRoute {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let expected = || {
+            format!(
+                "unsupported storage route `{}`, expected \
+                 `<content-type>=dir:<path>` or `<content-type>=jsonl:<file>`",
+                s
+            )
+        };
+        let (content_type, target) = s.split_once('=').ok_or_else(expected)?;
+        let content_type = content_type.trim().to_ascii_lowercase();
+        if !content_type.contains('/') {
+            return Err(expected());
+        }
+        let target = match target.split_once(':').ok_or_else(expected)? {
+            ("dir", path) if !path.is_empty() => RouteTarget::Dir(path.into()),
+            ("jsonl", file) if !file.is_empty() => RouteTarget::Jsonl(file.into()),
+            _ => return Err(expected()),
+        };
+        if !target.is_within_root() {
+            return Err(format!(
+                "storage route `{}` leaves the storage root, expected a relative \
+                 path without `..`",
+                s
+            ));
+        }
+        Ok(Self {
+            content_type,
+            target,
+        })
+    }
+}
+
+impl fmt::Display for StorageRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.target {
+            RouteTarget::Dir(path) => write!(f, "{}=dir:{}", self.content_type, path.display()),
+            RouteTarget::Jsonl(file) => write!(f, "{}=jsonl:{}", self.content_type, file),
+        }
+    }
+}
+
+/// Bounds on the resources used by a crawl
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Max number of concurrent tasks
+    pub max_tasks: usize,
+    /// Max number of pages to visit
+    pub max_pages: usize,
+    /// Max number of pages to visit on a single host
+    pub max_pages_per_host: Option<usize>,
+    /// Max number of bytes of page bodies held in memory at once
+    pub max_inflight_bytes: Option<usize>,
+    /// Max number of seconds to wait for a visit to complete before
+    /// the crawl is considered stalled and aborted
+    pub stall_timeout_secs: Option<u64>,
+    /// Max number of seconds a visit may take, from its fetch to the
+    /// storage of its page, before it is cancelled as timed out
+    pub task_timeout_secs: Option<u64>,
+    /// Max number of urls pending in the frontier
+    pub max_frontier: Option<usize>,
+    /// What to do with the urls discovered beyond `max_frontier`
+    pub frontier_overflow: FrontierOverflow,
+    /// Max number of characters of a url to visit
+    pub max_url_length: Option<usize>,
+    /// Max number of query parameters of a url to visit
+    pub max_query_params: Option<usize>,
+    /// Max number of path segments of a url to visit
+    pub max_path_depth: Option<usize>,
+    /// Adapt the number of concurrent tasks, up to `max_tasks`, to the
+    /// errors and latency of the visits
+    pub adaptive_concurrency: bool,
+}
+
+impl Limits {
+    /// The limits on the urls to visit
+    pub fn url_limits(&self) -> UrlLimits {
+        UrlLimits {
+            max_length: self.max_url_length,
+            max_query_params: self.max_query_params,
+            max_path_depth: self.max_path_depth,
+        }
+    }
+
+    /// The contradictory settings of the limits
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut zero = |field: &str, value: Option<usize>, consequence: &str| {
+            if value == Some(0) {
+                problems.push(format!("`limits.{}` is 0, so {}", field, consequence));
+            }
+        };
+        zero("max_tasks", Some(self.max_tasks), "no page is ever visited");
+        zero("max_pages", Some(self.max_pages), "no page is ever visited");
+        zero(
+            "max_pages_per_host",
+            self.max_pages_per_host,
+            "no page is ever visited",
+        );
+        zero(
+            "max_inflight_bytes",
+            self.max_inflight_bytes,
+            "no page body can be held",
+        );
+        zero("max_frontier", self.max_frontier, "no url is ever queued");
+        zero(
+            "max_url_length",
+            self.max_url_length,
+            "every url is rejected",
+        );
+        if self.stall_timeout_secs == Some(0) {
+            problems.push(
+                "`limits.stall_timeout_secs` is 0, so every crawl is aborted as stalled"
+                    .to_string(),
+            );
+        }
+        if self.task_timeout_secs == Some(0) {
+            problems.push("`limits.task_timeout_secs` is 0, so every visit times out".to_string());
+        }
+        if self.max_frontier.is_none() && self.frontier_overflow != FrontierOverflow::default() {
+            problems.push(format!(
+                "`limits.frontier_overflow` is `{}` but no `limits.max_frontier` is set",
+                self.frontier_overflow
+            ));
+        }
+        problems
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_tasks: 5,
+            max_pages: 100,
+            max_pages_per_host: None,
+            max_inflight_bytes: None,
+            stall_timeout_secs: None,
+            task_timeout_secs: None,
+            max_frontier: None,
+            frontier_overflow: FrontierOverflow::default(),
+            max_url_length: None,
+            max_query_params: None,
+            max_path_depth: None,
+            adaptive_concurrency: false,
+        }
+    }
+}
+
+/// How to treat the alternate-language versions of a page,
+/// declared by `<link rel="alternate" hreflang="...">`.
+///
+/// The alternates of every page are recorded regardless of the policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HreflangPolicy {
+    /// Record the alternates without following them
+    #[default]
+    Record,
+    /// Follow the alternates in all languages
+    Follow,
+    /// Follow the alternates in the given languages only. A language
+    /// also matches its regional variants, e.g. `en` matches `en-GB`.
+    Languages(Vec<String>),
+}
+
+impl HreflangPolicy {
+    /// Whether an alternate in language `hreflang` is followed
+    pub fn follows(&self, hreflang: &str) -> bool {
+        match self {
+            Self::Record => false,
+            Self::Follow => true,
+            Self::Languages(languages) => {
+                let hreflang = hreflang.to_ascii_lowercase();
+                languages.iter().any(|language| {
+                    let language = language.to_ascii_lowercase();
+                    hreflang == language || hreflang.starts_with(&format!("{}-", language))
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for HreflangPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "record" => Ok(Self::Record),
+            "follow" => Ok(Self::Follow),
+            languages => {
+                let languages: Vec<_> = languages
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|language| !language.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if languages.is_empty() {
+                    return Err(
+                        "expected `record`, `follow` or a comma-separated list of languages".into(),
+                    );
+                }
+                Ok(Self::Languages(languages))
+            }
+        }
+    }
+}
+
+impl fmt::Display for HreflangPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Record => write!(f, "record"),
+            Self::Follow => write!(f, "follow"),
+            Self::Languages(languages) => write!(f, "{}", languages.join(",")),
+        }
+    }
+}
+
+/// Which links to follow, relative to the seeds of the crawl. The links
+/// out of the scope are recorded as external links.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrawlScope {
+    /// Follow all the links
+    #[default]
+    All,
+    /// Follow the links on the host of a seed
+    SameHost,
+    /// Follow the links on the host of a seed or its subdomains
+    Subdomains,
+}
+
+impl CrawlScope {
+    /// Whether `link` is in the scope of `seed`
+    pub fn includes(&self, seed: &url::Url, link: &url::Url) -> bool {
+        match self {
+            Self::All => true,
+            Self::SameHost => seed.host() == link.host(),
+            Self::Subdomains => match (seed.host_str(), link.host_str()) {
+                (Some(seed), Some(link)) => {
+                    link == seed
+                        || link
+                            .strip_suffix(seed)
+                            .is_some_and(|subdomain| subdomain.ends_with('.'))
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+impl FromStr for CrawlScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "same-host" => Ok(Self::SameHost),
+            "subdomains" => Ok(Self::Subdomains),
+            other => Err(format!(
+                "unsupported scope `{}`, expected `all`, `same-host` or `subdomains`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CrawlScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::SameHost => write!(f, "same-host"),
+            Self::Subdomains => write!(f, "subdomains"),
+        }
+    }
+}
+
+/// Which `frame` and `iframe` sources to follow
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FrameScope {
+    /// Follow the frames on the host of the framing page
+    #[default]
+    SameHost,
+    /// Follow all the frames
+    All,
+}
+
+impl FrameScope {
+    /// Whether the `frame` of `page` is followed
+    pub fn includes(&self, page: &url::Url, frame: &url::Url) -> bool {
+        match self {
+            Self::SameHost => page.host() == frame.host(),
+            Self::All => true,
+        }
+    }
+}
+
+impl FromStr for FrameScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "same-host" => Ok(Self::SameHost),
+            "all" => Ok(Self::All),
+            other => Err(format!(
+                "unsupported frame scope `{}`, expected `same-host` or `all`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FrameScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SameHost => write!(f, "same-host"),
+            Self::All => write!(f, "all"),
+        }
+    }
+}
+
+/// Which response headers are recorded with a stored page,
+/// besides its caching headers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeaderCapture {
+    /// Record no headers
+    #[default]
+    None,
+    /// Record all the headers
+    All,
+    /// Record the headers of the given names, in any case
+    Names(Vec<String>),
+}
+
+impl HeaderCapture {
+    /// Whether the header `name` is recorded
+    pub fn captures(&self, name: &str) -> bool {
+        match self {
+            Self::None => false,
+            Self::All => true,
+            Self::Names(names) => names.iter().any(|n| n.eq_ignore_ascii_case(name)),
+        }
+    }
+
+    /// The recorded `headers` by lowercase name, joining
+    /// the values of repeated headers with commas
+    pub fn capture(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        let mut captured = BTreeMap::<String, String>::new();
+        for (name, value) in headers {
+            if !self.captures(name.as_str()) {
+                continue;
+            }
+            let value = String::from_utf8_lossy(value.as_bytes());
+            captured
+                .entry(name.as_str().to_string())
+                .and_modify(|values| {
+                    values.push_str(", ");
+                    values.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+        captured
+    }
+}
+
+impl FromStr for HeaderCapture {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "all" => Ok(Self::All),
+            names => {
+                let names: Vec<_> = names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if names.is_empty() {
+                    return Err(
+                        "expected `none`, `all` or a comma-separated list of header names".into(),
+                    );
+                }
+                Ok(Self::Names(names))
+            }
+        }
+    }
+}
+
+impl fmt::Display for HeaderCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::All => write!(f, "all"),
+            Self::Names(names) => write!(f, "{}", names.join(",")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_stay_within_the_storage_root() {
+        let route: StorageRoute = "image/*=dir:media/images".parse().unwrap();
+        assert_eq!(route.target, RouteTarget::Dir("media/images".into()));
+        assert!("application/json=jsonl:./api.jsonl"
+            .parse::<StorageRoute>()
+            .is_ok());
+        for route in [
+            "image/*=dir:/etc",
+            "image/*=dir:../x",
+            "image/*=dir:images/../../x",
+            "image/*=dir:.",
+            "application/json=jsonl:/tmp/api.jsonl",
+            "application/json=jsonl:../api.jsonl",
+        ] {
+            let err = route.parse::<StorageRoute>().unwrap_err();
+            assert!(err.contains("leaves the storage root"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn configured_routes_stay_within_the_storage_root() {
+        let config: CrawlConfig = serde_json::from_value(serde_json::json!({
+            "root_url": "https://example.com/",
+            "storage": {"routes": [
+                {"content_type": "image/*", "dir": "images"},
+                {"content_type": "text/css", "dir": "/etc"},
+                {"content_type": "text/javascript", "dir": "../x"},
+                {"content_type": "application/json", "jsonl": "../../api.jsonl"},
+            ]},
+        }))
+        .unwrap();
+        let problems = config.problems();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        for (problem, i) in problems.iter().zip(1..) {
+            assert!(problem.starts_with(&format!("`storage.routes[{}]`", i)));
+            assert!(problem.contains("leaves the storage root"));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Validation of the hreflang and canonical annotations of a crawl.
+//!
+//! Annotations can only be validated against the whole crawled set: the
+//! alternate-language versions of a page must link back to it, and the
+//! targets of the annotations must be crawled pages. The validation runs
+//! on the `alternates.jsonl` and `canonicals.jsonl` records of a crawl,
+//! once it is over.
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::parse::Alternate;
+
+/// Name of the file recording the canonical urls declared by pages
+pub const CANONICALS_FILE: &str = "canonicals.jsonl";
+
+/// The canonical url declared by a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageCanonical {
+    pub url: url::Url,
+    pub canonical: url::Url,
+}
+
+/// An alternate-language annotation of a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HreflangIssue {
+    pub url: url::Url,
+    pub hreflang: String,
+    pub alternate: url::Url,
+}
+
+/// A canonical annotation of a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanonicalIssue {
+    pub url: url::Url,
+    pub canonical: url::Url,
+}
+
+/// The inconsistent annotations of a crawl
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    /// Alternates that do not link back to the annotated page
+    pub missing_return_links: Vec<HreflangIssue>,
+    /// Alternates that were not crawled
+    pub unresolved_alternates: Vec<HreflangIssue>,
+    /// Alternates declaring another page as canonical
+    pub non_canonical_alternates: Vec<HreflangIssue>,
+    /// Canonical urls that were not crawled
+    pub unresolved_canonicals: Vec<CanonicalIssue>,
+    /// Canonical urls declaring yet another page as canonical
+    pub canonical_chains: Vec<CanonicalIssue>,
+}
+
+impl ConsistencyReport {
+    /// Validate the `alternates` and `canonicals` declared by the pages
+    /// of a crawl, against the `crawled` urls
+    pub fn new(
+        crawled: &HashSet<url::Url>,
+        alternates: &HashMap<url::Url, Vec<Alternate>>,
+        canonicals: &HashMap<url::Url, url::Url>,
+    ) -> Self {
+        let mut report = Self::default();
+        let canonical_of = |url: &url::Url| canonicals.get(url).filter(|c| *c != url);
+        for (url, page_alternates) in alternates {
+            for alternate in page_alternates.iter().filter(|a| &a.url != url) {
+                let issue = HreflangIssue {
+                    url: url.clone(),
+                    hreflang: alternate.hreflang.clone(),
+                    alternate: alternate.url.clone(),
+                };
+                if !crawled.contains(&alternate.url) {
+                    report.unresolved_alternates.push(issue);
+                    continue;
+                }
+                if canonical_of(&alternate.url).is_some() {
+                    report.non_canonical_alternates.push(issue.clone());
+                }
+                let links_back = alternates
+                    .get(&alternate.url)
+                    .is_some_and(|back| back.iter().any(|back| &back.url == url));
+                if !links_back {
+                    report.missing_return_links.push(issue);
+                }
+            }
+        }
+        for (url, canonical) in canonicals.iter().filter(|(url, c)| url != c) {
+            let issue = CanonicalIssue {
+                url: url.clone(),
+                canonical: canonical.clone(),
+            };
+            if !crawled.contains(canonical) {
+                report.unresolved_canonicals.push(issue);
+            } else if canonical_of(canonical).is_some() {
+                report.canonical_chains.push(issue);
+            }
+        }
+        report
+            .missing_return_links
+            .sort_by(|a, b| a.url.cmp(&b.url));
+        report
+            .unresolved_alternates
+            .sort_by(|a, b| a.url.cmp(&b.url));
+        report
+            .non_canonical_alternates
+            .sort_by(|a, b| a.url.cmp(&b.url));
+        report
+            .unresolved_canonicals
+            .sort_by(|a, b| a.url.cmp(&b.url));
+        report.canonical_chains.sort_by(|a, b| a.url.cmp(&b.url));
+        report
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// The records of the JSONL file `file_name` in `root`, if any
+#[cfg(feature = "runtime")]
+async fn load_records<T: serde::de::DeserializeOwned>(
+    root: &std::path::Path,
+    file_name: &str,
+) -> crate::error::Result<Vec<T>> {
+    let contents = match tokio::fs::read_to_string(root.join(file_name)).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Validate the annotations of the crawl stored in `root`. The crawled
+/// set consists of the stored pages and the pages with annotations.
+#[cfg(feature = "runtime")]
+pub async fn validate(root: &std::path::Path) -> crate::error::Result<ConsistencyReport> {
+    use crate::crawler::{PageAlternates, ALTERNATES_FILE};
+    use crate::manifest::Manifest;
+
+    let manifest = Manifest::load(root).await?;
+    let alternates: HashMap<_, _> = load_records::<PageAlternates>(root, ALTERNATES_FILE)
+        .await?
+        .into_iter()
+        .map(|record| (record.url, record.alternates))
+        .collect();
+    let canonicals: HashMap<_, _> = load_records::<PageCanonical>(root, CANONICALS_FILE)
+        .await?
+        .into_iter()
+        .map(|record| (record.url, record.canonical))
+        .collect();
+    let crawled = manifest
+        .entries()
+        .iter()
+        .map(|entry| entry.url.clone())
+        .chain(alternates.keys().cloned())
+        .chain(canonicals.keys().cloned())
+        .collect();
+    Ok(ConsistencyReport::new(&crawled, &alternates, &canonicals))
+}
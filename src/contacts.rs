@@ -0,0 +1,133 @@
+//! Harvesting of contact data.
+//!
+//! The opt-in [`ContactExtractor`] collects the e-mail addresses and
+//! phone numbers of the pages, from their `mailto:` and `tel:` links
+//! and from their text. Each contact is reported once per crawl, with
+//! the first page it was found in.
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::extract::Extractor;
+use crate::parse::Document;
+
+/// Name of the contacts report
+pub const CONTACTS_FILE: &str = "contacts.jsonl";
+
+fn email_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap())
+}
+
+/// Phone numbers in international format, e.g. `+30 210 123 4567`
+fn phone_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"\+\d{1,3}[\s.-]?\(?\d{1,4}\)?(?:[\s.-]?\d{2,4}){2,4}").unwrap()
+    })
+}
+
+/// The lowercase e-mail address
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// The digits of a phone number, keeping a leading `+`
+fn normalize_phone(phone: &str) -> String {
+    let phone = phone.trim();
+    let digits = phone.chars().filter(char::is_ascii_digit);
+    match phone.starts_with('+') {
+        true => std::iter::once('+').chain(digits).collect(),
+        false => digits.collect(),
+    }
+}
+
+/// The contacts first found in a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageContacts {
+    pub url: url::Url,
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+}
+
+/// Collects the e-mail addresses and phone numbers of the pages
+#[derive(Debug, Default)]
+pub struct ContactExtractor {
+    seen: Mutex<HashSet<String>>,
+}
+
+impl ContactExtractor {
+    /// The contacts of the page at `url`, deduplicated
+    pub fn contacts(url: &url::Url, document: &Document) -> PageContacts {
+        let mut contacts = PageContacts {
+            url: url.clone(),
+            emails: Vec::new(),
+            phones: Vec::new(),
+        };
+        let text = document.text();
+        let links = document.links();
+        let mut emails: Vec<_> = links
+            .iter()
+            .filter(|link| link.scheme() == "mailto")
+            .map(|link| {
+                link.path()
+                    .split('?')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect();
+        emails.extend(
+            email_regex()
+                .find_iter(&text)
+                .map(|m| m.as_str().to_string()),
+        );
+        let mut phones: Vec<_> = links
+            .iter()
+            .filter(|link| link.scheme() == "tel")
+            .map(|link| link.path().to_string())
+            .collect();
+        phones.extend(
+            phone_regex()
+                .find_iter(&text)
+                .map(|m| m.as_str().to_string()),
+        );
+        for email in emails.iter().map(|email| normalize_email(email)) {
+            if !email.is_empty() && !contacts.emails.contains(&email) {
+                contacts.emails.push(email);
+            }
+        }
+        for phone in phones.iter().map(|phone| normalize_phone(phone)) {
+            if phone.trim_start_matches('+').len() >= 6 && !contacts.phones.contains(&phone) {
+                contacts.phones.push(phone);
+            }
+        }
+        contacts
+    }
+}
+
+impl Extractor for ContactExtractor {
+    fn report_file(&self) -> &str {
+        CONTACTS_FILE
+    }
+
+    fn extract(&self, url: &url::Url, document: &Document) -> Option<Value> {
+        let mut contacts = Self::contacts(url, document);
+        {
+            let mut seen = self.seen.lock().unwrap();
+            contacts
+                .emails
+                .retain(|email| seen.insert(format!("mailto:{}", email)));
+            contacts
+                .phones
+                .retain(|phone| seen.insert(format!("tel:{}", phone)));
+        }
+        if contacts.emails.is_empty() && contacts.phones.is_empty() {
+            return None;
+        }
+        serde_json::to_value(contacts).ok()
+    }
+}
@@ -0,0 +1,1088 @@
+//! Breadth-first traversal of a website
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use futures::stream::{FuturesOrdered, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::budget::ByteBudget;
+use crate::catalog::Catalog;
+use crate::concurrency::AdaptiveConcurrency;
+use crate::config::{CrawlConfig, CrawlScope, HostOverride, Limits, WebhookConfig};
+use crate::consistency::{PageCanonical, CANONICALS_FILE};
+use crate::error::{CrawlerError, Result};
+use crate::executor::{self, Executor};
+use crate::frontier::{Frontier, TaskContext};
+use crate::hosts::{HostTable, TOP_HOSTS};
+use crate::hsts::{self, HstsTable};
+use crate::index::{self, INDEX_FILE};
+use crate::latency::{LatencyRecorder, SLOW_PAGES_FILE};
+use crate::manifest::Manifest;
+use crate::page::CrawledPage;
+use crate::parse::Alternate;
+use crate::run::{RunConfig, RunMetadata, RunStats};
+use crate::sample::Sampler;
+use crate::seed::{self, Seed};
+use crate::simhash::{self, NEAR_DUPLICATES_FILE};
+use crate::state::CrawlState;
+use crate::webhook::{WebhookEvent, Webhooks};
+use crate::writer::{StorageWriter, WriterPool, DEFAULT_WRITERS};
+use crate::{Scraper, Storage};
+
+/// Max time a stalled crawl waits for its queued pages to be stored
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Name of the file recording the alternate-language versions of pages
+pub const ALTERNATES_FILE: &str = "alternates.jsonl";
+
+/// Name of the file recording the links out of the scope of the crawl
+pub const EXTERNAL_LINKS_FILE: &str = "external_links.jsonl";
+
+/// Name of the file holding the urls spilled by a full frontier
+pub const FRONTIER_SPILL_FILE: &str = "frontier_spill.jsonl";
+
+/// The links of a page out of the scope of the crawl, which are not followed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalLinks {
+    pub url: url::Url,
+    pub links: Vec<url::Url>,
+}
+
+/// Name of the file recording the failed visits
+pub const FAILURES_FILE: &str = "failures.jsonl";
+
+/// How a visit failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureKind {
+    /// The page could not be fetched or scraped
+    Error,
+    /// The task visiting the page panicked
+    Panic,
+    /// The visit exceeded its deadline and was cancelled
+    Timeout,
+}
+
+/// A failed visit of a page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageFailure {
+    pub url: url::Url,
+    pub kind: FailureKind,
+    pub message: String,
+}
+
+/// The alternate-language versions declared by a page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageAlternates {
+    pub url: url::Url,
+    pub alternates: Vec<Alternate>,
+}
+
+/// The outcome of a crawl task, along with the url it visited
+type TaskOutcome = (url::Url, std::thread::Result<Result<CrawledPage>>);
+
+/// The progress of a running crawl, as polled from its [`CrawlerHandle`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiveStats {
+    /// Pages fetched and scraped
+    pub visited: usize,
+    /// Entries pending in the frontier, including duplicates
+    pub queued: usize,
+    /// Pages being visited
+    pub in_flight: usize,
+    /// Pages that could not be fetched or scraped
+    pub errors: usize,
+    /// Bytes of the bodies of the visited pages
+    pub bytes: u64,
+}
+
+/// The counters behind the [`LiveStats`] of a crawl, updated as it runs
+#[derive(Debug, Default)]
+struct Counters {
+    visited: AtomicUsize,
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+    errors: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+/// A handle to a crawl, shared with the tasks inspecting or stopping it
+#[derive(Debug, Clone)]
+pub struct CrawlerHandle {
+    run_id: Uuid,
+    frontier: Arc<Mutex<Frontier>>,
+    stop: Arc<AtomicBool>,
+    counters: Arc<Counters>,
+}
+
+impl CrawlerHandle {
+    /// The unique id of the crawl run
+    pub fn run_id(&self) -> Uuid {
+        self.run_id
+    }
+
+    /// The urls pending a visit, with their depth, referrer and priority,
+    /// in the order they would be taken. The urls spilled to the disk by
+    /// a full frontier are not included.
+    pub fn frontier_snapshot(&self) -> Vec<TaskContext> {
+        self.frontier.lock().unwrap().snapshot()
+    }
+
+    /// The progress of the crawl, cheap enough to be polled e.g. by a
+    /// progress bar. Each counter is read atomically on its own, so the
+    /// counters may be a visit apart from each other.
+    pub fn stats(&self) -> LiveStats {
+        let counters = &self.counters;
+        LiveStats {
+            visited: counters.visited.load(Ordering::Relaxed),
+            queued: counters.queued.load(Ordering::Relaxed),
+            in_flight: counters.in_flight.load(Ordering::Relaxed),
+            errors: counters.errors.load(Ordering::Relaxed),
+            bytes: counters.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Stop queueing pages. The visits in progress are completed,
+    /// and the pending frontier is saved for a resume.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+pub struct Crawler {
+    run_id: Uuid,
+    resumed: bool,
+    root_url: url::Url,
+    /// The root url and the other seeds the crawl starts from
+    seed_urls: Vec<url::Url>,
+    storage: Arc<Storage>,
+    scraper: Scraper,
+    /// The scrapers of the hosts matching overrides, tried in order
+    host_scrapers: Vec<(HostOverride, Scraper)>,
+    frontier: Arc<Mutex<Frontier>>,
+    limits: Limits,
+    scope: CrawlScope,
+    hsts: bool,
+    sampler: Option<Sampler>,
+    /// The visit slots shared with other crawls of the process, if any
+    visit_slots: Option<Arc<Semaphore>>,
+    n_writers: usize,
+    slow_pages: usize,
+    index: bool,
+    near_duplicates: Option<u32>,
+    deterministic: bool,
+    executor: Arc<dyn Executor>,
+    task_queue: FuturesOrdered<BoxFuture<'static, TaskOutcome>>,
+    pages: Vec<UnboundedSender<CrawledPage>>,
+    seeds: Option<UnboundedReceiver<url::Url>>,
+    stop: Arc<AtomicBool>,
+    counters: Arc<Counters>,
+    webhooks: Webhooks,
+    tag: Option<String>,
+    catalog: Option<Catalog>,
+}
+
+impl Crawler {
+    pub fn new(
+        root_url: String,
+        storage: Option<Storage>,
+        scraper: Option<Scraper>,
+    ) -> Result<Self> {
+        let root_url = seed::parse_seed(&root_url)?;
+        let storage = match storage {
+            Some(storage) => Arc::new(storage),
+            None => Arc::new(Storage::try_from(&root_url)?),
+        };
+        let frontier = Frontier::new([TaskContext::seed(root_url.clone())], Default::default());
+        let frontier = Arc::new(Mutex::new(frontier));
+        let scraper = scraper.unwrap_or_default();
+        let task_queue = FuturesOrdered::new();
+        Ok(Self {
+            run_id: Uuid::new_v4(),
+            resumed: false,
+            seed_urls: vec![root_url.clone()],
+            root_url,
+            storage,
+            scraper,
+            host_scrapers: Vec::new(),
+            frontier,
+            limits: Limits::default(),
+            scope: CrawlScope::default(),
+            hsts: false,
+            sampler: None,
+            visit_slots: None,
+            n_writers: DEFAULT_WRITERS,
+            slow_pages: 0,
+            index: false,
+            near_duplicates: None,
+            deterministic: false,
+            executor: executor::default_executor(),
+            task_queue,
+            pages: Vec::new(),
+            seeds: None,
+            stop: Arc::default(),
+            counters: Arc::default(),
+            webhooks: Webhooks::default(),
+            tag: None,
+            catalog: None,
+        })
+    }
+
+    /// A handle to inspect and stop the crawl while it runs, to be
+    /// taken once the crawler is configured
+    pub fn handle(&self) -> CrawlerHandle {
+        CrawlerHandle {
+            run_id: self.run_id,
+            frontier: Arc::clone(&self.frontier),
+            stop: Arc::clone(&self.stop),
+            counters: Arc::clone(&self.counters),
+        }
+    }
+
+    /// The unique id of this crawl run
+    pub fn run_id(&self) -> Uuid {
+        self.run_id
+    }
+
+    /// The storage of the visited pages
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// A crawler configured by `config`, failing with all
+    /// its [problems](CrawlConfig::problems) at once
+    pub fn from_config(config: CrawlConfig) -> Result<Self> {
+        config.validate()?;
+        let root_url = seed::parse_seed(&config.root_url)?;
+        let mut storage = match &config.storage.dir {
+            Some(dir) => Storage::new(dir.clone()),
+            None => Storage::try_from(&root_url)?,
+        }
+        .with_config(&config.storage)?;
+        if config.deterministic {
+            storage = storage.with_sequential_timestamps();
+        }
+        let scraper = Scraper::from_config(&config.scraper)?.with_policy(config.policy.clone());
+        let mut crawler = Self::new(config.root_url.clone(), Some(storage), Some(scraper))?;
+        for host in &config.hosts {
+            let scraper = Scraper::from_config(&host.scraper)?.with_policy(config.policy.clone());
+            crawler = crawler.with_host_scraper(host.clone(), scraper);
+        }
+        crawler.with_config(config)
+    }
+
+    /// Crawl as configured by `config`, but for its root url, storage
+    /// and scrapers, which are those the crawler was created with
+    pub fn with_config(self, config: CrawlConfig) -> Result<Self> {
+        let sampler = config
+            .policy
+            .sampling
+            .as_ref()
+            .map(Sampler::new)
+            .transpose()?;
+        let mut crawler = self;
+        for seed in config.seeds {
+            crawler = crawler.with_seed(seed)?;
+        }
+        Ok(crawler
+            .with_limits(config.limits)
+            .with_scope(config.policy.scope)
+            .with_hsts(config.policy.hsts)
+            .with_sampler(sampler)
+            .with_writers(config.storage.n_writers)
+            .with_slow_pages(config.report.slow_pages)
+            .with_index(config.report.index)
+            .with_near_duplicates(config.report.near_duplicates)
+            .with_deterministic(config.deterministic)
+            .with_webhooks(config.webhooks)
+            .with_tag(config.tag)
+            .with_catalog(config.catalog.map(Catalog::new)))
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Also start the crawl from `seed`, or give its metadata to the root
+    /// url, recording the metadata with every page reached from it
+    pub fn with_seed(mut self, seed: Seed) -> Result<Self> {
+        let url = seed::parse_seed(&seed.url)?;
+        if !self.seed_urls.contains(&url) {
+            self.seed_urls.push(url.clone());
+        }
+        self.frontier()
+            .replace(TaskContext::seed(url).with_metadata(seed.metadata));
+        Ok(self)
+    }
+
+    /// Visit the pages of the hosts matching `host` with `scraper`, in
+    /// place of the scraper of the crawl. The first override matching
+    /// the host of a page applies.
+    pub fn with_host_scraper(mut self, host: HostOverride, scraper: Scraper) -> Self {
+        self.host_scrapers.push((host, scraper));
+        self
+    }
+
+    /// The scraper of the page of `url`
+    fn scraper_for(&self, url: &url::Url) -> &Scraper {
+        let host = url.host_str().unwrap_or_default();
+        self.host_scrapers
+            .iter()
+            .find(|(pattern, _)| pattern.matches(host))
+            .map_or(&self.scraper, |(_, scraper)| scraper)
+    }
+
+    /// Follow only the links within `scope`, recording the others
+    pub fn with_scope(mut self, scope: CrawlScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Remember the hosts declaring HTTP Strict Transport Security and
+    /// upgrade their `http://` urls before visiting them, along with the
+    /// links of the pages declaring `upgrade-insecure-requests`
+    pub fn with_hsts(mut self, hsts: bool) -> Self {
+        self.hsts = hsts;
+        self
+    }
+
+    /// Visit only the discovered links drawn by `sampler`, if any
+    pub fn with_sampler(mut self, sampler: Option<Sampler>) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Take a slot of `slots` for every visit, on top of the limit of
+    /// concurrent visits of the crawl, so that the crawls sharing the
+    /// slots visit at most as many pages at a time as there are slots
+    pub fn with_visit_slots(mut self, slots: Arc<Semaphore>) -> Self {
+        self.visit_slots = Some(slots);
+        self
+    }
+
+    /// Resume the crawl whose state was saved in `storage`
+    pub async fn resume(storage: Storage, scraper: Option<Scraper>) -> Result<Self> {
+        let state = CrawlState::load(storage.path()).await?;
+        Ok(Self {
+            run_id: Uuid::new_v4(),
+            resumed: true,
+            seed_urls: vec![state.root_url.clone()],
+            root_url: state.root_url,
+            storage: Arc::new(storage),
+            scraper: scraper.unwrap_or_default(),
+            host_scrapers: Vec::new(),
+            frontier: Arc::new(Mutex::new(Frontier::new(
+                state.frontier,
+                state.visited.into_iter().collect(),
+            ))),
+            limits: Limits::default(),
+            scope: CrawlScope::default(),
+            hsts: false,
+            sampler: None,
+            visit_slots: None,
+            n_writers: DEFAULT_WRITERS,
+            slow_pages: 0,
+            index: false,
+            near_duplicates: None,
+            deterministic: false,
+            executor: executor::default_executor(),
+            task_queue: FuturesOrdered::new(),
+            pages: Vec::new(),
+            seeds: None,
+            stop: Arc::default(),
+            counters: Arc::default(),
+            webhooks: Webhooks::default(),
+            tag: None,
+            catalog: None,
+        })
+    }
+
+    /// Persist pages with `n_writers` concurrent writers
+    pub fn with_writers(mut self, n_writers: usize) -> Self {
+        self.n_writers = n_writers;
+        self
+    }
+
+    /// Write an [index](crate::index) of the stored pages in the
+    /// storage root once the crawl is over, if `index`
+    pub fn with_index(mut self, index: bool) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// List the clusters of the stored pages whose text fingerprints are
+    /// within `max_distance` bits in the storage root, if given
+    pub fn with_near_duplicates(mut self, max_distance: Option<u32>) -> Self {
+        self.near_duplicates = max_distance;
+        self
+    }
+
+    /// List the `slow_pages` slowest pages in the storage root
+    pub fn with_slow_pages(mut self, slow_pages: usize) -> Self {
+        self.slow_pages = slow_pages;
+        self
+    }
+
+    /// Visit one page at a time and persist pages with a single writer, so
+    /// that the pages of a site are visited and stored in a stable order.
+    ///
+    /// Identical manifests also require a storage
+    /// [with sequential timestamps](Storage::with_sequential_timestamps).
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Send every visited page to `pages`, as soon as it is scraped.
+    /// Each of several senders receives every page.
+    pub fn with_pages(mut self, pages: UnboundedSender<CrawledPage>) -> Self {
+        self.pages.push(pages);
+        self
+    }
+
+    /// Queue the seeds received from `seeds` while crawling, as they
+    /// arrive. The crawl waits for more seeds until the sender is dropped.
+    pub fn with_seeds(mut self, seeds: UnboundedReceiver<url::Url>) -> Self {
+        self.seeds = Some(seeds);
+        self
+    }
+
+    /// Stop queueing pages once `stop` is set. The visits in progress
+    /// are completed, and the pending frontier is saved for a resume.
+    pub fn with_stop(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Notify the webhooks of `config` of the start, milestones
+    /// and end of the crawl
+    pub fn with_webhooks(mut self, config: WebhookConfig) -> Self {
+        self.webhooks = Webhooks::new(config);
+        self
+    }
+
+    /// Tag the run, e.g. to find it in a catalog
+    pub fn with_tag(mut self, tag: Option<String>) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Record the run in `catalog` when it starts and when it finishes
+    pub fn with_catalog(mut self, catalog: Option<Catalog>) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    /// Spawn the crawl and storage tasks on `executor`
+    pub fn with_executor(mut self, executor: Arc<dyn Executor>) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    /// Notify the webhooks of `event` in the background
+    fn notify(&self, event: WebhookEvent, run: Option<RunMetadata>) {
+        if self.webhooks.is_empty() {
+            return;
+        }
+        let webhooks = self.webhooks.clone();
+        let run_id = self.run_id;
+        let notification = async move { webhooks.notify(run_id, event, run).await };
+        self.executor
+            .spawn_boxed(notification.in_current_span().boxed());
+    }
+
+    pub fn queue_task(&mut self, context: TaskContext, writer: StorageWriter) {
+        let scraper = self.scraper_for(&context.url).clone();
+        let url = context.url.clone();
+        let timeout = self.limits.task_timeout_secs.map(Duration::from_secs);
+        let slots = self.visit_slots.clone();
+        // The visit is dropped at its next await past the deadline,
+        // releasing its concurrency slot and byte reservation
+        let visit = async move {
+            let _slot = match &slots {
+                Some(slots) => Some(slots.acquire().await.expect("slots are never closed")),
+                None => None,
+            };
+            let visit = scraper.visit(context, writer);
+            match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, visit)
+                    .await
+                    .unwrap_or(Err(CrawlerError::TaskTimeout(timeout))),
+                None => visit.await,
+            }
+        };
+        let handle = executor::spawn(self.executor.as_ref(), visit.in_current_span());
+        self.task_queue
+            .push_back(handle.map(|result| (url, result)).boxed());
+    }
+
+    /// Run the crawl, on any runtime. Its logs, including those of its
+    /// tasks, are recorded within a `crawl` span carrying the run id, so
+    /// that the crawls running concurrently in a process can be told apart.
+    pub async fn run(self) -> Result<()> {
+        executor::compat(self.run_in_span()).await
+    }
+
+    async fn run_in_span(self) -> Result<()> {
+        let span = tracing::info_span!("crawl", run_id = %self.run_id);
+        let run_id = self.run_id;
+        let webhooks = self.webhooks.clone();
+        let root = self.storage.path().to_path_buf();
+        #[cfg(feature = "object-store")]
+        let storage = Arc::clone(&self.storage);
+        let result = self.crawl().instrument(span.clone()).await;
+        // The files of a failed crawl are uploaded as well, for a resume
+        #[cfg(feature = "object-store")]
+        let result = match storage.sync_remote().instrument(span.clone()).await {
+            Ok(()) => result,
+            Err(err) => {
+                tracing::error!("==> Failed to upload the storage: {}", err);
+                result.and(Err(err))
+            }
+        };
+        if !webhooks.is_empty() {
+            let event = match &result {
+                Ok(()) => WebhookEvent::Finished,
+                Err(err) => WebhookEvent::Failed {
+                    error: err.to_string(),
+                },
+            };
+            // A run failing early leaves the metadata of a previous run, if any
+            let run = RunMetadata::load(&root)
+                .await
+                .ok()
+                .filter(|run| run.run_id == run_id);
+            webhooks.notify(run_id, event, run).instrument(span).await;
+        }
+        result
+    }
+
+    async fn crawl(mut self) -> Result<()> {
+        let mut problems = self.limits.problems();
+        if self.n_writers == 0 {
+            problems.push("no storage writer, so no page is ever stored".to_string());
+        }
+        if !problems.is_empty() {
+            return Err(CrawlerError::InvalidConfig(problems));
+        }
+        let Limits {
+            max_tasks,
+            max_pages,
+            max_pages_per_host,
+            max_inflight_bytes,
+            stall_timeout_secs,
+            max_frontier,
+            frontier_overflow,
+            adaptive_concurrency,
+            ..
+        } = self.limits;
+        let url_limits = self.limits.url_limits();
+        let sampler = self.sampler.clone();
+        self.update_frontier(|frontier| frontier.with_url_limits(url_limits).with_sampler(sampler));
+        let max_tasks = match self.deterministic {
+            true => 1,
+            false => max_tasks,
+        };
+        let mut concurrency = adaptive_concurrency.then(|| AdaptiveConcurrency::new(max_tasks));
+        if self.deterministic {
+            self.n_writers = 1;
+        }
+        if let Some(max_frontier) = max_frontier {
+            self.update_frontier(|frontier| {
+                frontier.with_capacity(max_frontier, frontier_overflow)
+            });
+        }
+        if let Some(max_inflight_bytes) = max_inflight_bytes {
+            let budget = ByteBudget::new(max_inflight_bytes);
+            for (_, scraper) in &mut self.host_scrapers {
+                scraper.budget = Some(budget.clone());
+            }
+            self.scraper.budget = Some(budget);
+        }
+        // Setup storagedir
+        self.storage.setup().await?;
+        let _lease = self.storage.lease().await?;
+        self.scraper.log_in().await?;
+        for (_, scraper) in &mut self.host_scrapers {
+            scraper.log_in().await?;
+        }
+        let mut metadata = RunMetadata::start(
+            self.run_id,
+            self.seed_urls.clone(),
+            self.resumed,
+            RunConfig {
+                limits: self.limits.clone(),
+                n_writers: self.n_writers,
+                layout: self.storage.layout(),
+            },
+        )
+        .with_tag(self.tag.clone());
+        metadata.save(self.storage.path()).await?;
+        Self::record_in_catalog(self.catalog.as_ref(), &metadata, self.storage.path()).await;
+        tracing::info!("==> Starting run {}", self.run_id);
+        self.notify(WebhookEvent::Started, Some(metadata.clone()));
+        let writers = WriterPool::new(
+            Arc::clone(&self.storage),
+            self.n_writers,
+            self.executor.as_ref(),
+        );
+        // Descend into nested urls, starting with the frontier
+        let mut n_pages_queued = 0;
+        let mut n_pages_visited = 0;
+        let mut n_pages_failed = 0;
+        let mut n_pages_panicked = 0;
+        let mut n_pages_timed_out = 0;
+        let mut hosts = HostTable::default();
+        let mut n_host_pages_queued = HashMap::<String, usize>::new();
+        let mut latencies = LatencyRecorder::new(self.slow_pages);
+        let mut in_flight = HashSet::new();
+        let mut hsts = self.hsts.then(HstsTable::default);
+        // Queue again the urls spilled by a previous run
+        self.unspill().await?;
+        let mut spilled = false;
+        let counters = Arc::clone(&self.counters);
+        loop {
+            spilled |= self.spill().await?;
+            let disk_full = writers.is_disk_full();
+            let max_tasks = concurrency
+                .as_ref()
+                .map_or(max_tasks, AdaptiveConcurrency::limit);
+            while self.task_queue.len() < max_tasks
+                && n_pages_queued < max_pages
+                && !self.stop.load(Ordering::Relaxed)
+                && !disk_full
+            {
+                let next = self.frontier().pop();
+                let Some(mut context) = next else {
+                    if spilled {
+                        self.unspill().await?;
+                        spilled = false;
+                        continue;
+                    }
+                    break;
+                };
+                if let Some(upgraded) = hsts.as_ref().and_then(|hsts| hsts.upgrade(&context.url)) {
+                    if self.frontier().is_visited(&upgraded) {
+                        continue;
+                    }
+                    tracing::debug!("  -> Upgrading {} to https", context.url);
+                    self.frontier().skip(upgraded.clone());
+                    context.url = upgraded;
+                }
+                let host = context.url.host_str().unwrap_or_default().to_string();
+                let n_host_pages = n_host_pages_queued.entry(host).or_default();
+                if max_pages_per_host.is_some_and(|max| *n_host_pages >= max) {
+                    tracing::debug!("  -> Skipping {} beyond the quota of its host", context.url);
+                    continue;
+                }
+                *n_host_pages += 1;
+                in_flight.insert(context.url.clone());
+                self.queue_task(context, writers.writer());
+                n_pages_queued += 1;
+            }
+            counters
+                .queued
+                .store(self.frontier().len(), Ordering::Relaxed);
+            counters.in_flight.store(in_flight.len(), Ordering::Relaxed);
+            let stopped =
+                n_pages_queued >= max_pages || self.stop.load(Ordering::Relaxed) || disk_full;
+            // Waiting for seeds with no visit in flight is not a stall
+            let stall_timeout = stall_timeout_secs
+                .filter(|_| !self.task_queue.is_empty())
+                .map(Duration::from_secs);
+            let next_event = async {
+                match &mut self.seeds {
+                    Some(seeds) if !stopped => {
+                        if self.task_queue.is_empty() {
+                            Either::Right(seeds.next().await)
+                        } else {
+                            match future::select(self.task_queue.next(), seeds.next()).await {
+                                Either::Left((outcome, _)) => Either::Left(outcome),
+                                Either::Right((seed, _)) => Either::Right(seed),
+                            }
+                        }
+                    }
+                    _ => Either::Left(self.task_queue.next().await),
+                }
+            };
+            let event = match stall_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, next_event).await {
+                    Ok(event) => event,
+                    Err(_) => return self.abort_stalled(timeout, in_flight, writers).await,
+                },
+                None => next_event.await,
+            };
+            let (url, result) = match event {
+                Either::Left(Some(outcome)) => {
+                    in_flight.remove(&outcome.0);
+                    counters.in_flight.store(in_flight.len(), Ordering::Relaxed);
+                    outcome
+                }
+                Either::Left(None) => break,
+                Either::Right(Some(seed)) => {
+                    tracing::info!("==> Received seed {}", seed);
+                    metadata.seeds.push(seed.clone());
+                    self.frontier().push(TaskContext::seed(seed));
+                    continue;
+                }
+                Either::Right(None) => {
+                    self.seeds = None;
+                    continue;
+                }
+            };
+            let failure = match result {
+                Ok(Ok(mut page)) => {
+                    if let Some(hsts) = &mut hsts {
+                        hsts.record(&page.final_url, &page.headers);
+                        if hsts::upgrades_insecure_requests(&page.headers) {
+                            upgrade_links(&mut page);
+                        }
+                    }
+                    hosts.record_page(&page.url, page.body.len(), page.latency);
+                    latencies.record(&page.url, page.latency);
+                    if let Some(concurrency) = &mut concurrency {
+                        concurrency.record_visit(page.status, page.latency);
+                    }
+                    for pages in &self.pages {
+                        // A closed receiver only stops listening to the crawl
+                        let _ = pages.unbounded_send(page.clone());
+                    }
+                    n_pages_visited += 1;
+                    counters.visited.fetch_add(1, Ordering::Relaxed);
+                    counters
+                        .bytes
+                        .fetch_add(page.body.len() as u64, Ordering::Relaxed);
+                    tracing::info!("==> Visited {} out of {}", n_pages_visited, max_pages);
+                    if let Some(percent) = self.webhooks.milestone(n_pages_visited, max_pages) {
+                        let milestone = WebhookEvent::Milestone {
+                            percent,
+                            visited: n_pages_visited,
+                            max_pages,
+                        };
+                        self.notify(milestone, None);
+                    }
+                    tracing::debug!(
+                        "  -> {} {} (depth {}, {} links)",
+                        page.status,
+                        page.final_url,
+                        page.depth,
+                        page.discovered_links.len()
+                    );
+                    if !page.alternates.is_empty() {
+                        let record = PageAlternates {
+                            url: page.url.clone(),
+                            alternates: page.alternates,
+                        };
+                        self.storage.append_record(ALTERNATES_FILE, &record).await?;
+                    }
+                    if let Some(canonical) = &page.canonical {
+                        let record = PageCanonical {
+                            url: page.url.clone(),
+                            canonical: canonical.clone(),
+                        };
+                        self.storage.append_record(CANONICALS_FILE, &record).await?;
+                    }
+                    for extraction in &page.extractions {
+                        self.storage
+                            .append_record(&extraction.report_file, &extraction.record)
+                            .await?;
+                    }
+                    for variant in page.variants {
+                        self.frontier().skip(variant);
+                    }
+                    let (links, external): (Vec<_>, Vec<_>) =
+                        page.discovered_links.into_iter().partition(|link| {
+                            metadata
+                                .seeds
+                                .iter()
+                                .any(|seed| self.scope.includes(seed, link))
+                        });
+                    if !external.is_empty() {
+                        let record = ExternalLinks {
+                            url: page.url.clone(),
+                            links: external,
+                        };
+                        self.storage
+                            .append_record(EXTERNAL_LINKS_FILE, &record)
+                            .await?;
+                    }
+                    self.frontier().discover(
+                        &page.url,
+                        page.depth,
+                        &page.metadata,
+                        links,
+                        &page.priorities,
+                    );
+                    continue;
+                }
+                Ok(Err(err)) => {
+                    if let Some(concurrency) = &mut concurrency {
+                        concurrency.record_failure();
+                    }
+                    let kind = match err {
+                        CrawlerError::TaskTimeout(_) => {
+                            n_pages_timed_out += 1;
+                            tracing::warn!("visit of page {} cancelled: {}", url, err);
+                            FailureKind::Timeout
+                        }
+                        _ => {
+                            tracing::warn!("error visiting page {}: {:?}", url, err);
+                            FailureKind::Error
+                        }
+                    };
+                    PageFailure {
+                        url,
+                        kind,
+                        message: err.to_string(),
+                    }
+                }
+                Err(payload) => {
+                    n_pages_panicked += 1;
+                    let message = executor::panic_message(payload.as_ref());
+                    tracing::error!("task visiting page {} panicked: {}", url, message);
+                    PageFailure {
+                        url,
+                        kind: FailureKind::Panic,
+                        message,
+                    }
+                }
+            };
+            n_pages_queued -= 1;
+            n_pages_failed += 1;
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+            if let Some(n_host_pages) =
+                n_host_pages_queued.get_mut(failure.url.host_str().unwrap_or_default())
+            {
+                *n_host_pages -= 1;
+            }
+            hosts.record_error(&failure.url);
+            self.storage.append_record(FAILURES_FILE, &failure).await?;
+        }
+        let stats = writers.close().await?;
+        tracing::info!(
+            "==> Stored {} pages ({} failed, max queue depth {})",
+            stats.written,
+            stats.failed,
+            stats.max_queue_depth
+        );
+        for (host, stats) in hosts.top(TOP_HOSTS) {
+            tracing::info!(
+                "  -> {}: {} pages, {} bytes, {:?} avg latency, {:.1}% errors",
+                host,
+                stats.pages,
+                stats.bytes,
+                stats.avg_latency(),
+                stats.error_rate() * 100.0
+            );
+        }
+        if let Some(concurrency) = &concurrency {
+            tracing::info!(
+                "==> Adapted the concurrent visits to {}",
+                concurrency.limit()
+            );
+        }
+        let latency = latencies.summary();
+        if let Some(latency) = &latency {
+            tracing::info!(
+                "==> Fetch latency p50 {}ms, p90 {}ms, p99 {}ms, max {}ms",
+                latency.p50_ms,
+                latency.p90_ms,
+                latency.p99_ms,
+                latency.max_ms
+            );
+        }
+        if self.slow_pages > 0 {
+            let mut lines = Vec::new();
+            for page in latencies.slowest() {
+                serde_json::to_writer(&mut lines, &page)?;
+                lines.push(b'\n');
+            }
+            tokio::fs::write(self.storage.path().join(SLOW_PAGES_FILE), lines).await?;
+        }
+        if let Some(max_distance) = self.near_duplicates.filter(|_| stats.disk_full.is_none()) {
+            let manifest = Manifest::load(self.storage.path()).await?;
+            let pages: Vec<_> = manifest
+                .latest()
+                .into_values()
+                .filter_map(|entry| Some((entry.url.clone(), entry.stats.as_ref()?.simhash?)))
+                .collect();
+            let clusters = simhash::clusters(&pages, max_distance);
+            let mut lines = Vec::new();
+            for cluster in &clusters {
+                serde_json::to_writer(&mut lines, cluster)?;
+                lines.push(b'\n');
+            }
+            tokio::fs::write(self.storage.path().join(NEAR_DUPLICATES_FILE), lines).await?;
+            tracing::info!(
+                "==> Found {} clusters of near-duplicate pages",
+                clusters.len()
+            );
+        }
+        if self.index && stats.disk_full.is_none() {
+            if let Some(n_pages) = index::write_index(&self.storage).await? {
+                tracing::info!("==> Indexed {} pages in {}", n_pages, INDEX_FILE);
+            }
+        }
+        let dropped = self.frontier().dropped();
+        if dropped > 0 {
+            tracing::info!("==> Dropped {} urls from the full frontier", dropped);
+        }
+        let rejected = self.frontier().rejected().clone();
+        if rejected.total() > 0 {
+            tracing::info!(
+                "==> Rejected {} urls: {} too long, {} with too many query parameters, {} too deep",
+                rejected.total(),
+                rejected.length,
+                rejected.query_params,
+                rejected.path_depth
+            );
+        }
+        let sampled_out = self.frontier().sampled_out();
+        if sampled_out > 0 {
+            tracing::info!("==> Left {} discovered urls out of the sample", sampled_out);
+        }
+        // Persist the pending frontier, along with the pages that could not be stored
+        self.unspill().await?;
+        let (mut visited, mut frontier) = std::mem::take(&mut *self.frontier()).into_parts();
+        visited.retain(|url| !stats.refused.contains(url));
+        frontier.extend(stats.refused.iter().cloned().map(TaskContext::seed));
+        let pending = frontier.len();
+        CrawlState::new(self.root_url.clone(), visited, frontier)
+            .save(self.storage.path())
+            .await?;
+        metadata.finish(RunStats {
+            visited: n_pages_visited,
+            failed: n_pages_failed,
+            panicked: n_pages_panicked,
+            timed_out: n_pages_timed_out,
+            stored: stats.written,
+            store_failed: stats.failed,
+            pending,
+            hosts,
+            latency,
+            rejected,
+            sampled_out,
+        });
+        metadata.save(self.storage.path()).await?;
+        Self::record_in_catalog(self.catalog.as_ref(), &metadata, self.storage.path()).await;
+        if let Some(available) = stats.disk_full {
+            tracing::error!("==> Aborted the crawl for lack of disk space");
+            return Err(CrawlerError::DiskFull {
+                path: self.storage.path().to_path_buf(),
+                available,
+                required: self.storage.min_free_bytes(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Record the run stored in `storage` in the `catalog`, if any. A
+    /// catalog that cannot be written is reported without failing the crawl.
+    async fn record_in_catalog(catalog: Option<&Catalog>, metadata: &RunMetadata, storage: &Path) {
+        let Some(catalog) = catalog else { return };
+        if let Err(err) = catalog.record(metadata, storage).await {
+            tracing::warn!(
+                "==> Cannot record the run in the catalog {:?}: {}",
+                catalog.path(),
+                err
+            );
+        }
+    }
+
+    fn frontier(&self) -> MutexGuard<'_, Frontier> {
+        self.frontier.lock().unwrap()
+    }
+
+    fn update_frontier(&self, update: impl FnOnce(Frontier) -> Frontier) {
+        let mut frontier = self.frontier();
+        *frontier = update(std::mem::take(&mut *frontier));
+    }
+
+    /// Append the urls set aside by the full frontier to the spill
+    /// file. Returns whether any url was spilled.
+    async fn spill(&mut self) -> Result<bool> {
+        let spilled = self.frontier().take_spilled();
+        for context in &spilled {
+            self.storage
+                .append_record(FRONTIER_SPILL_FILE, context)
+                .await?;
+        }
+        Ok(!spilled.is_empty())
+    }
+
+    /// Queue again the urls of the spill file, removing it
+    async fn unspill(&mut self) -> Result<()> {
+        let path = self.storage.path().join(FRONTIER_SPILL_FILE);
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        tokio::fs::remove_file(&path).await?;
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            self.frontier().push(serde_json::from_str(line)?);
+        }
+        Ok(())
+    }
+
+    /// Abort a run where no visit completed within `timeout`, reporting
+    /// the visits `in_flight` and saving them in the pending frontier.
+    ///
+    /// The writers are stopped without waiting for the pages in their queue.
+    async fn abort_stalled(
+        mut self,
+        timeout: Duration,
+        in_flight: HashSet<url::Url>,
+        writers: WriterPool,
+    ) -> Result<()> {
+        tracing::error!(
+            "==> No visit completed in {:?}: {} visits in flight, {} pages waiting to be \
+             stored, {} urls in the frontier",
+            timeout,
+            in_flight.len(),
+            writers.writer().queue_depth(),
+            self.frontier().len()
+        );
+        for url in &in_flight {
+            tracing::error!("  -> In flight: {}", url);
+        }
+        // The pages not stored are visited again on a resume
+        let mut dropped = in_flight;
+        for url in writers.flush(FLUSH_TIMEOUT).await {
+            tracing::error!("  -> Not stored: {}", url);
+            dropped.insert(url);
+        }
+        self.unspill().await?;
+        let (mut visited, mut frontier) = std::mem::take(&mut *self.frontier()).into_parts();
+        visited.retain(|url| !dropped.contains(url));
+        frontier.extend(dropped.into_iter().map(TaskContext::seed));
+        CrawlState::new(self.root_url, visited, frontier)
+            .save(self.storage.path())
+            .await?;
+        Err(CrawlerError::Stalled(timeout))
+    }
+}
+
+/// Upgrade the `http://` links of `page` to its own host, as
+/// asked by its `upgrade-insecure-requests` directive
+fn upgrade_links(page: &mut CrawledPage) {
+    let host = page.final_url.host_str().map(str::to_owned);
+    for link in &mut page.discovered_links {
+        if link.scheme() == "http" && link.host_str() == host.as_deref() {
+            let upgraded = hsts::upgrade(link);
+            if let Some(priority) = page.priorities.remove(link) {
+                page.priorities.insert(upgraded.clone(), priority);
+            }
+            *link = upgraded;
+        }
+    }
+}
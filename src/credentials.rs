@@ -0,0 +1,212 @@
+//! Credentials of the crawled hosts.
+//!
+//! A credentials file lists the authentication of some hosts, each given
+//! by a host or a `*.domain` pattern as the [host overrides](crate::config::HostOverride),
+//! so that authenticated crawls need no secret on the command line:
+//!
+//! ```json
+//! [
+//!   {"host": "intranet.example.com", "auth": {"basic": {"username": "crawler", "password": "..."}}},
+//!   {"host": "*.api.example.com", "auth": {"bearer": "..."}},
+//!   {"host": "docs.example.com", "auth": {"header": {"name": "X-Api-Key", "value": "..."}}}
+//! ]
+//! ```
+//!
+//! The file must not be accessible by other users. The secrets are sent
+//! as sensitive headers and never logged. The redirects of the requests
+//! are followed one hop at a time, each hop carrying the credentials of
+//! its own host only, so that no header, custom ones included, is sent
+//! to another host a request is redirected to.
+use std::fmt;
+use std::path::Path;
+
+use base64::Engine;
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION};
+use serde::Deserialize;
+
+use crate::config::matches_host;
+use crate::error::{CrawlerError, Result};
+
+/// The authentication of the requests to a host
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum Auth {
+    /// HTTP basic authentication
+    Basic {
+        username: String,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    /// A bearer token
+    Bearer(String),
+    /// A custom header, e.g. an API key
+    Header { name: String, value: String },
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic { .. } => write!(f, "Basic(..)"),
+            Self::Bearer(_) => write!(f, "Bearer(..)"),
+            Self::Header { name, .. } => write!(f, "Header({}: ..)", name),
+        }
+    }
+}
+
+impl Auth {
+    /// The header authenticating a request, marked as sensitive
+    pub fn header(&self) -> Result<(HeaderName, HeaderValue)> {
+        let (name, value) = match self {
+            Self::Basic { username, password } => {
+                let credentials = format!("{}:{}", username, password.as_deref().unwrap_or(""));
+                let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+                (AUTHORIZATION, format!("Basic {}", encoded))
+            }
+            Self::Bearer(token) => (AUTHORIZATION, format!("Bearer {}", token)),
+            Self::Header { name, value } => {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| CrawlerError::InvalidHeader(name.clone()))?;
+                (name, value.clone())
+            }
+        };
+        // The value is left out of the error, which may be logged
+        let mut value = HeaderValue::from_str(&value)
+            .map_err(|_| CrawlerError::InvalidHeader(format!("{}: ..", name)))?;
+        value.set_sensitive(true);
+        Ok((name, value))
+    }
+}
+
+/// The authentication of the hosts matching a pattern
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostCredentials {
+    /// The host, e.g. `example.com`, or a pattern matching a
+    /// domain and its subdomains, e.g. `*.example.com`
+    pub host: String,
+    pub auth: Auth,
+}
+
+/// The credentials of some hosts, the first matching the host
+/// of a request applying
+#[derive(Clone, Default)]
+pub struct Credentials {
+    hosts: Vec<(String, HeaderName, HeaderValue)>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.hosts.iter().map(|(host, _, _)| host))
+            .finish()
+    }
+}
+
+impl Credentials {
+    pub fn new(hosts: Vec<HostCredentials>) -> Result<Self> {
+        let hosts = hosts
+            .into_iter()
+            .map(|credentials| {
+                let (name, value) = credentials.auth.header()?;
+                Ok((credentials.host, name, value))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { hosts })
+    }
+
+    /// The credentials of the JSON file at `path`, failing if the
+    /// file is accessible by other users than its owner
+    pub fn load(path: &Path) -> Result<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(path)?.permissions().mode();
+            if mode & 0o077 != 0 {
+                return Err(CrawlerError::InsecureCredentials(path.to_path_buf()));
+            }
+        }
+        let hosts: Vec<HostCredentials> = serde_json::from_slice(&std::fs::read(path)?)?;
+        Self::new(hosts)
+    }
+
+    /// The header authenticating the requests to `url`, if any
+    pub fn header(&self, url: &url::Url) -> Option<(&HeaderName, &HeaderValue)> {
+        let host = url.host_str()?;
+        self.hosts
+            .iter()
+            .find(|(pattern, _, _)| matches_host(pattern, host))
+            .map(|(_, name, value)| (name, value))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTS: &str = r#"[
+        {"host": "intranet.example.com", "auth": {"basic": {"username": "crawler", "password": "secret"}}},
+        {"host": "*.api.example.com", "auth": {"bearer": "token"}}
+    ]"#;
+
+    fn url(url: &str) -> url::Url {
+        url::Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn the_first_matching_host_applies() {
+        let credentials = Credentials::new(serde_json::from_str(HOSTS).unwrap()).unwrap();
+        let (name, value) = credentials
+            .header(&url("https://intranet.example.com/"))
+            .unwrap();
+        assert_eq!(name, AUTHORIZATION);
+        assert_eq!(value, "Basic Y3Jhd2xlcjpzZWNyZXQ=");
+        assert!(value.is_sensitive());
+        let (_, value) = credentials
+            .header(&url("https://v1.api.example.com/"))
+            .unwrap();
+        assert_eq!(value, "Bearer token");
+        assert!(credentials.header(&url("https://example.com/")).is_none());
+    }
+
+    #[test]
+    fn secrets_are_not_printed() {
+        let credentials = Credentials::new(serde_json::from_str(HOSTS).unwrap()).unwrap();
+        let printed = format!("{:?}", credentials);
+        assert!(!printed.contains("secret") && !printed.contains("token"));
+        let auth = Auth::Header {
+            name: "X-Api-Key".into(),
+            value: "key\n".into(),
+        };
+        assert_eq!(format!("{:?}", auth), "Header(X-Api-Key: ..)");
+        match auth.header() {
+            Err(CrawlerError::InvalidHeader(header)) => assert_eq!(header, "x-api-key: .."),
+            other => panic!("invalid header accepted: {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_accessible_by_others_are_rejected() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.json");
+        std::fs::write(&path, HOSTS).unwrap();
+        for (mode, accepted) in [(0o600, true), (0o400, true), (0o640, false), (0o604, false)] {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            match Credentials::load(&path) {
+                Ok(credentials) => assert!(accepted && !credentials.is_empty()),
+                Err(CrawlerError::InsecureCredentials(insecure)) => {
+                    assert!(!accepted);
+                    assert_eq!(insecure, path);
+                }
+                Err(err) => panic!("unexpected error: {}", err),
+            }
+        }
+    }
+}
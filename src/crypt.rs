@@ -0,0 +1,104 @@
+//! Encryption at rest of the stored pages.
+//!
+//! Pages are encrypted with AES-256-GCM under a 32-byte key, given as 64
+//! hex digits in a key file or in the `WEBCRAWLER_ENCRYPTION_KEY`
+//! environment variable. An encrypted file holds a magic prefix, the
+//! random nonce of the page, its ciphertext and the authentication tag.
+//!
+//! The manifest keeps recording the hash and size of the plain content,
+//! so that changes are detected as without encryption, and marks the
+//! encrypted entries.
+use std::fmt;
+use std::path::Path;
+
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use crate::error::{CrawlerError, Result};
+use crate::manifest::ENCRYPTION_OVERHEAD;
+
+/// The environment variable holding the hex-encoded key
+pub const KEY_ENV: &str = "WEBCRAWLER_ENCRYPTION_KEY";
+
+const MAGIC: &[u8] = b"WCE1";
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// An AES-256 key
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+impl EncryptionKey {
+    /// The key encoded by the 64 hex digits of `hex`
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let mut key = [0; 32];
+        hex::decode_to_slice(hex.trim(), &mut key)
+            .map_err(|err| CrawlerError::Encryption(format!("invalid key: {}", err)))?;
+        Ok(Self(key))
+    }
+
+    /// The key stored in the file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_hex(&std::fs::read_to_string(path)?)
+    }
+
+    /// The key of the environment, if any
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(KEY_ENV) {
+            Ok(hex) => Self::from_hex(&hex).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Encrypt `content` under a new random nonce
+    pub fn encrypt(&self, content: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        openssl::rand::rand_bytes(&mut nonce).map_err(crypto_error)?;
+        let mut tag = [0; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(&nonce),
+            MAGIC,
+            content,
+            &mut tag,
+        )
+        .map_err(crypto_error)?;
+        let mut encrypted = Vec::with_capacity(content.len() + ENCRYPTION_OVERHEAD);
+        encrypted.extend_from_slice(MAGIC);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        encrypted.extend_from_slice(&tag);
+        Ok(encrypted)
+    }
+
+    /// Decrypt the `encrypted` content, failing if it was not
+    /// encrypted under this key or was altered
+    pub fn decrypt(&self, encrypted: &[u8]) -> Result<Vec<u8>> {
+        if encrypted.len() < ENCRYPTION_OVERHEAD || !encrypted.starts_with(MAGIC) {
+            return Err(CrawlerError::Encryption(
+                "not an encrypted page".to_string(),
+            ));
+        }
+        let (nonce, rest) = encrypted[MAGIC.len()..].split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(nonce),
+            MAGIC,
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| CrawlerError::Encryption("wrong key or altered page".to_string()))
+    }
+}
+
+fn crypto_error(err: openssl::error::ErrorStack) -> CrawlerError {
+    CrawlerError::Encryption(err.to_string())
+}
@@ -0,0 +1,184 @@
+//! Comparison of crawls and of page versions.
+//!
+//! Two storage directories are compared through their manifests:
+//! pages are matched by url, and their content hashes tell whether
+//! a page changed between the crawls.
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+
+use crate::error::Result;
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::Storage;
+
+/// A page stored in both crawls with different content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangedPage {
+    pub url: url::Url,
+    pub old_hash: String,
+    pub new_hash: String,
+    /// Unified diff of the old and new content, if requested
+    pub diff: Option<String>,
+}
+
+/// The differences between two crawls
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CrawlDiff {
+    pub added: Vec<url::Url>,
+    pub removed: Vec<url::Url>,
+    pub changed: Vec<ChangedPage>,
+}
+
+impl CrawlDiff {
+    /// The differences between the latest pages of the `old`
+    /// and `new` manifests, without text diffs
+    pub fn between(old: &Manifest, new: &Manifest) -> Self {
+        let old_pages = old.latest();
+        let new_pages = new.latest();
+
+        let mut diff = Self::default();
+        for (url, new_entry) in &new_pages {
+            match old_pages.get(url) {
+                None => diff.added.push((*url).clone()),
+                Some(old_entry) if old_entry.hash != new_entry.hash => {
+                    diff.changed.push(ChangedPage {
+                        url: (*url).clone(),
+                        old_hash: old_entry.hash.clone(),
+                        new_hash: new_entry.hash.clone(),
+                        diff: None,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = old_pages
+            .keys()
+            .filter(|url| !new_pages.contains_key(*url))
+            .map(|url| (*url).clone())
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort_by(|a, b| a.url.cmp(&b.url));
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare the crawl stored in `old` against the one in `new`.
+///
+/// When `text` is set, changed pages carry a unified diff of their
+/// content, read through the storages, which decrypt encrypted pages.
+pub async fn compare(old: &Storage, new: &Storage, text: bool) -> Result<CrawlDiff> {
+    let old_manifest = Manifest::load(old.path()).await?;
+    let new_manifest = Manifest::load(new.path()).await?;
+    let mut diff = CrawlDiff::between(&old_manifest, &new_manifest);
+    if text {
+        let old_pages = old_manifest.latest();
+        let new_pages = new_manifest.latest();
+        for changed in &mut diff.changed {
+            let (old_entry, new_entry) = (old_pages[&changed.url], new_pages[&changed.url]);
+            changed.diff = Some(diff_entries(old, old_entry, new, new_entry).await?);
+        }
+    }
+    Ok(diff)
+}
+
+async fn diff_entries(
+    old: &Storage,
+    old_entry: &ManifestEntry,
+    new: &Storage,
+    new_entry: &ManifestEntry,
+) -> Result<String> {
+    if old_entry.binary || new_entry.binary {
+        return Ok(binary(&old_entry.hash, &new_entry.hash));
+    }
+    let old = old.read(old_entry).await?;
+    let new = new.read(new_entry).await?;
+    Ok(unified(
+        &String::from_utf8_lossy(&old),
+        &String::from_utf8_lossy(&new),
+        &old_entry.hash,
+        &new_entry.hash,
+    ))
+}
+
+/// Render the difference of two binary pages, which are not diffed
+pub fn binary(old_label: &str, new_label: &str) -> String {
+    format!("Binary pages {} and {} differ\n", old_label, new_label)
+}
+
+/// Render a unified diff going from `old` to `new`
+pub fn unified(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(old_label, new_label)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::storage::PageAnnotations;
+
+    async fn crawl(dir: &Path, pages: &[(&str, &str)]) -> Storage {
+        let storage = Storage::new(dir.to_path_buf());
+        storage.setup().await.unwrap();
+        for (url, page) in pages {
+            let annotations = PageAnnotations::new(url::Url::parse(url).unwrap());
+            storage.serialize(page, &annotations).await.unwrap();
+        }
+        storage
+    }
+
+    fn urls(urls: &[url::Url]) -> Vec<&str> {
+        urls.iter().map(url::Url::path).collect()
+    }
+
+    #[tokio::test]
+    async fn crawls_are_compared_by_url_and_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = crawl(
+            &dir.path().join("old"),
+            &[
+                ("https://example.com/kept", "same\n"),
+                ("https://example.com/changed", "one\ntwo\n"),
+                ("https://example.com/removed", "gone\n"),
+            ],
+        )
+        .await;
+        let new = crawl(
+            &dir.path().join("new"),
+            &[
+                ("https://example.com/kept", "same\n"),
+                ("https://example.com/changed", "one\nthree\n"),
+                ("https://example.com/added", "new\n"),
+            ],
+        )
+        .await;
+
+        let diff = compare(&old, &new, false).await.unwrap();
+        assert_eq!(urls(&diff.added), ["/added"]);
+        assert_eq!(urls(&diff.removed), ["/removed"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].url.path(), "/changed");
+        assert!(diff.changed[0].diff.is_none());
+
+        let diff = compare(&old, &new, true).await.unwrap();
+        let text = diff.changed[0].diff.as_deref().unwrap();
+        assert!(text.contains("-two\n") && text.contains("+three\n"));
+        assert!(compare(&old, &old, true).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn unified_diffs_are_labelled() {
+        let diff = unified("a\nb\n", "a\nc\n", "old", "new");
+        assert!(diff.starts_with("--- old\n+++ new\n"));
+        assert!(diff.ends_with(" a\n-b\n+c\n"));
+        assert_eq!(binary("1", "2"), "Binary pages 1 and 2 differ\n");
+    }
+}
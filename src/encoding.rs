@@ -0,0 +1,159 @@
+//! Decoding of compressed response bodies.
+//!
+//! The scraper advertises the encodings of its config in the
+//! `Accept-Encoding` header of its requests, and decodes the bodies served
+//! with any of them, so that pages are transferred compressed but scraped,
+//! hashed and stored as plain content. A body served with an encoding that
+//! cannot be decoded, e.g. `br`, is kept as is, along with its
+//! `Content-Encoding` header.
+use std::io::Read;
+
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH};
+
+pub use crate::config::ContentEncoding;
+use crate::error::{CrawlerError, Result};
+
+/// The `Accept-Encoding` header accepting `encodings`, in order of
+/// preference, if any
+pub fn accept_encoding(encodings: &[ContentEncoding]) -> Option<HeaderValue> {
+    if encodings.is_empty() {
+        return None;
+    }
+    let n = encodings.len();
+    let value = encodings
+        .iter()
+        .enumerate()
+        .map(|(i, encoding)| match i {
+            0 => encoding.to_string(),
+            _ => format!("{};q={:.1}", encoding, 1.0 - i as f64 / n as f64),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    HeaderValue::from_str(&value).ok()
+}
+
+/// Decode the `body` of `url` served with `headers`, and drop the
+/// `Content-Encoding` and `Content-Length` headers of a decoded body
+pub fn decode(url: &url::Url, headers: &mut HeaderMap, body: Bytes) -> Result<Bytes> {
+    let Some(value) = headers.get(CONTENT_ENCODING).and_then(|v| v.to_str().ok()) else {
+        return Ok(body);
+    };
+    // The encodings are listed in the order they were applied
+    let encodings: Option<Vec<_>> = value
+        .split(',')
+        .map(|encoding| encoding.trim().to_ascii_lowercase())
+        .filter(|encoding| !encoding.is_empty() && encoding != "identity")
+        .map(|encoding| encoding.parse::<ContentEncoding>().ok())
+        .collect();
+    let Some(encodings) = encodings else {
+        tracing::debug!("  -> Keeping the {} body of {}", value, url);
+        return Ok(body);
+    };
+    let mut body = body.to_vec();
+    for encoding in encodings.into_iter().rev() {
+        body = decode_one(encoding, &body).map_err(|err| CrawlerError::Decoding {
+            url: url.to_string(),
+            encoding: encoding.to_string(),
+            reason: err.to_string(),
+        })?;
+    }
+    headers.remove(CONTENT_ENCODING);
+    headers.remove(CONTENT_LENGTH);
+    Ok(body.into())
+}
+
+fn decode_one(encoding: ContentEncoding, body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    match encoding {
+        ContentEncoding::Gzip => {
+            flate2::read::MultiGzDecoder::new(body).read_to_end(&mut decoded)?
+        }
+        // Some servers send raw deflate streams instead of zlib ones
+        ContentEncoding::Deflate => {
+            match flate2::read::ZlibDecoder::new(body).read_to_end(&mut decoded) {
+                Ok(n) => n,
+                Err(_) => {
+                    decoded.clear();
+                    flate2::read::DeflateDecoder::new(body).read_to_end(&mut decoded)?
+                }
+            }
+        }
+        ContentEncoding::Zstd => return zstd::stream::decode_all(body),
+    };
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const PAGE: &[u8] = b"<html><body>compressed page</body></html>";
+
+    fn url() -> url::Url {
+        url::Url::parse("https://example.com/").unwrap()
+    }
+
+    fn encoded(content_encoding: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, content_encoding.parse().unwrap());
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("10"));
+        headers
+    }
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn accepted_encodings_are_weighted_by_preference() {
+        assert_eq!(accept_encoding(&[]), None);
+        let encodings = [
+            ContentEncoding::Zstd,
+            ContentEncoding::Gzip,
+            ContentEncoding::Deflate,
+        ];
+        assert_eq!(
+            accept_encoding(&encodings).unwrap(),
+            "zstd, gzip;q=0.7, deflate;q=0.3"
+        );
+    }
+
+    #[test]
+    fn encoded_bodies_are_decoded_in_reverse_order() {
+        let mut raw_deflate =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+        raw_deflate.write_all(PAGE).unwrap();
+        let bodies = [
+            ("gzip", gzip(PAGE)),
+            ("deflate", raw_deflate.finish().unwrap()),
+            ("zstd", zstd::stream::encode_all(PAGE, 0).unwrap()),
+            (
+                "GZIP, identity, zstd",
+                zstd::stream::encode_all(gzip(PAGE).as_slice(), 0).unwrap(),
+            ),
+        ];
+        for (content_encoding, body) in bodies {
+            let mut headers = encoded(content_encoding);
+            let decoded = decode(&url(), &mut headers, body.into()).unwrap();
+            assert_eq!(decoded, PAGE, "{}", content_encoding);
+            assert!(headers.is_empty());
+        }
+    }
+
+    #[test]
+    fn undecodable_bodies_are_kept_or_rejected() {
+        let mut headers = encoded("br");
+        let body = decode(&url(), &mut headers, Bytes::from_static(b"brotli")).unwrap();
+        assert_eq!(body, "brotli");
+        assert!(headers.contains_key(CONTENT_ENCODING));
+
+        let mut headers = encoded("gzip");
+        let err = decode(&url(), &mut headers, Bytes::from_static(b"not gzip")).unwrap_err();
+        assert!(matches!(err, CrawlerError::Decoding { encoding, .. } if encoding == "gzip"));
+    }
+}
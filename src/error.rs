@@ -1,21 +1,100 @@
 //! Library-specific errors
 use thiserror::Error;
-use tokio::sync::mpsc;
-
-use super::TaskContext;
 
 #[derive(Error, Debug)]
 pub enum CrawlerError {
-    #[error("sync error {0}")]
-    UrlSend(#[from] mpsc::error::SendError<TaskContext>),
     #[error("url with no host")]
     NoUrlHost,
     #[error("url parse error {0}")]
     UrlParse(#[from] url::ParseError),
+    #[error("invalid seed `{seed}`: {reason}")]
+    InvalidSeed { seed: String, reason: String },
+    #[error("invalid crawl config: {}", .0.join("; "))]
+    InvalidConfig(Vec<String>),
     #[error("{0}")]
     Reqwest(#[from] reqwest::Error),
     #[error("io error {0}")]
     Io(#[from] std::io::Error),
+    #[error("json error {0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "runtime")]
+    #[error("zip error {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[cfg(feature = "nats")]
+    #[error("message queue error: {0}")]
+    Queue(String),
+    #[cfg(feature = "kafka")]
+    #[error("kafka error: {0}")]
+    Kafka(String),
+    #[cfg(feature = "object-store")]
+    #[error("object store error: {0}")]
+    ObjectStore(#[from] object_store::Error),
+    #[cfg(feature = "postgres")]
+    #[error("postgres error: {0}")]
+    Postgres(#[from] tokio_postgres::Error),
+    #[cfg(feature = "serve")]
+    #[error("http server error {0}")]
+    Hyper(#[from] hyper::Error),
+    #[error("invalid crawl state: {0}")]
+    InvalidState(String),
+    #[error("unsupported crawl state format version {found}, expected 1 to {supported}")]
+    UnsupportedStateVersion { found: u32, supported: u32 },
+    #[error("invalid schedule `{expression}`: {reason}")]
+    InvalidSchedule { expression: String, reason: String },
+    #[error("invalid header `{0}`")]
+    InvalidHeader(String),
+    #[error("invalid store policy {0}")]
+    StorePolicy(String),
+    #[error("disk full: {available} bytes available in {path:?}, {required} required")]
+    DiskFull {
+        path: std::path::PathBuf,
+        available: u64,
+        required: u64,
+    },
+    #[error("page {url} of {size} bytes exceeds the max of {max} bytes")]
+    PageTooLarge {
+        url: String,
+        size: usize,
+        max: usize,
+    },
+    #[error("cannot decode the {encoding} body of {url}: {reason}")]
+    Decoding {
+        url: String,
+        encoding: String,
+        reason: String,
+    },
+    #[error("visit timed out after {0:?}")]
+    TaskTimeout(std::time::Duration),
+    #[error("crawl stalled: no visit completed in {0:?}")]
+    Stalled(std::time::Duration),
+    #[error("{0} missing, corrupted or extra files in the storage")]
+    Integrity(usize),
+    #[error("storage directory {0:?} is in use by another crawl")]
+    StorageInUse(std::path::PathBuf),
+    #[error("more than {max} redirects, up to {url}")]
+    TooManyRedirects { url: String, max: usize },
+    #[error("login failed: {0}")]
+    Login(String),
+    #[error("credentials file {0:?} is accessible by other users, restrict it with `chmod 600`")]
+    InsecureCredentials(std::path::PathBuf),
+    #[error("encryption error: {0}")]
+    Encryption(String),
+    #[error("storage writers closed")]
+    WriterClosed,
+    #[cfg(feature = "runtime")]
+    #[error("task error {0}")]
+    Join(#[from] tokio::task::JoinError),
+    #[error("task panicked")]
+    TaskPanicked,
+    #[cfg(feature = "sqlite")]
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(feature = "scripting")]
+    #[error("script error: {0}")]
+    Script(String),
+    #[cfg(feature = "otel")]
+    #[error("telemetry error: {0}")]
+    Telemetry(String),
 }
 
 pub type Result<T> = std::result::Result<T, CrawlerError>;
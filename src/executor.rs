@@ -0,0 +1,108 @@
+//! Executors running the crawl tasks.
+//!
+//! The crawl logic does not depend on a particular async runtime:
+//! tasks are spawned through an [`Executor`]. Tokio is supported by
+//! default, while the `smol` and `async-std` features provide executors
+//! for those runtimes. Since the fetch and storage layers are built on
+//! tokio IO, timers and filesystem, the latter run their tasks, and the
+//! crawls awaiting them, within a tokio compatibility layer.
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt, RemoteHandle};
+
+/// Spawns futures in the background
+pub trait Executor: Send + Sync {
+    /// Run `future` to completion in the background
+    fn spawn_boxed(&self, future: BoxFuture<'static, ()>);
+}
+
+/// The handle of a spawned task, resolving to its output or, if the
+/// task panicked, to the panic payload. Dropping the handle cancels
+/// the task.
+pub type TaskHandle<T> = RemoteHandle<std::thread::Result<T>>;
+
+/// The message of the panic with `payload`, as passed to `panic!`
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic payload".to_string(),
+        },
+    }
+}
+
+/// Spawn `future` on `executor`
+pub fn spawn<T, F>(executor: &dyn Executor, future: F) -> TaskHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let (remote, handle) = AssertUnwindSafe(future).catch_unwind().remote_handle();
+    executor.spawn_boxed(remote.boxed());
+    handle
+}
+
+/// Run `future`, built on the tokio IO of the fetch and storage layers,
+/// under any runtime: with the `smol` or `async-std` feature, within a
+/// tokio compatibility layer, which enters the ambient tokio runtime, if
+/// any, or a global one
+pub fn compat<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    #[cfg(any(feature = "smol", feature = "async-std"))]
+    return async_compat::Compat::new(future);
+    #[cfg(not(any(feature = "smol", feature = "async-std")))]
+    return future;
+}
+
+/// The executor of the runtime selected by the enabled features,
+/// preferring tokio
+pub fn default_executor() -> Arc<dyn Executor> {
+    #[cfg(feature = "tokio")]
+    return Arc::new(TokioExecutor);
+    #[cfg(all(not(feature = "tokio"), feature = "smol"))]
+    return Arc::new(SmolExecutor);
+    #[cfg(all(not(feature = "tokio"), not(feature = "smol"), feature = "async-std"))]
+    return Arc::new(AsyncStdExecutor);
+}
+
+#[cfg(not(any(feature = "tokio", feature = "smol", feature = "async-std")))]
+compile_error!("one of the `tokio`, `smol` or `async-std` features must be enabled");
+
+/// Spawns on the ambient tokio runtime
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioExecutor;
+
+#[cfg(feature = "tokio")]
+impl Executor for TokioExecutor {
+    fn spawn_boxed(&self, future: BoxFuture<'static, ()>) {
+        tokio::spawn(future);
+    }
+}
+
+/// Spawns on the global smol executor
+#[cfg(feature = "smol")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmolExecutor;
+
+#[cfg(feature = "smol")]
+impl Executor for SmolExecutor {
+    fn spawn_boxed(&self, future: BoxFuture<'static, ()>) {
+        smol::spawn(async_compat::Compat::new(future)).detach();
+    }
+}
+
+/// Spawns on the global async-std executor
+#[cfg(feature = "async-std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdExecutor;
+
+#[cfg(feature = "async-std")]
+impl Executor for AsyncStdExecutor {
+    fn spawn_boxed(&self, future: BoxFuture<'static, ()>) {
+        async_std::task::spawn(async_compat::Compat::new(future));
+    }
+}
@@ -0,0 +1,509 @@
+//! Packing of a storage directory into a single archive.
+//!
+//! The archive holds the manifest and every page it references.
+//! Entries are written in path order with fixed timestamps, so
+//! exporting the same crawl twice yields identical archives.
+//!
+//! An MHTML export instead is a directory of single-file documents that
+//! browsers open, one per HTML page, named by the hash of its url. Every
+//! document holds the latest version of its page, followed by the
+//! subresources of the page stored by the crawl, e.g. its images or
+//! stylesheets, inlined as the other parts.
+//!
+//! A delta export against the manifest of a baseline crawl only holds
+//! the pages that were added or changed since the baseline, along with
+//! a manifest of their entries and a [`DELTA_FILE`] listing the added,
+//! changed and removed urls, so that downstream indexers only process
+//! what changed between two crawls.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use base64::Engine;
+
+use crate::diff::CrawlDiff;
+use crate::error::Result;
+use crate::manifest::{content_hash, Manifest, ManifestEntry, MANIFEST_FILE};
+use crate::parse::Document;
+use crate::Storage;
+
+/// Name of the file listing the added, changed and removed
+/// urls of a delta export
+pub const DELTA_FILE: &str = "delta.json";
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// The boundary between the parts of an MHTML archive
+const MHTML_BOUNDARY: &str = "----=_webcrawler_part";
+
+/// Length of the base64 lines of an MHTML part
+const MHTML_LINE: usize = 76;
+
+/// The supported archive formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarZst,
+    Mhtml,
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "zip" => Ok(Self::Zip),
+            "tar.zst" => Ok(Self::TarZst),
+            "mhtml" => Ok(Self::Mhtml),
+            other => Err(format!(
+                "unsupported archive format `{}`, expected `zip`, `tar.zst` or `mhtml`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Zip => write!(f, "zip"),
+            Self::TarZst => write!(f, "tar.zst"),
+            Self::Mhtml => write!(f, "mhtml"),
+        }
+    }
+}
+
+/// Export the crawl stored in `dir` to an archive at `output`, or
+/// to a directory of MHTML documents at `output`.
+///
+/// Returns the number of archived files.
+pub async fn export(dir: &Path, output: &Path, format: ArchiveFormat) -> Result<usize> {
+    let manifest = Manifest::load(dir).await?;
+    if format == ArchiveFormat::Mhtml {
+        let latest = manifest.latest();
+        let documents = mhtml_documents(dir, latest.values().copied(), &latest).await?;
+        return write_dir(documents, output).await;
+    }
+    let mut files: Vec<ArchiveFile> = manifest
+        .entries()
+        .iter()
+        .map(|entry| ArchiveFile::Stored(entry.path.clone()))
+        .collect();
+    files.push(ArchiveFile::Stored(PathBuf::from(MANIFEST_FILE)));
+    write_archive(dir, files, output, format).await
+}
+
+/// Export the crawl stored in `dir` to a zip archive at `output`
+/// of its MHTML documents, e.g. for a single download.
+///
+/// Returns the number of documents.
+pub async fn export_mhtml_zip(dir: &Path, output: &Path) -> Result<usize> {
+    let manifest = Manifest::load(dir).await?;
+    let latest = manifest.latest();
+    let documents = mhtml_documents(dir, latest.values().copied(), &latest).await?;
+    write_archive(dir, documents, output, ArchiveFormat::Zip).await
+}
+
+/// Export to an archive at `output` the pages of the crawl stored
+/// in `dir` that were added or changed since the `baseline` crawl,
+/// along with their manifest and the [`DELTA_FILE`]. The MHTML
+/// documents of the pages are written to a directory at `output`,
+/// along with the [`DELTA_FILE`].
+///
+/// Returns the differences between the crawls.
+pub async fn export_delta(
+    dir: &Path,
+    baseline: &Manifest,
+    output: &Path,
+    format: ArchiveFormat,
+) -> Result<CrawlDiff> {
+    let manifest = Manifest::load(dir).await?;
+    let diff = CrawlDiff::between(baseline, &manifest);
+    let latest = manifest.latest();
+    let entries: Vec<ManifestEntry> = diff
+        .added
+        .iter()
+        .chain(diff.changed.iter().map(|page| &page.url))
+        .filter_map(|url| latest.get(url).map(|entry| (*entry).clone()))
+        .collect();
+    if format == ArchiveFormat::Mhtml {
+        let mut documents = mhtml_documents(dir, entries.iter(), &latest).await?;
+        documents.push(ArchiveFile::Generated(
+            PathBuf::from(DELTA_FILE),
+            serde_json::to_vec_pretty(&diff)?,
+        ));
+        write_dir(documents, output).await?;
+        return Ok(diff);
+    }
+    let mut files: Vec<ArchiveFile> = entries
+        .iter()
+        .map(|entry| ArchiveFile::Stored(entry.path.clone()))
+        .collect();
+    let mut delta_manifest = Vec::new();
+    for entry in &entries {
+        serde_json::to_writer(&mut delta_manifest, entry)?;
+        delta_manifest.push(b'\n');
+    }
+    files.push(ArchiveFile::Generated(
+        PathBuf::from(MANIFEST_FILE),
+        delta_manifest,
+    ));
+    files.push(ArchiveFile::Generated(
+        PathBuf::from(DELTA_FILE),
+        serde_json::to_vec_pretty(&diff)?,
+    ));
+    write_archive(dir, files, output, format).await?;
+    Ok(diff)
+}
+
+/// A file of an archive
+enum ArchiveFile {
+    /// A file of the storage directory
+    Stored(PathBuf),
+    /// A file generated for the archive, with its content
+    Generated(PathBuf, Vec<u8>),
+}
+
+impl ArchiveFile {
+    fn path(&self) -> &Path {
+        match self {
+            Self::Stored(path) | Self::Generated(path, _) => path,
+        }
+    }
+
+    fn content(&self, dir: &Path) -> Result<Vec<u8>> {
+        match self {
+            Self::Stored(path) => Ok(std::fs::read(dir.join(path))?),
+            Self::Generated(_, content) => Ok(content.clone()),
+        }
+    }
+}
+
+/// Write `files` in path order to an archive at `output`,
+/// returning their number
+async fn write_archive(
+    dir: &Path,
+    mut files: Vec<ArchiveFile>,
+    output: &Path,
+    format: ArchiveFormat,
+) -> Result<usize> {
+    files.sort_by(|a, b| a.path().cmp(b.path()));
+    files.dedup_by(|a, b| a.path() == b.path());
+
+    let dir = dir.to_path_buf();
+    let output = output.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let archive = File::create(output)?;
+        match format {
+            ArchiveFormat::Zip => write_zip(&dir, &files, archive)?,
+            ArchiveFormat::TarZst => write_tar_zst(&dir, &files, archive)?,
+            ArchiveFormat::Mhtml => unreachable!("MHTML documents are written to a directory"),
+        }
+        Ok(files.len())
+    })
+    .await?
+}
+
+fn write_zip(dir: &Path, files: &[ArchiveFile], archive: File) -> Result<()> {
+    let mut zip = zip::ZipWriter::new(archive);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default());
+    for file in files {
+        zip.start_file(archive_name(file.path()), options)?;
+        zip.write_all(&file.content(dir)?)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn write_tar_zst(dir: &Path, files: &[ArchiveFile], archive: File) -> Result<()> {
+    let encoder = zstd::Encoder::new(archive, ZSTD_LEVEL)?;
+    let mut tar = tar::Builder::new(encoder);
+    tar.mode(tar::HeaderMode::Deterministic);
+    for file in files {
+        let content = file.content(dir)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_cksum();
+        tar.append_data(&mut header, archive_name(file.path()), content.as_slice())?;
+    }
+    tar.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
+
+/// Write the generated `files` to the directory at `output`,
+/// returning their number
+async fn write_dir(files: Vec<ArchiveFile>, output: &Path) -> Result<usize> {
+    tokio::fs::create_dir_all(output).await?;
+    for file in &files {
+        if let ArchiveFile::Generated(path, content) = file {
+            tokio::fs::write(output.join(path), content).await?;
+        }
+    }
+    Ok(files.len())
+}
+
+/// The MHTML documents of the HTML `pages` of the crawl stored in
+/// `dir`, inlining the subresources of every page found in `latest`
+async fn mhtml_documents<'a>(
+    dir: &Path,
+    pages: impl Iterator<Item = &'a ManifestEntry>,
+    latest: &HashMap<&url::Url, &ManifestEntry>,
+) -> Result<Vec<ArchiveFile>> {
+    let storage = Storage::new(dir.to_path_buf());
+    let mut pages: Vec<&ManifestEntry> = pages.filter(|entry| is_html(entry)).collect();
+    pages.sort_by(|a, b| a.url.cmp(&b.url));
+    let mut documents = Vec::with_capacity(pages.len());
+    for page in pages {
+        let content = storage.read(page).await?;
+        let subresources = Document::parse(&content).with_url(&page.url).subresources();
+        let mut mhtml = format!(
+            "From: <Saved by {}>\r\nSubject: {}\r\nMIME-Version: 1.0\r\n\
+             Content-Type: multipart/related; type=\"text/html\"; boundary=\"{}\"\r\n\r\n",
+            env!("CARGO_PKG_NAME"),
+            page.url,
+            MHTML_BOUNDARY
+        );
+        push_part(&mut mhtml, page, &content, "text/html");
+        for url in &subresources {
+            if let Some(entry) = latest.get(url).filter(|entry| entry.url != page.url) {
+                let content = storage.read(entry).await?;
+                push_part(&mut mhtml, entry, &content, "application/octet-stream");
+            }
+        }
+        mhtml.push_str(&format!("--{}--\r\n", MHTML_BOUNDARY));
+        let name = format!("{}.mhtml", content_hash(page.url.as_str().as_bytes()));
+        documents.push(ArchiveFile::Generated(
+            PathBuf::from(name),
+            mhtml.into_bytes(),
+        ));
+    }
+    Ok(documents)
+}
+
+/// Append the part of the page or subresource of `entry`, whose stored
+/// content is `content`, to `mhtml`, of its recorded media type if any
+/// or else `default_type`
+fn push_part(mhtml: &mut String, entry: &ManifestEntry, content: &[u8], default_type: &str) {
+    let content_type = entry
+        .headers
+        .get("content-type")
+        .map_or(default_type, String::as_str);
+    mhtml.push_str(&format!(
+        "--{}\r\nContent-Type: {}\r\nContent-Transfer-Encoding: base64\r\n\
+         Content-Location: {}\r\n\r\n",
+        MHTML_BOUNDARY, content_type, entry.url
+    ));
+    let content = base64::engine::general_purpose::STANDARD.encode(content);
+    for line in content.as_bytes().chunks(MHTML_LINE) {
+        mhtml.push_str(std::str::from_utf8(line).unwrap());
+        mhtml.push_str("\r\n");
+    }
+}
+
+/// Whether the page of `entry` is HTML: not binary, and served as
+/// HTML if its media type is recorded
+fn is_html(entry: &ManifestEntry) -> bool {
+    !entry.binary
+        && entry
+            .headers
+            .get("content-type")
+            .is_none_or(|t| t.starts_with("text/html") || t.starts_with("application/xhtml+xml"))
+}
+
+/// The name of an archived file, with `/` separators on every platform
+fn archive_name(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, CONTENT_TYPE};
+
+    use super::*;
+    use crate::config::HeaderCapture;
+    use crate::storage::PageAnnotations;
+
+    /// A storage recording the media types of its pages
+    async fn storage(dir: &Path) -> Storage {
+        let storage = Storage::new(dir.to_path_buf())
+            .with_headers(HeaderCapture::Names(vec!["content-type".to_string()]));
+        storage.setup().await.unwrap();
+        storage
+    }
+
+    async fn store(storage: &Storage, url: &str, content_type: &str, body: &[u8]) {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+        let annotations = PageAnnotations::new(url.parse().unwrap()).with_headers(headers);
+        storage.serialize(body, &annotations).await.unwrap();
+    }
+
+    fn document_name(url: &str) -> String {
+        format!("{}.mhtml", content_hash(url.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn mhtml_exports_a_document_per_page_with_its_subresources() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        let page = br#"<html><head><link rel="stylesheet" href="style.css"></head>
+            <body><img src="logo.png"><a href="b.html">B</a></body></html>"#;
+        store(&storage, "https://example.com/a.html", "text/html", page).await;
+        store(
+            &storage,
+            "https://example.com/b.html",
+            "text/html",
+            b"<p>B</p>",
+        )
+        .await;
+        store(
+            &storage,
+            "https://example.com/logo.png",
+            "image/png",
+            b"\x89PNG\0\0",
+        )
+        .await;
+
+        let output = dir.path().join("pages");
+        assert_eq!(
+            export(dir.path(), &output, ArchiveFormat::Mhtml)
+                .await
+                .unwrap(),
+            2
+        );
+        let mut names: Vec<_> = std::fs::read_dir(&output)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        let mut expected = vec![
+            document_name("https://example.com/a.html"),
+            document_name("https://example.com/b.html"),
+        ];
+        expected.sort();
+        assert_eq!(names, expected);
+
+        let document =
+            std::fs::read_to_string(output.join(document_name("https://example.com/a.html")))
+                .unwrap();
+        let locations: Vec<&str> = document
+            .lines()
+            .filter_map(|line| line.strip_prefix("Content-Location: "))
+            .collect();
+        assert_eq!(
+            locations,
+            ["https://example.com/a.html", "https://example.com/logo.png"]
+        );
+        assert!(document.starts_with("From: <Saved by webcrawler>\r\n"));
+        assert!(document.contains("Subject: https://example.com/a.html\r\n"));
+        assert!(document.contains("Content-Type: image/png\r\n"));
+        assert!(document.ends_with(&format!("--{}--\r\n", MHTML_BOUNDARY)));
+        let b = std::fs::read_to_string(output.join(document_name("https://example.com/b.html")))
+            .unwrap();
+        assert_eq!(b.matches("Content-Location: ").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn mhtml_documents_are_zipped_for_a_single_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        store(&storage, "https://example.com/", "text/html", b"<p>A</p>").await;
+        let output = dir.path().join("pages.mhtml.zip");
+        assert_eq!(export_mhtml_zip(dir.path(), &output).await.unwrap(), 1);
+        let zip = zip::ZipArchive::new(File::open(output).unwrap()).unwrap();
+        let names: Vec<_> = zip.file_names().collect();
+        assert_eq!(names, [document_name("https://example.com/").as_str()]);
+    }
+
+    async fn crawl(dir: &Path) {
+        let storage = storage(dir).await;
+        store(
+            &storage,
+            "https://example.com/b.html",
+            "text/html",
+            b"<p>B</p>",
+        )
+        .await;
+        store(
+            &storage,
+            "https://example.com/a.html",
+            "text/html",
+            b"<p>A</p>",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn archives_hold_the_pages_and_manifest_in_path_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let crawl_dir = dir.path().join("crawl");
+        crawl(&crawl_dir).await;
+
+        let output = dir.path().join("crawl.zip");
+        assert_eq!(
+            export(&crawl_dir, &output, ArchiveFormat::Zip)
+                .await
+                .unwrap(),
+            3
+        );
+        let zip = zip::ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let names: Vec<_> = zip.file_names().collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        assert!(names.contains(&MANIFEST_FILE));
+
+        let output = dir.path().join("crawl.tar.zst");
+        assert_eq!(
+            export(&crawl_dir, &output, ArchiveFormat::TarZst)
+                .await
+                .unwrap(),
+            3
+        );
+        let decoder = zstd::Decoder::new(File::open(&output).unwrap()).unwrap();
+        let mut tar = tar::Archive::new(decoder);
+        let tar_names: Vec<_> = tar
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(tar_names, sorted);
+    }
+
+    #[tokio::test]
+    async fn exporting_a_crawl_twice_yields_identical_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let crawl_dir = dir.path().join("crawl");
+        crawl(&crawl_dir).await;
+        for (format, name) in [
+            (ArchiveFormat::Zip, "zip"),
+            (ArchiveFormat::TarZst, "tar.zst"),
+        ] {
+            let first = dir.path().join(format!("first.{}", name));
+            let second = dir.path().join(format!("second.{}", name));
+            export(&crawl_dir, &first, format).await.unwrap();
+            export(&crawl_dir, &second, format).await.unwrap();
+            assert_eq!(
+                std::fs::read(first).unwrap(),
+                std::fs::read(second).unwrap()
+            );
+        }
+    }
+}
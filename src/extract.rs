@@ -0,0 +1,109 @@
+//! Optional extractors of page data.
+//!
+//! An [`Extractor`] derives a record from every visited page. The records
+//! of each extractor are appended to its own JSONL report in the storage
+//! root, one line per page.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::frontier::SeedMetadata;
+use crate::page::CrawledPage;
+use crate::parse::{Document, Form, IntegrityRef};
+
+/// Derives a report record from a page
+pub trait Extractor: Send + Sync {
+    /// Name of the report file in the storage root
+    fn report_file(&self) -> &str;
+
+    /// The record of the page at `url`, if there is anything to report
+    fn extract(&self, url: &url::Url, document: &Document) -> Option<Value>;
+
+    /// The record of the fetched `page`, for extractors that need more
+    /// of the response than its url, e.g. its headers
+    fn extract_page(&self, page: &CrawledPage, document: &Document) -> Option<Value> {
+        self.extract(&page.url, document)
+    }
+}
+
+/// A record produced by an extractor
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    pub report_file: String,
+    pub record: Value,
+}
+
+impl Extraction {
+    /// Record the `metadata` of the seed of the page, if any,
+    /// in a `metadata` field of an object record
+    pub fn with_metadata(mut self, metadata: &SeedMetadata) -> Self {
+        if let (Value::Object(record), false) = (&mut self.record, metadata.is_empty()) {
+            record.insert("metadata".to_string(), serde_json::json!(metadata));
+        }
+        self
+    }
+}
+
+/// Name of the forms report
+pub const FORMS_FILE: &str = "forms.jsonl";
+
+/// The forms found in a page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageForms {
+    pub url: url::Url,
+    pub forms: Vec<Form>,
+}
+
+/// Records the forms of the pages, without submitting them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FormExtractor;
+
+impl Extractor for FormExtractor {
+    fn report_file(&self) -> &str {
+        FORMS_FILE
+    }
+
+    fn extract(&self, url: &url::Url, document: &Document) -> Option<Value> {
+        let forms = document.forms();
+        if forms.is_empty() {
+            return None;
+        }
+        let record = PageForms {
+            url: url.clone(),
+            forms,
+        };
+        serde_json::to_value(record).ok()
+    }
+}
+
+/// Name of the Subresource Integrity report
+pub const INTEGRITY_FILE: &str = "integrity.jsonl";
+
+/// The subresources of a page declaring their expected digests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageIntegrity {
+    pub url: url::Url,
+    pub subresources: Vec<IntegrityRef>,
+}
+
+/// Records the `integrity` attributes of the scripts and stylesheets
+/// of the pages
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IntegrityExtractor;
+
+impl Extractor for IntegrityExtractor {
+    fn report_file(&self) -> &str {
+        INTEGRITY_FILE
+    }
+
+    fn extract(&self, url: &url::Url, document: &Document) -> Option<Value> {
+        let subresources = document.integrity();
+        if subresources.is_empty() {
+            return None;
+        }
+        let record = PageIntegrity {
+            url: url.clone(),
+            subresources,
+        };
+        serde_json::to_value(record).ok()
+    }
+}
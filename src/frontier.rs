@@ -0,0 +1,601 @@
+//! The urls pending a visit.
+//!
+//! The frontier is kept by the crawler itself and is independent
+//! of how and where the crawl tasks are executed.
+//!
+//! Urls are taken in breadth-first order, unless they are given a
+//! priority: urls of higher priority are taken first, and urls of
+//! equal priority in the order they were queued. A url is pending
+//! once: queued again, it only takes the higher of its priorities.
+//!
+//! The frontier is unbounded by default. Given a capacity, the urls
+//! queued beyond it are handled by a [`FrontierOverflow`] strategy.
+//! Urls exceeding the [`UrlLimits`] of the frontier are rejected when
+//! queued, as a guard against pathological link generators. Given a
+//! [`Sampler`], the discovered links it does not draw are not queued.
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sample::Sampler;
+
+/// The metadata given to a seed, e.g. labels or a category, carried
+/// by every page reached from it
+pub type SeedMetadata = BTreeMap<String, serde_json::Value>;
+
+/// Context for spawning a crawl task
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskContext {
+    pub url: url::Url,
+    /// The number of links followed from a seed to reach `url`
+    pub depth: usize,
+    /// The page `url` was discovered in, if not a seed
+    pub referrer: Option<url::Url>,
+    /// Urls of higher priority are visited first
+    #[serde(default, skip_serializing_if = "is_default_priority")]
+    pub priority: i32,
+    /// The metadata of the seed `url` was reached from
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: SeedMetadata,
+}
+
+fn is_default_priority(priority: &i32) -> bool {
+    *priority == 0
+}
+
+impl TaskContext {
+    /// The context of a seed url
+    pub fn seed(url: url::Url) -> Self {
+        Self {
+            url,
+            depth: 0,
+            referrer: None,
+            priority: 0,
+            metadata: SeedMetadata::new(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: SeedMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// An entry of the queue, ordered by priority and then by arrival
+#[derive(Debug, Clone)]
+struct Pending {
+    sequence: Reverse<u64>,
+    context: TaskContext,
+}
+
+impl Pending {
+    fn key(&self) -> (i32, Reverse<u64>) {
+        (self.context.priority, self.sequence)
+    }
+
+    /// The entry of `key`, for looking it up in the queue
+    fn probe(key: (i32, Reverse<u64>), url: url::Url) -> Self {
+        let (priority, sequence) = key;
+        Self {
+            sequence,
+            context: TaskContext {
+                priority,
+                ..TaskContext::seed(url)
+            },
+        }
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// What to do with the urls queued in a full frontier
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FrontierOverflow {
+    /// Drop the url of lowest priority, the most recently queued among equals
+    #[default]
+    DropLowest,
+    /// Drop the newly queued url
+    DropNew,
+    /// Set the newly queued url aside, to be spilled to the disk by the
+    /// crawler and queued again once the frontier is drained
+    Spill,
+}
+
+impl FromStr for FrontierOverflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "drop-lowest" => Ok(Self::DropLowest),
+            "drop-new" => Ok(Self::DropNew),
+            "spill" => Ok(Self::Spill),
+            other => Err(format!(
+                "unsupported frontier overflow `{}`, expected `drop-lowest`, `drop-new` or `spill`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FrontierOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DropLowest => write!(f, "drop-lowest"),
+            Self::DropNew => write!(f, "drop-new"),
+            Self::Spill => write!(f, "spill"),
+        }
+    }
+}
+
+/// Sanity limits on the urls queued for a visit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UrlLimits {
+    /// Max number of characters of a url
+    pub max_length: Option<usize>,
+    /// Max number of query parameters of a url
+    pub max_query_params: Option<usize>,
+    /// Max number of path segments of a url
+    pub max_path_depth: Option<usize>,
+}
+
+impl UrlLimits {
+    /// The limit exceeded by `url`, if any
+    pub fn check(&self, url: &url::Url) -> Option<UrlRejection> {
+        if self.max_length.is_some_and(|max| url.as_str().len() > max) {
+            return Some(UrlRejection::Length);
+        }
+        if self
+            .max_query_params
+            .is_some_and(|max| url.query_pairs().count() > max)
+        {
+            return Some(UrlRejection::QueryParams);
+        }
+        let depth = || {
+            url.path_segments()
+                .map_or(0, |segments| segments.filter(|s| !s.is_empty()).count())
+        };
+        if self.max_path_depth.is_some_and(|max| depth() > max) {
+            return Some(UrlRejection::PathDepth);
+        }
+        None
+    }
+}
+
+/// The limit a rejected url exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlRejection {
+    Length,
+    QueryParams,
+    PathDepth,
+}
+
+/// The numbers of queued urls rejected by the [`UrlLimits`] of a frontier,
+/// by exceeded limit
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RejectedUrls {
+    pub length: usize,
+    pub query_params: usize,
+    pub path_depth: usize,
+}
+
+impl RejectedUrls {
+    pub fn record(&mut self, rejection: UrlRejection) {
+        match rejection {
+            UrlRejection::Length => self.length += 1,
+            UrlRejection::QueryParams => self.query_params += 1,
+            UrlRejection::PathDepth => self.path_depth += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.length + self.query_params + self.path_depth
+    }
+}
+
+/// A queue of urls that skips visited ones
+#[derive(Debug, Clone, Default)]
+pub struct Frontier {
+    pending: BTreeSet<Pending>,
+    /// The key of the pending entry of every queued url
+    queued: HashMap<url::Url, (i32, Reverse<u64>)>,
+    sequence: u64,
+    visited: HashSet<url::Url>,
+    capacity: Option<usize>,
+    overflow: FrontierOverflow,
+    spilled: Vec<TaskContext>,
+    dropped: usize,
+    url_limits: UrlLimits,
+    rejected: RejectedUrls,
+    sampler: Option<Sampler>,
+    /// The draws of the discovered urls left out of the sample
+    sampled_out: HashSet<u64>,
+}
+
+impl Frontier {
+    /// A frontier of already `visited` urls, with `pending` ones to visit
+    pub fn new(pending: impl IntoIterator<Item = TaskContext>, visited: HashSet<url::Url>) -> Self {
+        let mut frontier = Self {
+            visited,
+            ..Self::default()
+        };
+        for context in pending {
+            frontier.push(context);
+        }
+        frontier
+    }
+
+    /// Hold at most `capacity` queued entries, handling the
+    /// urls queued beyond it according to `overflow`
+    pub fn with_capacity(mut self, capacity: usize, overflow: FrontierOverflow) -> Self {
+        self.capacity = Some(capacity);
+        self.overflow = overflow;
+        self
+    }
+
+    /// Reject the urls exceeding `url_limits` when queued
+    pub fn with_url_limits(mut self, url_limits: UrlLimits) -> Self {
+        self.url_limits = url_limits;
+        self
+    }
+
+    /// Queue only the discovered links drawn by `sampler`, if any
+    pub fn with_sampler(mut self, sampler: Option<Sampler>) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Queue `context` unless its url is already visited or exceeds the
+    /// url limits. A url already pending is queued again in its place
+    /// with a higher priority only.
+    pub fn push(&mut self, context: TaskContext) {
+        if self.visited.contains(&context.url) {
+            return;
+        }
+        if let Some(rejection) = self.url_limits.check(&context.url) {
+            tracing::debug!("  -> Rejecting {} ({:?})", context.url, rejection);
+            self.rejected.record(rejection);
+            return;
+        }
+        if let Some(&key) = self.queued.get(&context.url) {
+            if context.priority <= key.0 {
+                return;
+            }
+            self.pending
+                .remove(&Pending::probe(key, context.url.clone()));
+            self.insert(context);
+            return;
+        }
+        let full = self
+            .capacity
+            .is_some_and(|capacity| self.pending.len() >= capacity);
+        if full {
+            match self.overflow {
+                FrontierOverflow::DropLowest => {}
+                FrontierOverflow::DropNew => {
+                    self.dropped += 1;
+                    return;
+                }
+                FrontierOverflow::Spill => {
+                    self.spilled.push(context);
+                    return;
+                }
+            }
+        }
+        self.insert(context);
+        if full {
+            if let Some(lowest) = self.pending.pop_first() {
+                self.queued.remove(&lowest.context.url);
+            }
+            self.dropped += 1;
+        }
+    }
+
+    fn insert(&mut self, context: TaskContext) {
+        let pending = Pending {
+            sequence: Reverse(self.sequence),
+            context,
+        };
+        self.sequence += 1;
+        self.queued
+            .insert(pending.context.url.clone(), pending.key());
+        self.pending.insert(pending);
+    }
+
+    /// Remove the pending entry of `url`, if any
+    fn unqueue(&mut self, url: &url::Url) {
+        if let Some(key) = self.queued.remove(url) {
+            self.pending.remove(&Pending::probe(key, url.clone()));
+        }
+    }
+
+    /// Queue `context` in place of the pending entry of its url
+    pub fn replace(&mut self, context: TaskContext) {
+        self.unqueue(&context.url);
+        self.push(context);
+    }
+
+    /// Queue the `links` discovered in the page of `referrer`, found at
+    /// `depth` from a seed of `metadata`, with their `priorities` if any
+    pub fn discover(
+        &mut self,
+        referrer: &url::Url,
+        depth: usize,
+        metadata: &SeedMetadata,
+        links: Vec<url::Url>,
+        priorities: &HashMap<url::Url, i32>,
+    ) {
+        for url in links {
+            if let Some(sampler) = &self.sampler {
+                let draw = sampler.draw(&url);
+                if !sampler.samples(&url, depth + 1) {
+                    if !self.visited.contains(&url) {
+                        self.sampled_out.insert(draw);
+                    }
+                    continue;
+                }
+                self.sampled_out.remove(&draw);
+            }
+            let priority = priorities.get(&url).copied().unwrap_or_default();
+            self.push(TaskContext {
+                url,
+                depth: depth + 1,
+                referrer: Some(referrer.clone()),
+                priority,
+                metadata: metadata.clone(),
+            });
+        }
+    }
+
+    /// Take the next url not yet visited, marking it as visited
+    pub fn pop(&mut self) -> Option<TaskContext> {
+        let Pending { context, .. } = self.pending.pop_last()?;
+        self.queued.remove(&context.url);
+        self.visited.insert(context.url.clone());
+        Some(context)
+    }
+
+    /// Mark `url` as visited, so that it is never taken
+    pub fn skip(&mut self, url: url::Url) {
+        self.unqueue(&url);
+        self.visited.insert(url);
+    }
+
+    pub fn is_visited(&self, url: &url::Url) -> bool {
+        self.visited.contains(url)
+    }
+
+    /// The number of pending urls
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// The number of urls dropped from a full frontier
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// The number of discovered urls left out of the sample, and
+    /// never drawn at a depth of a higher probability
+    pub fn sampled_out(&self) -> usize {
+        self.sampled_out.len()
+    }
+
+    /// The numbers of urls rejected by the url limits
+    pub fn rejected(&self) -> &RejectedUrls {
+        &self.rejected
+    }
+
+    /// Take the entries set aside by a full frontier with
+    /// the [`Spill`](FrontierOverflow::Spill) strategy
+    pub fn take_spilled(&mut self) -> Vec<TaskContext> {
+        std::mem::take(&mut self.spilled)
+    }
+
+    /// The distinct entries pending a visit, in the order they would be
+    /// taken, followed by the entries set aside by a full frontier
+    pub fn snapshot(&self) -> Vec<TaskContext> {
+        let mut seen = HashSet::new();
+        self.pending
+            .iter()
+            .rev()
+            .map(|pending| &pending.context)
+            .chain(&self.spilled)
+            .filter(|context| {
+                !self.visited.contains(&context.url) && seen.insert(context.url.clone())
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Split into the sorted visited urls, and the distinct
+    /// pending entries in the order they would be taken
+    pub fn into_parts(self) -> (Vec<url::Url>, Vec<TaskContext>) {
+        let pending = self.snapshot();
+        let mut visited: Vec<_> = self.visited.into_iter().collect();
+        visited.sort();
+        (visited, pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(path: &str) -> url::Url {
+        url::Url::parse("https://example.com/")
+            .unwrap()
+            .join(path)
+            .unwrap()
+    }
+
+    fn context(path: &str, priority: i32) -> TaskContext {
+        TaskContext {
+            priority,
+            ..TaskContext::seed(url(path))
+        }
+    }
+
+    fn drain(frontier: &mut Frontier) -> Vec<String> {
+        std::iter::from_fn(|| frontier.pop())
+            .map(|context| context.url.path().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn urls_are_taken_by_priority_then_in_order() {
+        let mut frontier = Frontier::new(
+            [
+                context("a", 0),
+                context("b", 1),
+                context("c", 0),
+                context("d", 1),
+                context("a", 2),
+            ],
+            HashSet::new(),
+        );
+        assert_eq!(drain(&mut frontier), ["/a", "/b", "/d", "/c"]);
+    }
+
+    #[test]
+    fn visited_urls_are_not_taken() {
+        let mut frontier = Frontier::new([context("a", 0)], HashSet::from([url("b")]));
+        frontier.push(context("b", 0));
+        frontier.skip(url("a"));
+        frontier.push(context("c", 0));
+        assert_eq!(drain(&mut frontier), ["/c"]);
+    }
+
+    #[test]
+    fn a_full_frontier_drops_the_lowest_urls() {
+        let mut frontier = Frontier::default().with_capacity(2, FrontierOverflow::DropLowest);
+        frontier.push(context("a", 0));
+        frontier.push(context("b", 1));
+        frontier.push(context("c", 2));
+        frontier.push(context("d", 0));
+        assert_eq!(frontier.dropped(), 2);
+        assert_eq!(drain(&mut frontier), ["/c", "/b"]);
+    }
+
+    #[test]
+    fn a_full_frontier_drops_the_new_urls() {
+        let mut frontier = Frontier::default().with_capacity(2, FrontierOverflow::DropNew);
+        frontier.push(context("a", 0));
+        frontier.push(context("b", 0));
+        frontier.push(context("c", 2));
+        assert_eq!(frontier.dropped(), 1);
+        assert_eq!(drain(&mut frontier), ["/a", "/b"]);
+    }
+
+    #[test]
+    fn a_full_frontier_spills_the_new_urls() {
+        let mut frontier = Frontier::default().with_capacity(1, FrontierOverflow::Spill);
+        frontier.push(context("a", 0));
+        frontier.push(context("b", 0));
+        frontier.push(context("c", 0));
+        assert_eq!(frontier.dropped(), 0);
+        let paths = |contexts: Vec<TaskContext>| -> Vec<String> {
+            contexts.iter().map(|c| c.url.path().to_string()).collect()
+        };
+        assert_eq!(paths(frontier.snapshot()), ["/a", "/b", "/c"]);
+        assert_eq!(paths(frontier.take_spilled()), ["/b", "/c"]);
+        assert_eq!(drain(&mut frontier), ["/a"]);
+    }
+
+    #[test]
+    fn pending_urls_are_queued_once() {
+        let mut frontier = Frontier::default();
+        frontier.push(context("a", 0));
+        frontier.push(context("b", 0));
+        frontier.push(context("a", 0));
+        frontier.push(context("b", 1));
+        assert_eq!(frontier.len(), 2);
+        assert_eq!(drain(&mut frontier), ["/b", "/a"]);
+    }
+
+    #[test]
+    fn duplicates_do_not_overflow_a_full_frontier() {
+        for overflow in [FrontierOverflow::DropLowest, FrontierOverflow::DropNew] {
+            let mut frontier = Frontier::default().with_capacity(2, overflow);
+            frontier.push(context("a", 0));
+            frontier.push(context("b", 0));
+            frontier.push(context("a", 0));
+            frontier.push(context("b", 3));
+            assert_eq!(frontier.dropped(), 0, "{}", overflow);
+            assert_eq!(drain(&mut frontier), ["/b", "/a"]);
+        }
+        let mut frontier = Frontier::default().with_capacity(1, FrontierOverflow::Spill);
+        frontier.push(context("a", 0));
+        frontier.push(context("a", 0));
+        assert!(frontier.take_spilled().is_empty());
+    }
+
+    #[test]
+    fn skipped_urls_leave_the_queue() {
+        let mut frontier = Frontier::default().with_capacity(1, FrontierOverflow::DropNew);
+        frontier.push(context("a", 0));
+        frontier.skip(url("a"));
+        frontier.push(context("b", 0));
+        assert_eq!(frontier.dropped(), 0);
+        assert_eq!(drain(&mut frontier), ["/b"]);
+    }
+
+    #[test]
+    fn urls_over_the_limits_are_rejected() {
+        let mut frontier = Frontier::default().with_url_limits(UrlLimits {
+            max_length: Some(40),
+            max_query_params: Some(1),
+            max_path_depth: Some(2),
+        });
+        frontier.push(context("a/b", 0));
+        frontier.push(context("a/b/c", 0));
+        frontier.push(context("a?x=1&y=2", 0));
+        frontier.push(context(&"a".repeat(40), 0));
+        assert_eq!(
+            frontier.rejected(),
+            &RejectedUrls {
+                length: 1,
+                query_params: 1,
+                path_depth: 1,
+            }
+        );
+        assert_eq!(drain(&mut frontier), ["/a/b"]);
+    }
+
+    #[test]
+    fn overflow_strategies_round_trip() {
+        for overflow in [
+            FrontierOverflow::DropLowest,
+            FrontierOverflow::DropNew,
+            FrontierOverflow::Spill,
+        ] {
+            assert_eq!(overflow.to_string().parse(), Ok(overflow));
+        }
+        assert!("drop".parse::<FrontierOverflow>().is_err());
+    }
+}
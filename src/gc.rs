@@ -0,0 +1,251 @@
+//! Garbage collection of a storage directory.
+//!
+//! Long-lived storage directories, especially [versioned](crate::StorageLayout::Versioned)
+//! ones revisited by a monitor, accumulate files that no longer serve
+//! the crawl: pages left unrecorded by an interrupted write, temporary
+//! files and old versions of pages. The collection removes them and
+//! rewrites the manifest without the entries of the removed files.
+//!
+//! It must not run while a crawl or a monitor writes to the directory.
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::index::INDEX_FILE;
+use crate::manifest::{Manifest, ManifestEntry};
+
+/// The outcome of a garbage collection
+#[derive(Debug, Clone, Default)]
+pub struct GcSummary {
+    /// Number of page files recorded by no manifest entry
+    pub orphans: usize,
+    /// Number of temporary files and truncated pages
+    pub incomplete: usize,
+    /// Number of old versions beyond the kept ones
+    pub pruned: usize,
+    /// Number of manifest entries whose file is missing
+    pub dangling: usize,
+    /// Number of bytes of the removed files
+    pub bytes: u64,
+}
+
+/// Remove from the storage directory `root` the page files recorded by no
+/// manifest entry, the temporary files, the truncated pages and, if
+/// `keep_versions` is given, all but the most recent versions of each url.
+///
+/// The entries of the removed files, and those whose file is missing,
+/// are dropped from the manifest. With `dry_run` nothing is removed,
+/// while the summary reports what would be.
+pub async fn gc(root: &Path, keep_versions: Option<usize>, dry_run: bool) -> Result<GcSummary> {
+    let mut summary = GcSummary::default();
+    let manifest = Manifest::load(root).await?;
+    let mut sizes: HashMap<PathBuf, u64> = HashMap::new();
+    for path in files(root).await? {
+        let size = tokio::fs::metadata(root.join(&path)).await?.len();
+        sizes.insert(path, size);
+    }
+
+    // The most recent entry of each path tells the size of its file
+    let mut latest: HashMap<&Path, &ManifestEntry> = HashMap::new();
+    for entry in manifest.entries() {
+        match latest.get(entry.path.as_path()) {
+            Some(current) if current.fetched_at > entry.fetched_at => {}
+            _ => {
+                latest.insert(&entry.path, entry);
+            }
+        }
+    }
+    let mut removed: HashSet<PathBuf> = HashSet::new();
+    for (path, entry) in &latest {
+        if sizes
+            .get(*path)
+            .is_some_and(|size| *size != entry.stored_size() as u64)
+        {
+            tracing::debug!("  -> Removing truncated page {:?}", path);
+            summary.incomplete += 1;
+            removed.insert(path.to_path_buf());
+        }
+    }
+
+    let mut entries: Vec<ManifestEntry> = Vec::new();
+    let mut by_url: HashMap<&url::Url, Vec<&ManifestEntry>> = HashMap::new();
+    for entry in manifest.entries() {
+        by_url.entry(&entry.url).or_default().push(entry);
+    }
+    for versions in by_url.values_mut() {
+        versions.sort_by_key(|entry| std::cmp::Reverse(entry.fetched_at));
+        let mut kept = 0;
+        for entry in versions.iter() {
+            if removed.contains(&entry.path) {
+                continue;
+            }
+            if !sizes.contains_key(&entry.path) {
+                summary.dangling += 1;
+                continue;
+            }
+            if keep_versions.is_some_and(|keep| kept >= keep) {
+                summary.pruned += 1;
+                continue;
+            }
+            kept += 1;
+            entries.push((*entry).clone());
+        }
+    }
+
+    let referenced: HashSet<&Path> = entries.iter().map(|e| e.path.as_path()).collect();
+    let mut garbage: Vec<&PathBuf> = Vec::new();
+    for path in sizes.keys() {
+        // The index of the pages is kept, though referenced by no entry
+        if referenced.contains(path.as_path()) || path == Path::new(INDEX_FILE) {
+            continue;
+        }
+        if !removed.contains(path) {
+            match is_temporary(path) {
+                true => summary.incomplete += 1,
+                false if !latest.contains_key(path.as_path()) => summary.orphans += 1,
+                false => {}
+            }
+        }
+        garbage.push(path);
+    }
+    garbage.sort();
+    for path in garbage {
+        summary.bytes += sizes[path];
+        if !dry_run {
+            tokio::fs::remove_file(root.join(path)).await?;
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                // Fails as long as other versions are left in the directory
+                let _ = tokio::fs::remove_dir(root.join(parent)).await;
+            }
+        }
+    }
+
+    if !dry_run && entries.len() != manifest.entries().len() {
+        entries.sort_by(|a, b| a.url.cmp(&b.url).then(a.fetched_at.cmp(&b.fetched_at)));
+        Manifest::new(entries).write(root).await?;
+    }
+    Ok(summary)
+}
+
+/// Whether `path` is a temporary file left by an interrupted write
+fn is_temporary(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "tmp")
+}
+
+/// The paths, relative to `root`, of the stored pages and temporary files
+/// in `root` and its version directories
+pub(crate) async fn files(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let mut read_dir = tokio::fs::read_dir(root.join(&dir)).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let path = dir.join(entry.file_name());
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                // Version directories are only found in the root
+                if dir.as_os_str().is_empty() {
+                    dirs.push(path);
+                }
+            } else if file_type.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext == "html" || ext == "tmp")
+            {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        tokio::fs::create_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+        tokio::fs::write(path, content).await.unwrap();
+    }
+
+    fn entry(url: &str, path: &str, content: &str, fetched_at: i64) -> ManifestEntry {
+        let url = url::Url::parse(url).unwrap();
+        ManifestEntry::new(url, path.into(), content.as_bytes(), fetched_at)
+    }
+
+    /// A versioned storage with three versions of `/a`, a truncated page,
+    /// an entry without file, an orphan page, a temporary file and an index
+    async fn storage(root: &Path) {
+        for (path, content) in [
+            ("a/1.html", "a1"),
+            ("a/2.html", "a2"),
+            ("a/3.html", "a3"),
+            ("b.html", "b"),
+            ("orphan.html", "orphan"),
+            ("page.tmp", "tmp"),
+            (INDEX_FILE, "index"),
+        ] {
+            write(root, path, content).await;
+        }
+        let entries = vec![
+            entry("https://example.com/a", "a/1.html", "a1", 1),
+            entry("https://example.com/a", "a/2.html", "a2", 2),
+            entry("https://example.com/a", "a/3.html", "a3", 3),
+            entry("https://example.com/b", "b.html", "bb", 1),
+            entry("https://example.com/c", "c.html", "c", 1),
+        ];
+        Manifest::new(entries).write(root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dry_runs_report_the_garbage_without_removing_it() {
+        let root = tempfile::tempdir().unwrap();
+        storage(root.path()).await;
+        let summary = gc(root.path(), Some(1), true).await.unwrap();
+        assert_eq!(
+            (
+                summary.orphans,
+                summary.incomplete,
+                summary.pruned,
+                summary.dangling
+            ),
+            (1, 2, 2, 1)
+        );
+        assert_eq!(summary.bytes, 14);
+        assert_eq!(files(root.path()).await.unwrap().len(), 7);
+        assert_eq!(
+            Manifest::load(root.path()).await.unwrap().entries().len(),
+            5
+        );
+    }
+
+    #[tokio::test]
+    async fn collections_keep_the_recent_versions_and_the_index() {
+        let root = tempfile::tempdir().unwrap();
+        storage(root.path()).await;
+        gc(root.path(), Some(1), false).await.unwrap();
+        let mut left = files(root.path()).await.unwrap();
+        left.sort();
+        assert_eq!(left, [PathBuf::from("a/3.html"), PathBuf::from(INDEX_FILE)]);
+        let manifest = Manifest::load(root.path()).await.unwrap();
+        let entries: Vec<_> = manifest
+            .entries()
+            .iter()
+            .map(|e| (e.url.path(), e.fetched_at))
+            .collect();
+        assert_eq!(entries, [("/a", 3)]);
+
+        // Every version is kept without a limit
+        let root = tempfile::tempdir().unwrap();
+        storage(root.path()).await;
+        let summary = gc(root.path(), None, false).await.unwrap();
+        assert_eq!(summary.pruned, 0);
+        assert_eq!(
+            Manifest::load(root.path()).await.unwrap().entries().len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Handlers of non-HTML responses.
+//!
+//! A [`HandlerRegistry`] maps content types to user callbacks. Pages
+//! served with a registered content type are passed to their handler
+//! instead of being scraped as HTML: the handler selects the links to
+//! follow, and may report a record in the `handlers.jsonl` file of the
+//! storage root.
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::page::CrawledPage;
+
+/// Name of the report of the handler records
+pub const HANDLERS_FILE: &str = "handlers.jsonl";
+
+/// The outcome of handling a page
+#[derive(Debug, Clone, Default)]
+pub struct Handled {
+    /// The links to follow
+    pub links: Vec<url::Url>,
+    /// The record to report, if any
+    pub record: Option<Value>,
+}
+
+/// A record reported by a handler
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandlerRecord {
+    pub url: url::Url,
+    pub content_type: String,
+    pub record: Value,
+}
+
+/// A callback handling pages of a content type
+pub type Handler = dyn Fn(&CrawledPage) -> Handled + Send + Sync;
+
+/// Handlers by content type
+#[derive(Clone, Default)]
+pub struct HandlerRegistry {
+    handlers: Vec<(String, Arc<Handler>)>,
+}
+
+impl HandlerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle the pages of `content_type` with `handler`. The content type
+    /// is matched in any case, ignoring its parameters, and may be a whole
+    /// type, e.g. `image/*`. A later handler of a content type replaces
+    /// the earlier one.
+    pub fn register_handler<F>(&mut self, content_type: &str, handler: F)
+    where
+        F: Fn(&CrawledPage) -> Handled + Send + Sync + 'static,
+    {
+        let content_type = content_type.trim().to_ascii_lowercase();
+        self.handlers
+            .retain(|(registered, _)| *registered != content_type);
+        self.handlers.push((content_type, Arc::new(handler)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// The media type of `page`, e.g. `application/json`, if declared
+    pub fn content_type(page: &CrawledPage) -> Option<String> {
+        page.content_type()
+    }
+
+    /// The handler of the media type `content_type`, preferring
+    /// an exact match over a whole type
+    pub fn handler(&self, content_type: &str) -> Option<&Handler> {
+        let whole_type = content_type
+            .split_once('/')
+            .map(|(kind, _)| format!("{}/*", kind))?;
+        self.handlers
+            .iter()
+            .find(|(registered, _)| registered == content_type)
+            .or_else(|| {
+                self.handlers
+                    .iter()
+                    .find(|(registered, _)| *registered == whole_type)
+            })
+            .map(|(_, handler)| handler.as_ref())
+    }
+}
+
+impl fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.handlers.iter().map(|(content_type, _)| content_type))
+            .finish()
+    }
+}
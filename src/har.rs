@@ -0,0 +1,240 @@
+//! Recording of the fetches of a crawl as an HTTP Archive (HAR).
+//!
+//! Every visited page is recorded as an entry of a HAR 1.2 log, with the
+//! request, the status, headers and size of the response, and the time
+//! waited for the response headers and spent receiving the body. The log
+//! is written once the crawl ends, and opens in the network panel of
+//! browsers or any HAR viewer.
+//!
+//! The HTTP client follows redirects and adds its own headers, so only
+//! the last response of a redirect chain is recorded, and the request
+//! headers are limited to the user agent of the crawler.
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::StreamExt;
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::page::CrawledPage;
+
+const HAR_VERSION: &str = "1.2";
+
+/// A HAR file
+#[derive(Debug, Clone, Serialize)]
+pub struct Har {
+    pub log: HarLog,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HarLog {
+    pub version: &'static str,
+    pub creator: HarCreator,
+    pub entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HarCreator {
+    pub name: &'static str,
+    pub version: &'static str,
+}
+
+/// The fetch of a page
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarEntry {
+    /// The time the request was sent, in RFC 3339 format
+    pub started_date_time: String,
+    /// The total time of the fetch in milliseconds
+    pub time: f64,
+    pub request: HarRequest,
+    pub response: HarResponse,
+    pub cache: HarCache,
+    pub timings: HarTimings,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarRequest {
+    pub method: &'static str,
+    pub url: url::Url,
+    pub http_version: String,
+    pub cookies: Vec<HarHeader>,
+    pub headers: Vec<HarHeader>,
+    pub query_string: Vec<HarHeader>,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub http_version: String,
+    pub cookies: Vec<HarHeader>,
+    pub headers: Vec<HarHeader>,
+    pub content: HarContent,
+    /// The url the request was redirected to, if any
+    #[serde(rename = "redirectURL")]
+    pub redirect_url: String,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+/// A header, cookie or query parameter
+#[derive(Debug, Clone, Serialize)]
+pub struct HarHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarContent {
+    pub size: i64,
+    pub mime_type: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HarCache {}
+
+/// The phases of a fetch in milliseconds, `-1` if unknown
+#[derive(Debug, Clone, Serialize)]
+pub struct HarTimings {
+    pub blocked: f64,
+    pub dns: f64,
+    pub connect: f64,
+    pub send: f64,
+    pub wait: f64,
+    pub receive: f64,
+}
+
+impl HarEntry {
+    /// The entry of the fetch of `page`, sent with `user_agent`
+    pub fn new(page: &CrawledPage, user_agent: Option<&str>) -> Self {
+        let http_version = format!("{:?}", page.version);
+        let started = chrono::DateTime::from_timestamp_millis(page.fetched_at).unwrap_or_default();
+        let wait = millis(page.waited);
+        let receive = millis(page.latency.saturating_sub(page.waited));
+        let headers = user_agent
+            .map(|user_agent| HarHeader {
+                name: reqwest::header::USER_AGENT.to_string(),
+                value: user_agent.to_string(),
+            })
+            .into_iter()
+            .collect();
+        Self {
+            started_date_time: started.to_rfc3339(),
+            time: wait + receive,
+            request: HarRequest {
+                method: "GET",
+                url: page.url.clone(),
+                http_version: http_version.clone(),
+                cookies: Vec::new(),
+                headers,
+                query_string: page
+                    .url
+                    .query_pairs()
+                    .map(|(name, value)| HarHeader {
+                        name: name.into_owned(),
+                        value: value.into_owned(),
+                    })
+                    .collect(),
+                headers_size: -1,
+                body_size: 0,
+            },
+            response: HarResponse {
+                status: page.status.as_u16(),
+                status_text: page.status.canonical_reason().unwrap_or("").to_string(),
+                http_version,
+                cookies: Vec::new(),
+                headers: har_headers(&page.headers),
+                content: HarContent {
+                    size: page.body.len() as i64,
+                    mime_type: page
+                        .headers
+                        .get(reqwest::header::CONTENT_TYPE)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or("")
+                        .to_string(),
+                },
+                redirect_url: match page.final_url != page.url {
+                    true => page.final_url.to_string(),
+                    false => String::new(),
+                },
+                headers_size: -1,
+                body_size: page.body.len() as i64,
+            },
+            cache: HarCache::default(),
+            timings: HarTimings {
+                blocked: -1.0,
+                dns: -1.0,
+                connect: -1.0,
+                send: 0.0,
+                wait,
+                receive,
+            },
+        }
+    }
+}
+
+/// A recorder of the fetches of a crawl
+pub struct HarRecorder {
+    path: PathBuf,
+    user_agent: Option<String>,
+}
+
+impl HarRecorder {
+    /// A recorder writing the HAR file at `path`
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            user_agent: None,
+        }
+    }
+
+    /// Record the `User-Agent` header sent by the crawler
+    pub fn with_user_agent(mut self, user_agent: Option<String>) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Record an entry for each of the `pages`, and write the log once
+    /// the crawl sending them ends. Returns the number of entries.
+    pub async fn run(self, mut pages: UnboundedReceiver<CrawledPage>) -> Result<usize> {
+        let mut entries = Vec::new();
+        while let Some(page) = pages.next().await {
+            entries.push(HarEntry::new(&page, self.user_agent.as_deref()));
+        }
+        let n_entries = entries.len();
+        let har = Har {
+            log: HarLog {
+                version: HAR_VERSION,
+                creator: HarCreator {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                entries,
+            },
+        };
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(&har)?).await?;
+        Ok(n_entries)
+    }
+}
+
+fn har_headers(headers: &HeaderMap) -> Vec<HarHeader> {
+    headers
+        .iter()
+        .map(|(name, value)| HarHeader {
+            name: name.to_string(),
+            value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        })
+        .collect()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
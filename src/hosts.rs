@@ -0,0 +1,88 @@
+//! Per-host statistics of a crawl.
+//!
+//! The crawl counts the pages, bytes, fetch latency and errors of every
+//! host it visits, so that the report of a run shows where its budget of
+//! pages and time went.
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Number of hosts listed in the reports
+pub const TOP_HOSTS: usize = 10;
+
+/// The counts of the visits to a single host
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostStats {
+    /// Pages fetched and scraped
+    pub pages: usize,
+    /// Bytes of the fetched bodies
+    pub bytes: u64,
+    /// Pages that could not be fetched
+    pub errors: usize,
+    /// Total fetch latency of the pages in milliseconds
+    pub latency_ms: u64,
+}
+
+impl HostStats {
+    /// The average fetch latency of the pages
+    pub fn avg_latency(&self) -> Duration {
+        match self.pages {
+            0 => Duration::ZERO,
+            pages => Duration::from_millis(self.latency_ms / pages as u64),
+        }
+    }
+
+    /// The fraction of the visits that failed
+    pub fn error_rate(&self) -> f64 {
+        match self.pages + self.errors {
+            0 => 0.0,
+            visits => self.errors as f64 / visits as f64,
+        }
+    }
+}
+
+/// The statistics of the hosts of a crawl, by host name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HostTable(BTreeMap<String, HostStats>);
+
+impl HostTable {
+    fn host(&mut self, url: &url::Url) -> &mut HostStats {
+        let host = url.host_str().unwrap_or_default().to_string();
+        self.0.entry(host).or_default()
+    }
+
+    /// Count a page of `bytes` fetched from `url` in `latency`
+    pub fn record_page(&mut self, url: &url::Url, bytes: usize, latency: Duration) {
+        let stats = self.host(url);
+        stats.pages += 1;
+        stats.bytes += bytes as u64;
+        stats.latency_ms += latency.as_millis() as u64;
+    }
+
+    /// Count a failed visit of `url`
+    pub fn record_error(&mut self, url: &url::Url) {
+        self.host(url).errors += 1;
+    }
+
+    /// The `n` hosts with the most visits, the most visited first
+    pub fn top(&self, n: usize) -> Vec<(&str, &HostStats)> {
+        let mut hosts: Vec<_> = self
+            .0
+            .iter()
+            .map(|(host, stats)| (host.as_str(), stats))
+            .collect();
+        hosts.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.pages + stats.errors));
+        hosts.truncate(n);
+        hosts
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
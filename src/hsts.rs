@@ -0,0 +1,200 @@
+//! HTTP Strict Transport Security of the crawled hosts.
+//!
+//! A host answering over https with a `Strict-Transport-Security` header
+//! is only to be reached over https for `max-age` seconds, along with its
+//! subdomains given `includeSubDomains`. The crawl remembers these hosts
+//! and upgrades their `http://` urls before visiting them, so that sites
+//! redirecting or refusing plain http are not visited twice. A page with
+//! a `Content-Security-Policy: upgrade-insecure-requests` directive asks
+//! for its own links to be upgraded likewise.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use reqwest::header::{HeaderMap, CONTENT_SECURITY_POLICY, STRICT_TRANSPORT_SECURITY};
+
+/// The policy of a host, as last declared
+#[derive(Debug, Clone, Copy)]
+struct HstsEntry {
+    include_subdomains: bool,
+    expires: Instant,
+}
+
+/// The hosts known to be reached over https only
+#[derive(Debug, Clone, Default)]
+pub struct HstsTable {
+    hosts: HashMap<String, HstsEntry>,
+}
+
+impl HstsTable {
+    /// Remember the policy declared by the `headers` of the response
+    /// of `url`. Policies declared over plain http are ignored.
+    pub fn record(&mut self, url: &url::Url, headers: &HeaderMap) {
+        // Hosts given by their IP address are not subject to HSTS
+        let (Some(url::Host::Domain(host)), "https") = (url.host(), url.scheme()) else {
+            return;
+        };
+        let Some((max_age, include_subdomains)) = headers
+            .get(STRICT_TRANSPORT_SECURITY)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse)
+        else {
+            return;
+        };
+        let host = host.to_ascii_lowercase();
+        if max_age == 0 {
+            self.hosts.remove(&host);
+            return;
+        }
+        tracing::debug!("  -> Reaching {} over https only", host);
+        let expires = Instant::now() + Duration::from_secs(max_age);
+        self.hosts.insert(
+            host,
+            HstsEntry {
+                include_subdomains,
+                expires,
+            },
+        );
+    }
+
+    /// Whether the host is to be reached over https only
+    pub fn is_known(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        let now = Instant::now();
+        let known = |domain: &str, subdomain: bool| {
+            self.hosts.get(domain).is_some_and(|entry| {
+                entry.expires > now && (!subdomain || entry.include_subdomains)
+            })
+        };
+        known(&host, false)
+            || host
+                .match_indices('.')
+                .any(|(i, _)| known(&host[i + 1..], true))
+    }
+
+    /// The https url of the `http://` url of a known host, if any
+    pub fn upgrade(&self, url: &url::Url) -> Option<url::Url> {
+        match url.host_str() {
+            Some(host) if url.scheme() == "http" && self.is_known(host) => Some(upgrade(url)),
+            _ => None,
+        }
+    }
+}
+
+/// The max age in seconds and the `includeSubDomains` directive of
+/// a `Strict-Transport-Security` header, if valid
+fn parse(value: &str) -> Option<(u64, bool)> {
+    let mut max_age = None;
+    let mut include_subdomains = false;
+    for directive in value.split(';').map(str::trim) {
+        match directive.split_once('=') {
+            Some((name, age)) if name.trim().eq_ignore_ascii_case("max-age") => {
+                max_age = age.trim().trim_matches('"').parse().ok();
+            }
+            None if directive.eq_ignore_ascii_case("includesubdomains") => {
+                include_subdomains = true;
+            }
+            _ => {}
+        }
+    }
+    max_age.map(|max_age| (max_age, include_subdomains))
+}
+
+/// Whether the `headers` of a page ask for its links to be upgraded
+pub fn upgrades_insecure_requests(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(CONTENT_SECURITY_POLICY)
+        .iter()
+        .any(|value| {
+            value.to_str().is_ok_and(|policy| {
+                policy.split(';').any(|directive| {
+                    directive
+                        .trim()
+                        .eq_ignore_ascii_case("upgrade-insecure-requests")
+                })
+            })
+        })
+}
+
+/// The https url of an `http://` url, keeping any explicit port but 80
+pub fn upgrade(url: &url::Url) -> url::Url {
+    let mut upgraded = url.clone();
+    if url.scheme() == "http" {
+        let _ = upgraded.set_scheme("https");
+    }
+    upgraded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(value: &str) -> url::Url {
+        url::Url::parse(value).unwrap()
+    }
+
+    fn hsts(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(STRICT_TRANSPORT_SECURITY, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn headers_are_parsed_for_their_directives() {
+        assert_eq!(parse("max-age=31536000"), Some((31536000, false)));
+        assert_eq!(
+            parse(r#" Max-Age="60" ; includeSubDomains; preload"#),
+            Some((60, true))
+        );
+        assert_eq!(parse("includeSubDomains"), None);
+        assert_eq!(parse("max-age=soon"), None);
+    }
+
+    #[test]
+    fn known_hosts_are_upgraded_with_their_subdomains() {
+        let mut table = HstsTable::default();
+        table.record(
+            &url("https://example.com/"),
+            &hsts("max-age=60; includeSubDomains"),
+        );
+        table.record(&url("https://plain.test/"), &hsts("max-age=60"));
+        // Declared over http or by an IP address, the policies are ignored
+        table.record(&url("http://insecure.test/"), &hsts("max-age=60"));
+        table.record(&url("https://127.0.0.1/"), &hsts("max-age=60"));
+
+        assert_eq!(
+            table.upgrade(&url("http://WWW.Example.com:8080/a?b")),
+            Some(url("https://www.example.com:8080/a?b"))
+        );
+        assert_eq!(
+            table.upgrade(&url("http://plain.test:80/")),
+            Some(url("https://plain.test/"))
+        );
+        assert!(!table.is_known("sub.plain.test"));
+        assert!(!table.is_known("insecure.test"));
+        assert!(!table.is_known("127.0.0.1"));
+        assert_eq!(table.upgrade(&url("https://example.com/")), None);
+    }
+
+    #[test]
+    fn zero_max_ages_forget_the_host() {
+        let mut table = HstsTable::default();
+        table.record(&url("https://example.com/"), &hsts("max-age=60"));
+        table.record(&url("https://example.com/"), &hsts("max-age=0"));
+        assert!(!table.is_known("example.com"));
+    }
+
+    #[test]
+    fn pages_ask_for_upgrades_through_their_policy() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            CONTENT_SECURITY_POLICY,
+            "default-src 'self'".parse().unwrap(),
+        );
+        assert!(!upgrades_insecure_requests(&headers));
+        headers.append(
+            CONTENT_SECURITY_POLICY,
+            "img-src *; Upgrade-Insecure-Requests".parse().unwrap(),
+        );
+        assert!(upgrades_insecure_requests(&headers));
+    }
+}
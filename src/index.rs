@@ -0,0 +1,101 @@
+//! An HTML index of a stored crawl.
+//!
+//! The index lists the latest stored version of every page in a table of
+//! its title, url, size and fetch time, linking to the stored file, so
+//! that the storage directory can be browsed by hand from a plain file
+//! browser. The titles are read from the stored HTML pages, except for
+//! the encrypted ones, whose titles are left out of the plain index.
+use std::fmt::Write as _;
+use std::path::{Component, Path};
+
+use crate::error::Result;
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::parse::Document;
+use crate::Storage;
+
+/// Name of the index file in the storage root
+pub const INDEX_FILE: &str = "index.html";
+
+/// Write the index of the pages of `storage`, returning the number of
+/// pages listed, or none if a page is stored as the index file itself
+pub async fn write_index(storage: &Storage) -> Result<Option<usize>> {
+    let manifest = Manifest::load(storage.path()).await?;
+    let mut entries: Vec<&ManifestEntry> = manifest.latest().into_values().collect();
+    if entries
+        .iter()
+        .any(|entry| entry.path == Path::new(INDEX_FILE))
+    {
+        tracing::warn!(
+            "==> Not writing the index, a page is stored as {}",
+            INDEX_FILE
+        );
+        return Ok(None);
+    }
+    entries.sort_by(|a, b| a.url.cmp(&b.url));
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<meta charset=\"utf-8\">\n<title>Crawled pages</title>\n\
+         <table>\n<tr><th>Title</th><th>URL</th><th>Size</th><th>Fetched</th></tr>\n",
+    );
+    for entry in &entries {
+        let title = title(storage, entry).await.unwrap_or_default();
+        let fetched_at = chrono::DateTime::from_timestamp_millis(entry.fetched_at)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_default();
+        writeln!(
+            html,
+            "<tr><td>{}</td><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td></tr>",
+            escape(&title),
+            escape(&href(&entry.path)),
+            escape(entry.url.as_str()),
+            entry.size,
+            fetched_at
+        )
+        .unwrap();
+    }
+    html.push_str("</table>\n");
+    tokio::fs::write(storage.path().join(INDEX_FILE), html).await?;
+    Ok(Some(entries.len()))
+}
+
+/// The title of a stored HTML page, if any
+async fn title(storage: &Storage, entry: &ManifestEntry) -> Option<String> {
+    if entry.binary || entry.encrypted {
+        return None;
+    }
+    let content = storage.read(entry).await.ok()?;
+    Document::parse(&content).title()
+}
+
+/// The relative url of a file of the storage root
+fn href(path: &Path) -> String {
+    let components: Vec<String> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(encode(&name.to_string_lossy())),
+            _ => None,
+        })
+        .collect();
+    components.join("/")
+}
+
+/// A path component, with the characters of special meaning in a url
+/// percent-encoded
+fn encode(name: &str) -> String {
+    name.chars().fold(String::new(), |mut encoded, c| {
+        match c {
+            '%' | '#' | '?' | ' ' | '"' | '\\' => {
+                let _ = write!(encoded, "%{:02X}", c as u32);
+            }
+            _ => encoded.push(c),
+        }
+        encoded
+    })
+}
+
+/// `value` escaped for an HTML attribute or text
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
@@ -0,0 +1,113 @@
+//! Fetch latency of the pages of a crawl.
+//!
+//! The crawl records how long every page took to fetch, and reports the
+//! distribution of the latencies as percentiles and a histogram. The
+//! slowest urls can also be kept, as a starting point of a performance
+//! audit.
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Name of the file listing the slowest pages
+pub const SLOW_PAGES_FILE: &str = "slow_pages.jsonl";
+
+/// Upper bounds of the histogram buckets in milliseconds
+const BUCKETS_MS: &[u64] = &[10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// A page and its fetch latency
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SlowPage {
+    pub latency_ms: u64,
+    pub url: url::Url,
+}
+
+/// The number of pages fetched within a latency bound
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyBucket {
+    /// The bound in milliseconds, or none for the slower pages
+    pub le_ms: Option<u64>,
+    pub count: usize,
+}
+
+/// The distribution of the fetch latencies of a crawl
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+    pub histogram: Vec<LatencyBucket>,
+}
+
+/// Records the fetch latencies of a crawl
+#[derive(Debug, Default)]
+pub struct LatencyRecorder {
+    samples: Vec<u64>,
+    max_slow_pages: usize,
+    slowest: BinaryHeap<Reverse<SlowPage>>,
+}
+
+impl LatencyRecorder {
+    /// A recorder keeping the `max_slow_pages` slowest pages
+    pub fn new(max_slow_pages: usize) -> Self {
+        Self {
+            max_slow_pages,
+            ..Default::default()
+        }
+    }
+
+    /// Record that `url` was fetched in `latency`
+    pub fn record(&mut self, url: &url::Url, latency: Duration) {
+        let latency_ms = latency.as_millis() as u64;
+        self.samples.push(latency_ms);
+        if self.max_slow_pages == 0 {
+            return;
+        }
+        self.slowest.push(Reverse(SlowPage {
+            latency_ms,
+            url: url.clone(),
+        }));
+        if self.slowest.len() > self.max_slow_pages {
+            self.slowest.pop();
+        }
+    }
+
+    /// The distribution of the recorded latencies, if any
+    pub fn summary(&self) -> Option<LatencySummary> {
+        let mut samples = self.samples.clone();
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+        let max_ms = *samples.last()?;
+        let mut histogram: Vec<_> = BUCKETS_MS
+            .iter()
+            .map(|&le_ms| LatencyBucket {
+                le_ms: Some(le_ms),
+                count: samples.partition_point(|&sample| sample <= le_ms),
+            })
+            .collect();
+        histogram.push(LatencyBucket {
+            le_ms: None,
+            count: samples.len(),
+        });
+        Some(LatencySummary {
+            p50_ms: percentile(50),
+            p90_ms: percentile(90),
+            p99_ms: percentile(99),
+            max_ms,
+            histogram,
+        })
+    }
+
+    /// The slowest pages, the slowest first
+    pub fn slowest(&self) -> Vec<SlowPage> {
+        let mut pages: Vec<_> = self
+            .slowest
+            .iter()
+            .map(|Reverse(page)| page.clone())
+            .collect();
+        pages.sort_by(|a, b| b.cmp(a));
+        pages
+    }
+}
@@ -4,182 +4,129 @@
 //! traverses the contained links in a breadth-first manner.
 //!
 //! Each visited page is stored in the disk.
-use std::collections::HashSet;
-use std::path::PathBuf;
-use std::sync::Arc;
-
-use futures::stream::{FuturesOrdered, StreamExt};
-use scraper::{Html, Selector};
-use sha1::{Digest, Sha1};
-use tokio::{sync::mpsc, task::JoinHandle};
-use tokio_stream::wrappers::ReceiverStream;
-
-use error::{CrawlerError, Result};
-
+//!
+//! The traversal logic ([`frontier`]) and the extraction of links
+//! ([`parse`]) do not depend on an async runtime, while the crawl
+//! tasks are spawned through an `executor::Executor`.
+//!
+//! The crawler, the storage and the tools built on them require the
+//! `runtime` feature, enabled by any of the `tokio` (default), `smol`
+//! or `async-std` features. Without it, the crate is reduced to a core
+//! that also compiles to `wasm32`. The `scripting` feature enables
+//! per-page hooks scripted in Rhai, the `capi` feature a C interface,
+//! the `serve` feature an HTTP API managing crawl jobs, the `nats`
+//! feature the exchange of seeds and pages through a NATS server, the
+//! `encryption` feature the encryption at rest of the stored pages and
+//! the `object-store` feature the upload of the stored pages to a cloud
+//! bucket.
+pub mod audit;
+pub mod config;
+pub mod consistency;
+pub mod contacts;
 pub mod error;
-
-pub struct Crawler {
-    root_url: url::Url,
-    storage: Arc<Storage>,
-    scraper: Scraper,
-    visited: HashSet<url::Url>,
-    task_queue: FuturesOrdered<JoinHandle<Result<()>>>,
-}
-
-impl Crawler {
-    pub fn new(
-        root_url: String,
-        storage: Option<Storage>,
-        scraper: Option<Scraper>,
-    ) -> Result<Self> {
-        let root_url = url::Url::parse(&root_url)?;
-        let storage = match storage {
-            Some(storage) => Arc::new(storage),
-            None => Arc::new(Storage::try_from(&root_url)?),
-        };
-        let visited = HashSet::default();
-        let scraper = scraper.unwrap_or_default();
-        let task_queue = FuturesOrdered::new();
-        Ok(Self {
-            root_url,
-            storage,
-            scraper,
-            visited,
-            task_queue,
-        })
-    }
-
-    pub fn queue_task(&mut self, url: url::Url, tx: mpsc::Sender<TaskContext>) {
-        let storage = Arc::clone(&self.storage);
-        let scraper = self.scraper.clone();
-        self.task_queue.push_back(tokio::spawn(async move {
-            scraper.visit(url, tx, storage).await
-        }));
-    }
-
-    pub async fn run(mut self, max_tasks: usize, max_pages: usize) -> Result<()> {
-        // Setup storagedir
-        self.storage.setup().await?;
-        // Setup crawler sync
-        let (tx, rx) = mpsc::channel(2_usize.pow(16));
-        let mut rx = ReceiverStream::new(rx).fuse();
-        // Start with root url
-        let root_url = self.root_url.clone();
-        self.queue_task(root_url, tx);
-        self.visited.insert(self.root_url.clone());
-        // Descend into nested urls
-        let mut n_tasks_remaining = max_tasks - 1;
-        let mut n_pages_queued = 1;
-        let mut n_pages_visited = 0;
-        loop {
-            tokio::select!(
-                Some(result) = &mut self.task_queue.next() => {
-                    match result {
-                        Ok(Ok(_)) => {
-                            n_pages_visited += 1;
-                            n_tasks_remaining += 1;
-                            tracing::info!("==> Visited {} out of {}", n_pages_visited, max_pages);
-                        },
-                        err => {
-                            n_pages_queued -= 1;
-                            tracing::warn!("error visiting page: {:?}", err);
-                        }
-                    }
-                },
-                Some(TaskContext((url, tx))) = rx.next(), if n_tasks_remaining > 0 && n_pages_queued < max_pages  => {
-                    if !&self.visited.contains(&url) {
-                        self.visited.insert(url.clone());
-                        self.queue_task(url, tx);
-                        n_tasks_remaining -= 1;
-                        n_pages_queued += 1;
-                    }
-                },
-                else => break
-            );
-        }
-        Ok(())
-    }
-}
-
-/// Context for spawning a crawl task
-#[derive(Debug, Clone)]
-pub struct TaskContext((url::Url, mpsc::Sender<TaskContext>));
-
-/// The storage for persisting webpages
-#[derive(Debug)]
-pub struct Storage {
-    path: PathBuf,
-}
-
-impl Storage {
-    pub fn new(path: PathBuf) -> Self {
-        Self { path }
-    }
-
-    pub async fn setup(&self) -> Result<()> {
-        Ok(tokio::fs::create_dir_all(&self.path).await?)
-    }
-
-    pub fn url_to_path(&self, url: &url::Url) -> PathBuf {
-        let hash = Sha1::digest(url.as_str().as_bytes());
-        let mut path = PathBuf::from(hex::encode(hash.as_slice()));
-        path.set_extension("html");
-        path
-    }
-
-    pub async fn serialize(&self, page: impl AsRef<[u8]>, url: &url::Url) -> Result<()> {
-        let path = self.path.join(self.url_to_path(url));
-        tokio::fs::write(path, page).await?;
-        Ok(())
-    }
-}
-
-impl TryFrom<&url::Url> for Storage {
-    type Error = CrawlerError;
-
-    fn try_from(url: &url::Url) -> Result<Self> {
-        let ts = chrono::Utc::now().timestamp_millis();
-        let host = url.host_str().ok_or(CrawlerError::NoUrlHost)?;
-        Ok(Storage::new(format!("webpages/{}_{}", host, ts).into()))
-    }
-}
-
-/// Encapsulates functionality to get the webpage
-/// and scrape the desired information
-#[derive(Default, Clone)]
-pub struct Scraper {
-    pub client: reqwest::Client,
-}
-
-impl Scraper {
-    pub fn new(client: reqwest::Client) -> Self {
-        Self { client }
-    }
-
-    pub fn scrape(page: String) -> Vec<url::Url> {
-        let html = Html::parse_document(&page);
-        let selector = Selector::parse("a").unwrap();
-        html.select(&selector)
-            .filter_map(|element| element.value().attr("href"))
-            .filter_map(|href| url::Url::parse(href).ok())
-            .collect()
-    }
-
-    pub async fn visit(
-        &self,
-        url: url::Url,
-        tx: mpsc::Sender<TaskContext>,
-        storage: Arc<Storage>,
-    ) -> Result<()> {
-        tracing::debug!("==> Visiting url: {:?}", url.as_str());
-        let body = self.client.get(url.as_str()).send().await?.text().await?;
-        tracing::debug!("  -> Serializing");
-        storage.serialize(&body, &url).await?;
-        tracing::debug!("  -> Scraping");
-        for url in Self::scrape(body) {
-            let new_tx = tx.clone();
-            tx.send(TaskContext((url, new_tx))).await?;
-        }
-        Ok(())
-    }
-}
+pub mod extract;
+pub mod frontier;
+pub mod handler;
+pub mod hosts;
+pub mod latency;
+pub mod naming;
+pub mod page;
+pub mod parse;
+pub mod relevance;
+pub mod robots;
+pub mod sample;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod seed;
+pub mod seo;
+pub mod simhash;
+pub mod state;
+pub mod store;
+pub mod structured;
+
+#[cfg(feature = "runtime")]
+pub mod backlinks;
+#[cfg(feature = "runtime")]
+pub mod batch;
+#[cfg(feature = "runtime")]
+pub mod budget;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "runtime")]
+pub mod catalog;
+#[cfg(feature = "runtime")]
+pub mod concurrency;
+#[cfg(feature = "runtime")]
+pub mod crawler;
+#[cfg(feature = "runtime")]
+pub mod credentials;
+#[cfg(feature = "encryption")]
+pub mod crypt;
+#[cfg(feature = "runtime")]
+pub mod diff;
+#[cfg(feature = "runtime")]
+pub mod encoding;
+#[cfg(feature = "runtime")]
+pub mod executor;
+#[cfg(feature = "runtime")]
+pub mod export;
+#[cfg(feature = "runtime")]
+pub mod gc;
+#[cfg(feature = "runtime")]
+pub mod har;
+#[cfg(feature = "runtime")]
+pub mod hsts;
+#[cfg(feature = "runtime")]
+pub mod index;
+#[cfg(feature = "runtime")]
+pub mod linkrot;
+#[cfg(feature = "runtime")]
+pub mod login;
+#[cfg(feature = "runtime")]
+pub mod manifest;
+#[cfg(feature = "runtime")]
+pub mod merge;
+#[cfg(feature = "runtime")]
+pub mod monitor;
+#[cfg(feature = "otel")]
+pub mod otel;
+#[cfg(feature = "nats")]
+pub mod queue;
+#[cfg(feature = "object-store")]
+pub mod remote;
+#[cfg(feature = "serve")]
+pub mod replay;
+#[cfg(feature = "runtime")]
+pub mod resolve;
+#[cfg(feature = "runtime")]
+pub mod run;
+#[cfg(feature = "runtime")]
+pub mod schedule;
+#[cfg(feature = "runtime")]
+pub mod scraper;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(feature = "runtime")]
+pub mod sink;
+#[cfg(feature = "runtime")]
+pub mod sql;
+#[cfg(feature = "runtime")]
+pub mod storage;
+#[cfg(feature = "runtime")]
+pub mod throttle;
+#[cfg(feature = "runtime")]
+pub mod verify;
+#[cfg(feature = "runtime")]
+pub mod webhook;
+#[cfg(feature = "runtime")]
+pub mod writer;
+
+pub use config::{StorageLayout, DEFAULT_MIN_FREE_BYTES, DEFAULT_WRITERS};
+pub use frontier::TaskContext;
+
+#[cfg(feature = "runtime")]
+pub use crawler::{Crawler, CrawlerHandle, LiveStats};
+#[cfg(feature = "runtime")]
+pub use scraper::Scraper;
+#[cfg(feature = "runtime")]
+pub use storage::Storage;
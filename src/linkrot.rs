@@ -0,0 +1,274 @@
+//! Long-running monitoring of a fixed list of links.
+//!
+//! The checker periodically requests every url of the list, and appends
+//! the outcome of each check to the `link_status.jsonl` history of its
+//! directory. A link is broken when its request fails or is answered
+//! with a client or server error. Links found broken after a successful
+//! check, or at their first check, are reported in `broken_links.jsonl`,
+//! and links answering again are logged as recovered.
+//!
+//! The last known state of every link is restored from the history, so
+//! that a restarted checker only reports the links broken since. With
+//! the `sqlite` feature, the history can be kept in the `checks` table
+//! of a `link_status.sqlite` database instead, to query it with SQL.
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::Storage;
+
+/// Name of the file recording every check
+pub const LINK_STATUS_FILE: &str = "link_status.jsonl";
+
+/// Name of the file reporting the newly broken links
+pub const BROKEN_LINKS_FILE: &str = "broken_links.jsonl";
+
+/// Name of the database recording every check
+#[cfg(feature = "sqlite")]
+pub const LINK_STATUS_DB: &str = "link_status.sqlite";
+
+#[cfg(feature = "sqlite")]
+const SCHEMA: &str = "\
+CREATE TABLE IF NOT EXISTS checks (
+    id INTEGER PRIMARY KEY,
+    url TEXT NOT NULL,
+    checked_at INTEGER NOT NULL,
+    status INTEGER,
+    error TEXT,
+    broken INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS checks_url ON checks (url, checked_at);
+";
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The outcome of the check of a link
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkCheck {
+    pub url: url::Url,
+    /// Time of the check in milliseconds since the epoch
+    pub checked_at: i64,
+    /// The status of the response, if any
+    pub status: Option<u16>,
+    /// The error the request failed with
+    pub error: Option<String>,
+    pub broken: bool,
+}
+
+/// A newly broken link
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrokenLink {
+    #[serde(flatten)]
+    pub check: LinkCheck,
+    /// Time of the last successful check, if any
+    pub last_ok_at: Option<i64>,
+}
+
+/// The last known state of a link
+#[derive(Debug, Clone, Copy, Default)]
+struct LinkState {
+    broken: Option<bool>,
+    last_ok_at: Option<i64>,
+}
+
+/// Where the checks are recorded
+enum History {
+    JsonLines,
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Connection),
+}
+
+/// Periodically checks a list of links
+pub struct LinkRotChecker {
+    urls: Vec<url::Url>,
+    storage: Storage,
+    client: reqwest::Client,
+    interval: Duration,
+    #[cfg(feature = "sqlite")]
+    sqlite: bool,
+}
+
+impl LinkRotChecker {
+    /// Check `urls` with `client`, recording the history in `dir`
+    pub fn new(urls: Vec<url::Url>, dir: PathBuf, client: reqwest::Client) -> Self {
+        Self {
+            urls,
+            storage: Storage::new(dir),
+            client,
+            interval: DEFAULT_INTERVAL,
+            #[cfg(feature = "sqlite")]
+            sqlite: false,
+        }
+    }
+
+    /// Check every link once per `interval`
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Record the history in a SQLite database rather than JSON lines
+    #[cfg(feature = "sqlite")]
+    pub fn with_sqlite(mut self, sqlite: bool) -> Self {
+        self.sqlite = sqlite;
+        self
+    }
+
+    /// Check the links until the future is dropped
+    pub async fn run(self) -> Result<()> {
+        self.storage.setup().await?;
+        let _lease = self.storage.lease().await?;
+        let mut history = self.open_history()?;
+        let mut states = self.load_states(&history).await?;
+        tracing::info!("==> Checking {} links", self.urls.len());
+        let mut ticks = tokio::time::interval(self.interval);
+        loop {
+            ticks.tick().await;
+            let checks = futures::future::join_all(self.urls.iter().map(|url| self.check(url)));
+            let mut n_broken = 0;
+            for check in checks.await {
+                n_broken += check.broken as usize;
+                let state = states.entry(check.url.clone()).or_default();
+                self.record(&mut history, &check, state).await?;
+            }
+            tracing::info!("==> Checked {} links, {} broken", self.urls.len(), n_broken);
+        }
+    }
+
+    fn open_history(&self) -> Result<History> {
+        #[cfg(feature = "sqlite")]
+        if self.sqlite {
+            let db = rusqlite::Connection::open(self.storage.path().join(LINK_STATUS_DB))?;
+            db.execute_batch(SCHEMA)?;
+            return Ok(History::Sqlite(db));
+        }
+        Ok(History::JsonLines)
+    }
+
+    /// The last known state of every link of the history
+    async fn load_states(&self, history: &History) -> Result<HashMap<url::Url, LinkState>> {
+        let mut states: HashMap<url::Url, LinkState> = HashMap::new();
+        let checks = match history {
+            History::JsonLines => self.read_history().await?,
+            #[cfg(feature = "sqlite")]
+            History::Sqlite(db) => query_history(db)?,
+        };
+        for check in checks {
+            let state = states.entry(check.url).or_default();
+            state.broken = Some(check.broken);
+            if !check.broken {
+                state.last_ok_at = Some(check.checked_at);
+            }
+        }
+        Ok(states)
+    }
+
+    /// The checks of the JSON lines history, in order
+    async fn read_history(&self) -> Result<Vec<LinkCheck>> {
+        let history =
+            match tokio::fs::read_to_string(self.storage.path().join(LINK_STATUS_FILE)).await {
+                Ok(history) => history,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(err) => return Err(err.into()),
+            };
+        history
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    async fn check(&self, url: &url::Url) -> LinkCheck {
+        let checked_at = chrono::Utc::now().timestamp_millis();
+        let (status, error) = match self.client.get(url.as_str()).send().await {
+            Ok(response) => (Some(response.status()), None),
+            Err(err) => (err.status(), Some(err.to_string())),
+        };
+        LinkCheck {
+            url: url.clone(),
+            checked_at,
+            status: status.map(|status| status.as_u16()),
+            error,
+            broken: match status {
+                Some(status) => status.is_client_error() || status.is_server_error(),
+                None => true,
+            },
+        }
+    }
+
+    /// Append `check` to the history, reporting the link if newly broken
+    async fn record(
+        &self,
+        history: &mut History,
+        check: &LinkCheck,
+        state: &mut LinkState,
+    ) -> Result<()> {
+        match history {
+            History::JsonLines => self.storage.append_record(LINK_STATUS_FILE, check).await?,
+            #[cfg(feature = "sqlite")]
+            History::Sqlite(db) => {
+                db.execute(
+                    "INSERT INTO checks (url, checked_at, status, error, broken) \
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![
+                        check.url.as_str(),
+                        check.checked_at,
+                        check.status,
+                        check.error,
+                        check.broken
+                    ],
+                )?;
+            }
+        }
+        match (state.broken, check.broken) {
+            (None | Some(false), true) => {
+                tracing::warn!("==> Broken link {}", check.url);
+                let broken = BrokenLink {
+                    check: check.clone(),
+                    last_ok_at: state.last_ok_at,
+                };
+                self.storage
+                    .append_record(BROKEN_LINKS_FILE, &broken)
+                    .await?;
+            }
+            (Some(true), false) => tracing::info!("==> Recovered link {}", check.url),
+            _ => {}
+        }
+        state.broken = Some(check.broken);
+        if !check.broken {
+            state.last_ok_at = Some(check.checked_at);
+        }
+        Ok(())
+    }
+}
+
+/// The checks of the SQLite history, in order
+#[cfg(feature = "sqlite")]
+fn query_history(db: &rusqlite::Connection) -> Result<Vec<LinkCheck>> {
+    let mut statement =
+        db.prepare("SELECT url, checked_at, status, error, broken FROM checks ORDER BY id")?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+        ))
+    })?;
+    let mut checks = Vec::new();
+    for row in rows {
+        let (url, checked_at, status, error, broken) = row?;
+        checks.push(LinkCheck {
+            url: url::Url::parse(&url)?,
+            checked_at,
+            status,
+            error,
+            broken,
+        });
+    }
+    Ok(checks)
+}
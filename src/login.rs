@@ -0,0 +1,249 @@
+//! Login before the crawl.
+//!
+//! Sites gating their content behind a login form are crawled within the
+//! session of a login performed before the crawl. The login form is
+//! posted with the configured fields, along with the hidden inputs of its
+//! form page, e.g. a CSRF token, and the cookies set along the way,
+//! redirects included, are sent with every request of the crawl to their
+//! domain. The session is not refreshed while crawling, and logins
+//! requiring scripts to run in a browser are not supported.
+use std::fmt;
+
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, HeaderValue, LOCATION, SET_COOKIE};
+use reqwest::{Method, StatusCode};
+
+use crate::config::LoginConfig;
+use crate::encoding;
+use crate::error::{CrawlerError, Result};
+use crate::parse::Document;
+
+/// Max number of redirects followed hop by hop by a login
+/// or authenticated request
+pub const MAX_REDIRECTS: usize = 10;
+
+/// A cookie set during the login
+#[derive(Clone)]
+struct Cookie {
+    domain: String,
+    /// Sent to `domain` only, not to its subdomains
+    host_only: bool,
+    name: String,
+    value: String,
+}
+
+impl Cookie {
+    /// The cookie of a `Set-Cookie` header of a response of `host`
+    fn parse(host: &str, header: &str) -> Option<Self> {
+        let mut attributes = header.split(';');
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let mut cookie = Self {
+            domain: host.to_ascii_lowercase(),
+            host_only: true,
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+        };
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                // A removed cookie is kept empty, in place of the previous one
+                "max-age" if value.starts_with(['0', '-']) => cookie.value.clear(),
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    // A domain other than the host or its parents is rejected
+                    if cookie.domain != domain && !cookie.domain.ends_with(&format!(".{}", domain))
+                    {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                _ => {}
+            }
+        }
+        (!cookie.name.is_empty()).then_some(cookie)
+    }
+
+    fn is_sent_to(&self, host: &str) -> bool {
+        host == self.domain || (!self.host_only && host.ends_with(&format!(".{}", self.domain)))
+    }
+}
+
+/// The cookies of a logged in session
+#[derive(Clone, Default)]
+pub struct Session {
+    cookies: Vec<Cookie>,
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Session({} cookies)", self.len())
+    }
+}
+
+impl Session {
+    /// Keep the cookies set by the `headers` of a response of `url`
+    fn set(&mut self, url: &url::Url, headers: &HeaderMap) {
+        let Some(host) = url.host_str() else {
+            return;
+        };
+        let cookies = headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .filter_map(|header| Cookie::parse(host, header));
+        for cookie in cookies {
+            self.cookies
+                .retain(|c| c.name != cookie.name || c.domain != cookie.domain);
+            if !cookie.value.is_empty() {
+                self.cookies.push(cookie);
+            }
+        }
+    }
+
+    /// The `Cookie` header of the requests to `url`, marked as sensitive
+    pub fn header(&self, url: &url::Url) -> Option<HeaderValue> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let cookies: Vec<_> = self
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.is_sent_to(&host))
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        let mut header = HeaderValue::from_str(&cookies.join("; ")).ok()?;
+        header.set_sensitive(true);
+        Some(header)
+    }
+
+    /// The number of cookies of the session
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+/// A login of a [`LoginConfig`]
+#[derive(Debug, Clone)]
+pub struct Login {
+    config: LoginConfig,
+    client: reqwest::Client,
+}
+
+impl Login {
+    /// A login sending its requests with `client`, which
+    /// must not follow redirects, so that their cookies are kept
+    pub fn new(config: LoginConfig, client: reqwest::Client) -> Self {
+        Self { config, client }
+    }
+
+    /// Log in, returning the session
+    pub async fn run(&self) -> Result<Session> {
+        let mut session = Session::default();
+        let url = parse_url(&self.config.url)?;
+        let mut fields = Vec::new();
+        if let Some(form_page) = &self.config.form_page {
+            let (_, _, body) = self
+                .follow(&mut session, Method::GET, parse_url(form_page)?, &[])
+                .await?;
+            fields = Document::parse(&body).login_fields();
+        }
+        fields.retain(|(name, _)| {
+            !self.config.fields.contains_key(name) && !self.config.env_fields.contains_key(name)
+        });
+        fields.extend(self.config.fields.clone());
+        for (name, variable) in &self.config.env_fields {
+            let value = std::env::var(variable).map_err(|_| {
+                CrawlerError::Login(format!(
+                    "the environment variable `{}` of the field `{}` is not set",
+                    variable, name
+                ))
+            })?;
+            fields.push((name.clone(), value));
+        }
+        let (final_url, status, body) = self
+            .follow(&mut session, Method::POST, url, &fields)
+            .await?;
+        if !status.is_success() {
+            return Err(CrawlerError::Login(format!(
+                "{} answered {}",
+                final_url, status
+            )));
+        }
+        if let Some(text) = &self.config.failure_text {
+            if String::from_utf8_lossy(&body).contains(text.as_str()) {
+                return Err(CrawlerError::Login(format!(
+                    "{} answered with `{}`",
+                    final_url, text
+                )));
+            }
+        }
+        if session.is_empty() {
+            return Err(CrawlerError::Login(format!(
+                "{} set no session cookie",
+                final_url
+            )));
+        }
+        tracing::info!(
+            "==> Logged in at {} with {} cookies",
+            final_url,
+            session.len()
+        );
+        Ok(session)
+    }
+
+    /// Send a request, posting `form` if any, and follow its redirects,
+    /// keeping the cookies set along the way. Returns the final url,
+    /// status and body.
+    async fn follow(
+        &self,
+        session: &mut Session,
+        mut method: Method,
+        mut url: url::Url,
+        form: &[(String, String)],
+    ) -> Result<(url::Url, StatusCode, Bytes)> {
+        for _ in 0..=MAX_REDIRECTS {
+            let mut request = self.client.request(method.clone(), url.as_str());
+            if let Some(cookie) = session.header(&url) {
+                request = request.header(reqwest::header::COOKIE, cookie);
+            }
+            if method == Method::POST {
+                request = request.form(form);
+            }
+            let response = request.send().await?;
+            session.set(&url, response.headers());
+            let status = response.status();
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .filter(|_| status.is_redirection())
+                .and_then(|location| location.to_str().ok());
+            let Some(location) = location else {
+                let mut headers = response.headers().clone();
+                let body = encoding::decode(&url, &mut headers, response.bytes().await?)?;
+                return Ok((url, status, body));
+            };
+            url = url.join(location)?;
+            // Only these redirects repeat the method and body of a request
+            if status != StatusCode::TEMPORARY_REDIRECT && status != StatusCode::PERMANENT_REDIRECT
+            {
+                method = Method::GET;
+            }
+        }
+        Err(CrawlerError::Login(format!(
+            "more than {} redirects",
+            MAX_REDIRECTS
+        )))
+    }
+}
+
+fn parse_url(url: &str) -> Result<url::Url> {
+    url::Url::parse(url)
+        .map_err(|err| CrawlerError::Login(format!("invalid url `{}`: {}", url, err)))
+}
@@ -0,0 +1,274 @@
+//! Index of the pages persisted in a storage directory.
+//!
+//! Every write to a [`Storage`](crate::Storage) appends an entry
+//! to the `manifest.jsonl` file in the storage root, one JSON
+//! object per line.
+//!
+//! With the [versioned](crate::StorageLayout::Versioned) layout
+//! the manifest doubles as a CDX-like index, mapping each url to
+//! the timestamped files of its stored versions. With the
+//! [content-addressed](crate::StorageLayout::ContentAddressed) one,
+//! it is the index of the url aliases of each stored content.
+//!
+//! Entries also record the HTTP caching headers the page was served
+//! with, so that revisits can reason about its freshness, along with
+//! any other response headers captured by the storage.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::header::{self, HeaderMap};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::error::{CrawlerError, Result};
+use crate::frontier::SeedMetadata;
+use crate::parse::ContentStats;
+
+/// Name of the manifest file in the storage root
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+
+/// Number of bytes an encrypted file adds to the content
+pub const ENCRYPTION_OVERHEAD: usize = 32;
+
+/// A record of a single stored page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub url: url::Url,
+    /// Path of the stored page relative to the storage root
+    pub path: PathBuf,
+    /// Hex-encoded SHA-1 digest of the stored content
+    pub hash: String,
+    /// Size of the stored content in bytes
+    pub size: usize,
+    /// Time of the fetch in milliseconds since the epoch
+    pub fetched_at: i64,
+    /// The caching headers of the response, if any
+    #[serde(default, skip_serializing_if = "CacheHeaders::is_empty")]
+    pub cache: CacheHeaders,
+    /// The captured response headers, by lowercase name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// The metadata of the seed the page was reached from
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: SeedMetadata,
+    /// The size of the response body, when the stored content is
+    /// truncated to the max size of a page, or empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated_from: Option<usize>,
+    /// Whether the page is binary, and so stored verbatim without
+    /// being scraped
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub binary: bool,
+    /// Whether the stored file is encrypted, the hash and
+    /// size describing the plain content
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+    /// The statistics of the content of an HTML page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ContentStats>,
+}
+
+impl ManifestEntry {
+    pub fn new(url: url::Url, path: PathBuf, content: &[u8], fetched_at: i64) -> Self {
+        Self {
+            url,
+            path,
+            hash: content_hash(content),
+            size: content.len(),
+            fetched_at,
+            cache: CacheHeaders::default(),
+            headers: BTreeMap::new(),
+            metadata: SeedMetadata::new(),
+            truncated_from: None,
+            binary: false,
+            encrypted: false,
+            stats: None,
+        }
+    }
+
+    pub fn with_cache(mut self, cache: CacheHeaders) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn with_headers(mut self, headers: BTreeMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: SeedMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn with_truncated_from(mut self, truncated_from: Option<usize>) -> Self {
+        self.truncated_from = truncated_from;
+        self
+    }
+
+    pub fn with_binary(mut self, binary: bool) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    pub fn with_encrypted(mut self, encrypted: bool) -> Self {
+        self.encrypted = encrypted;
+        self
+    }
+
+    pub fn with_stats(mut self, stats: Option<ContentStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// The size of the stored file in bytes
+    pub fn stored_size(&self) -> usize {
+        match self.encrypted {
+            true => self.size + ENCRYPTION_OVERHEAD,
+            false => self.size,
+        }
+    }
+}
+
+/// The HTTP caching headers a page was served with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheHeaders {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl CacheHeaders {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            cache_control: get(header::CACHE_CONTROL),
+            expires: get(header::EXPIRES),
+            etag: get(header::ETAG),
+            last_modified: get(header::LAST_MODIFIED),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// How long a page fetched at `fetched_at` stays fresh, after
+    /// `Cache-Control` or, failing that, `Expires`.
+    ///
+    /// Pages that must be revalidated are never fresh, while `None`
+    /// means that the headers do not tell.
+    pub fn freshness(&self, fetched_at: i64) -> Option<Duration> {
+        if let Some(cache_control) = &self.cache_control {
+            let mut max_age = None;
+            for directive in cache_control.split(',').map(str::trim) {
+                let directive = directive.to_ascii_lowercase();
+                if directive == "no-cache" || directive == "no-store" {
+                    return Some(Duration::ZERO);
+                }
+                if let Some(secs) = directive.strip_prefix("max-age=") {
+                    max_age = secs.trim_matches('"').parse().ok().map(Duration::from_secs);
+                }
+            }
+            if max_age.is_some() {
+                return max_age;
+            }
+        }
+        let expires = chrono::DateTime::parse_from_rfc2822(self.expires.as_deref()?)
+            .map(|expires| expires.timestamp_millis())
+            // Invalid dates, such as `0`, mean already expired
+            .unwrap_or(fetched_at);
+        Some(Duration::from_millis(
+            expires.saturating_sub(fetched_at).max(0) as u64,
+        ))
+    }
+}
+
+/// The hex-encoded SHA-1 digest of some content
+pub fn content_hash(content: &[u8]) -> String {
+    hex::encode(Sha1::digest(content).as_slice())
+}
+
+/// The list of entries recorded in a storage directory
+#[derive(Debug, Default, Clone)]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn new(entries: Vec<ManifestEntry>) -> Self {
+        Self { entries }
+    }
+
+    /// Load the manifest of the storage directory `root`.
+    ///
+    /// A directory without a manifest yields an empty one.
+    pub async fn load(root: &Path) -> Result<Self> {
+        match Self::load_file(&root.join(MANIFEST_FILE)).await {
+            Err(CrawlerError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            result => result,
+        }
+    }
+
+    /// Load the manifest file at `path`
+    pub async fn load_file(path: &Path) -> Result<Self> {
+        let contents = tokio::fs::read_to_string(path).await?;
+        let entries = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Replace the manifest of the storage directory `root` with this one
+    pub async fn write(&self, root: &Path) -> Result<()> {
+        let mut contents = Vec::new();
+        for entry in &self.entries {
+            serde_json::to_writer(&mut contents, entry)?;
+            contents.push(b'\n');
+        }
+        let tmp_path = root.join(MANIFEST_FILE).with_extension("jsonl.tmp");
+        tokio::fs::write(&tmp_path, contents).await?;
+        tokio::fs::rename(tmp_path, root.join(MANIFEST_FILE)).await?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// The stored versions of `url`, oldest first
+    pub fn versions(&self, url: &url::Url) -> Vec<&ManifestEntry> {
+        let mut versions: Vec<_> = self.entries.iter().filter(|e| &e.url == url).collect();
+        versions.sort_by_key(|entry| entry.fetched_at);
+        versions
+    }
+
+    /// The most recent entry of each recorded url
+    pub fn latest(&self) -> HashMap<&url::Url, &ManifestEntry> {
+        let mut latest: HashMap<&url::Url, &ManifestEntry> = HashMap::new();
+        for entry in &self.entries {
+            match latest.get(&entry.url) {
+                Some(current) if current.fetched_at > entry.fetched_at => {}
+                _ => {
+                    latest.insert(&entry.url, entry);
+                }
+            }
+        }
+        latest
+    }
+}
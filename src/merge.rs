@@ -0,0 +1,299 @@
+//! Combination of several storage directories into one.
+//!
+//! Crawls run in parallel shards can be merged afterwards: the
+//! resulting manifest is the union of the versions of the source
+//! manifests, and when more than one source stored the same url the
+//! conflict is resolved by the fetch timestamps according to a
+//! [`MergePolicy`].
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::Result;
+use crate::manifest::{Manifest, ManifestEntry};
+
+/// How to choose among the versions of a url stored in several sources
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep the most recently fetched version
+    #[default]
+    Newest,
+    /// Keep the earliest fetched version
+    Oldest,
+}
+
+impl FromStr for MergePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "newest" => Ok(Self::Newest),
+            "oldest" => Ok(Self::Oldest),
+            other => Err(format!(
+                "unsupported merge policy `{}`, expected `newest` or `oldest`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MergePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Newest => write!(f, "newest"),
+            Self::Oldest => write!(f, "oldest"),
+        }
+    }
+}
+
+/// The outcome of a merge
+#[derive(Debug, Clone, Default)]
+pub struct MergeSummary {
+    /// Number of pages in the merged manifest
+    pub pages: usize,
+    /// Number of conflicting versions resolved by the policy
+    pub conflicts: usize,
+    /// Number of versions stored under another path, their path
+    /// being taken by a different page in the output
+    pub renamed: usize,
+}
+
+/// Merge the crawls stored in `sources` into the storage directory `output`.
+///
+/// Every version recorded in `output` or a source takes part in the
+/// merge, a version recorded in several of them being kept once. When
+/// several of them stored the same url, the policy chooses among their
+/// latest versions the one latest in the merged manifest: the versions
+/// fetched after it are dropped, and the earlier ones are kept. A copied
+/// version whose path is taken by a different page of the output is
+/// stored under a new path.
+pub async fn merge(
+    sources: &[PathBuf],
+    output: &Path,
+    policy: MergePolicy,
+) -> Result<MergeSummary> {
+    tokio::fs::create_dir_all(output).await?;
+    let mut summary = MergeSummary::default();
+    // The versions of every url, with the directory storing them
+    let mut versions: HashMap<url::Url, Vec<(PathBuf, ManifestEntry)>> = HashMap::new();
+    // The latest version of every url in each directory
+    let mut latest: HashMap<url::Url, Vec<i64>> = HashMap::new();
+    let dirs = std::iter::once(output).chain(sources.iter().map(PathBuf::as_path));
+    for dir in dirs {
+        let manifest = Manifest::load(dir).await?;
+        for entry in manifest.latest().into_values() {
+            latest
+                .entry(entry.url.clone())
+                .or_default()
+                .push(entry.fetched_at);
+        }
+        for entry in manifest.entries() {
+            let url_versions = versions.entry(entry.url.clone()).or_default();
+            let duplicate = url_versions.iter().any(|(_, version)| {
+                version.fetched_at == entry.fetched_at && version.hash == entry.hash
+            });
+            if !duplicate {
+                url_versions.push((dir.to_path_buf(), entry.clone()));
+            }
+        }
+    }
+
+    let mut selected = Vec::new();
+    for (url, url_versions) in versions {
+        let candidates = latest.get_mut(&url).unwrap();
+        // A version recorded in several directories is no conflict
+        candidates.sort_unstable();
+        candidates.dedup();
+        let cutoff = match policy {
+            MergePolicy::Newest => candidates.iter().max(),
+            MergePolicy::Oldest => candidates.iter().min(),
+        }
+        .copied()
+        .unwrap_or(i64::MAX);
+        summary.conflicts += candidates.len() - 1;
+        selected.extend(
+            url_versions
+                .into_iter()
+                .filter(|(_, entry)| entry.fetched_at <= cutoff),
+        );
+    }
+    // The pages of the output keep their paths, the copied ones
+    // claiming theirs in a stable order
+    selected.sort_by(|(a_dir, a), (b_dir, b)| {
+        (a_dir != output)
+            .cmp(&(b_dir != output))
+            .then_with(|| a.url.cmp(&b.url))
+            .then(a.fetched_at.cmp(&b.fetched_at))
+    });
+    let mut claimed: HashMap<PathBuf, String> = HashMap::new();
+    let mut entries = Vec::with_capacity(selected.len());
+    for (dir, mut entry) in selected {
+        if dir == output {
+            claimed.insert(entry.path.clone(), entry.hash.clone());
+            entries.push(entry);
+            continue;
+        }
+        let source = dir.join(&entry.path);
+        let mut path = entry.path.clone();
+        let mut n = 0;
+        let copy = loop {
+            match claimed.get(&path) {
+                None => break true,
+                // The same content, e.g. of a content-addressed crawl
+                Some(hash) if *hash == entry.hash => break false,
+                Some(_) => {
+                    n += 1;
+                    path = renamed(&entry.path, n);
+                }
+            }
+        };
+        if n > 0 {
+            summary.renamed += 1;
+            tracing::debug!("  -> Storing {} as {:?}", entry.url, path);
+        }
+        if copy {
+            let target = output.join(&path);
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::copy(source, target).await?;
+            claimed.insert(path.clone(), entry.hash.clone());
+        }
+        entry.path = path;
+        entries.push(entry);
+    }
+    entries.sort_by(|a, b| a.url.cmp(&b.url).then(a.fetched_at.cmp(&b.fetched_at)));
+    summary.pages = entries
+        .iter()
+        .map(|entry| &entry.url)
+        .collect::<HashSet<_>>()
+        .len();
+    Manifest::new(entries).write(output).await?;
+    Ok(summary)
+}
+
+/// `path` with a numbered suffix `n` before its extension, if any
+fn renamed(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, n, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A storage directory holding `pages` of url, path, content
+    /// and fetch time
+    async fn shard(root: &Path, name: &str, pages: &[(&str, &str, &str, i64)]) -> PathBuf {
+        let dir = root.join(name);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let mut entries = Vec::new();
+        for (url, path, content, fetched_at) in pages {
+            tokio::fs::write(dir.join(path), content).await.unwrap();
+            let url = url::Url::parse(url).unwrap();
+            entries.push(ManifestEntry::new(
+                url,
+                path.into(),
+                content.as_bytes(),
+                *fetched_at,
+            ));
+        }
+        Manifest::new(entries).write(&dir).await.unwrap();
+        dir
+    }
+
+    /// The content of the latest version of every url of `dir`
+    async fn latest(dir: &Path) -> Vec<(String, String)> {
+        let manifest = Manifest::load(dir).await.unwrap();
+        let mut pages = Vec::new();
+        for entry in manifest.latest().into_values() {
+            let content = tokio::fs::read_to_string(dir.join(&entry.path))
+                .await
+                .unwrap();
+            pages.push((entry.url.to_string(), content));
+        }
+        pages.sort();
+        pages
+    }
+
+    async fn shards(root: &Path) -> Vec<PathBuf> {
+        vec![
+            shard(
+                root,
+                "a",
+                &[
+                    ("https://example.com/a", "a.html", "a-old", 1),
+                    ("https://example.com/b", "b.html", "b", 1),
+                ],
+            )
+            .await,
+            shard(
+                root,
+                "b",
+                &[("https://example.com/a", "a.html", "a-new", 2)],
+            )
+            .await,
+        ]
+    }
+
+    #[tokio::test]
+    async fn newest_versions_win_and_colliding_paths_are_renamed() {
+        let root = tempfile::tempdir().unwrap();
+        let sources = shards(root.path()).await;
+        let output = root.path().join("merged");
+        let summary = merge(&sources, &output, MergePolicy::Newest).await.unwrap();
+        assert_eq!(
+            (summary.pages, summary.conflicts, summary.renamed),
+            (2, 1, 1)
+        );
+        assert_eq!(
+            latest(&output).await,
+            [
+                ("https://example.com/a".to_string(), "a-new".to_string()),
+                ("https://example.com/b".to_string(), "b".to_string()),
+            ]
+        );
+        let manifest = Manifest::load(&output).await.unwrap();
+        let a = url::Url::parse("https://example.com/a").unwrap();
+        let paths: Vec<_> = manifest
+            .versions(&a)
+            .iter()
+            .map(|e| e.path.clone())
+            .collect();
+        assert_eq!(paths, [PathBuf::from("a.html"), PathBuf::from("a_1.html")]);
+    }
+
+    #[tokio::test]
+    async fn oldest_versions_drop_the_later_ones() {
+        let root = tempfile::tempdir().unwrap();
+        let sources = shards(root.path()).await;
+        let output = root.path().join("merged");
+        let summary = merge(&sources, &output, MergePolicy::Oldest).await.unwrap();
+        assert_eq!(
+            (summary.pages, summary.conflicts, summary.renamed),
+            (2, 1, 0)
+        );
+        assert_eq!(latest(&output).await[0].1, "a-old");
+        assert_eq!(Manifest::load(&output).await.unwrap().entries().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn merging_again_keeps_every_version_once() {
+        let root = tempfile::tempdir().unwrap();
+        let sources = shards(root.path()).await;
+        let output = root.path().join("merged");
+        merge(&sources, &output, MergePolicy::Newest).await.unwrap();
+        let summary = merge(&sources, &output, MergePolicy::Newest).await.unwrap();
+        // The shards still disagree on `/a`, but no version is copied twice
+        assert_eq!(
+            (summary.pages, summary.conflicts, summary.renamed),
+            (2, 1, 0)
+        );
+        assert_eq!(Manifest::load(&output).await.unwrap().entries().len(), 3);
+    }
+}
@@ -0,0 +1,216 @@
+//! Long-running revisiting of stored pages.
+//!
+//! The monitor loads the manifest of an existing storage directory
+//! and periodically refetches every recorded url. The revisit interval
+//! of each url adapts to how often its content is observed to change:
+//! it is halved whenever a change is detected and doubled otherwise,
+//! within the configured bounds. A page is not revisited before its
+//! caching headers consider it stale, up to the max interval.
+//!
+//! At most `max_tasks` pages are revisited at a time, the other due
+//! pages waiting for a slot.
+//!
+//! Detected changes are stored, and recorded along with a diff in the
+//! `changes.jsonl` file of the storage root.
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::config::Limits;
+use crate::diff;
+use crate::error::Result;
+use crate::executor;
+use crate::manifest::{content_hash, CacheHeaders, Manifest, ManifestEntry};
+use crate::storage::PageAnnotations;
+use crate::{Scraper, Storage};
+
+/// Name of the file recording the detected changes
+pub const CHANGES_FILE: &str = "changes.jsonl";
+
+const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_MAX_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A change detected on a revisited page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageChange {
+    pub url: url::Url,
+    /// Time of the detection in milliseconds since the epoch
+    pub detected_at: i64,
+    pub old_hash: String,
+    pub new_hash: String,
+    /// Unified diff of the old and new content
+    pub diff: String,
+    /// The revisit interval adapted to the change, in seconds
+    pub interval_secs: u64,
+    /// The sitemap `changefreq` corresponding to the adapted interval
+    pub changefreq: String,
+}
+
+/// The revisit schedule of a single url
+#[derive(Debug)]
+struct Schedule {
+    url: url::Url,
+    /// The entry of the last stored version
+    entry: ManifestEntry,
+    interval: Duration,
+    /// How long the last fetched version stays fresh, if known
+    freshness: Option<Duration>,
+    due: Instant,
+}
+
+/// Periodically revisits the pages of a storage directory
+pub struct Monitor {
+    storage: Arc<Storage>,
+    scraper: Scraper,
+    min_interval: Duration,
+    max_interval: Duration,
+    max_tasks: usize,
+}
+
+impl Monitor {
+    /// Monitor the pages stored in `storage`.
+    ///
+    /// New versions are written according to the layout of `storage`.
+    pub fn new(storage: Storage, scraper: Option<Scraper>) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            scraper: scraper.unwrap_or_default(),
+            min_interval: DEFAULT_MIN_INTERVAL,
+            max_interval: DEFAULT_MAX_INTERVAL,
+            max_tasks: Limits::default().max_tasks,
+        }
+    }
+
+    /// Bound the adaptive revisit interval of each url
+    pub fn with_intervals(mut self, min_interval: Duration, max_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self.max_interval = max_interval.max(min_interval);
+        self
+    }
+
+    /// Revisit at most `max_tasks` pages at a time
+    pub fn with_max_tasks(mut self, max_tasks: usize) -> Self {
+        self.max_tasks = max_tasks.max(1);
+        self
+    }
+
+    /// Revisit the stored pages until the future is dropped
+    pub async fn run(self) -> Result<()> {
+        executor::compat(self.monitor()).await
+    }
+
+    async fn monitor(self) -> Result<()> {
+        let _lease = self.storage.lease().await?;
+        let manifest = Manifest::load(self.storage.path()).await?;
+        let now = Instant::now();
+        let mut schedules: Vec<_> = manifest
+            .latest()
+            .into_values()
+            .map(|entry| {
+                let mut schedule = Schedule {
+                    url: entry.url.clone(),
+                    entry: entry.clone(),
+                    interval: self.min_interval,
+                    freshness: entry.cache.freshness(entry.fetched_at),
+                    due: now,
+                };
+                schedule.due = now + self.delay(&schedule);
+                schedule
+            })
+            .collect();
+        tracing::info!("==> Monitoring {} pages", schedules.len());
+        while let Some(next_due) = schedules.iter().map(|s| s.due).min() {
+            tokio::time::sleep_until(next_due).await;
+            let now = Instant::now();
+            let revisits = schedules
+                .iter_mut()
+                .filter(|schedule| schedule.due <= now)
+                .map(|schedule| self.revisit(schedule));
+            stream::iter(revisits)
+                .buffer_unordered(self.max_tasks)
+                .collect::<Vec<()>>()
+                .await;
+        }
+        Ok(())
+    }
+
+    async fn revisit(&self, schedule: &mut Schedule) {
+        match self.detect_change(schedule).await {
+            Ok(Some(change)) => {
+                tracing::info!("==> Changed {} ({})", change.url, change.changefreq);
+                if let Err(err) = self.storage.append_record(CHANGES_FILE, &change).await {
+                    tracing::warn!("error recording change: {:?}", err);
+                }
+            }
+            Ok(None) => tracing::debug!("==> Unchanged {}", schedule.url),
+            Err(err) => tracing::warn!("error revisiting page {}: {:?}", schedule.url, err),
+        }
+        schedule.due = Instant::now() + self.delay(schedule);
+    }
+
+    /// The delay of the next revisit, honouring the freshness
+    /// of the page within the max interval
+    fn delay(&self, schedule: &Schedule) -> Duration {
+        let freshness = schedule.freshness.unwrap_or_default();
+        schedule.interval.max(freshness.min(self.max_interval))
+    }
+
+    async fn detect_change(&self, schedule: &mut Schedule) -> Result<Option<PageChange>> {
+        let page = self.scraper.fetch_page(&schedule.url).await?;
+        let cache = CacheHeaders::from_headers(&page.headers);
+        schedule.freshness = cache.freshness(page.fetched_at);
+        let binary = page.is_binary();
+        let body = page.body;
+        let new_hash = content_hash(&body);
+        if new_hash == schedule.entry.hash {
+            schedule.interval = (schedule.interval * 2).min(self.max_interval);
+            return Ok(None);
+        }
+        schedule.interval = (schedule.interval / 2).max(self.min_interval);
+        let diff = match schedule.entry.binary || binary {
+            true => diff::binary(&schedule.entry.hash, &new_hash),
+            false => {
+                let old = self.storage.read(&schedule.entry).await?;
+                diff::unified(
+                    &String::from_utf8_lossy(&old),
+                    &String::from_utf8_lossy(&body),
+                    &schedule.entry.hash,
+                    &new_hash,
+                )
+            }
+        };
+        let entry = self
+            .storage
+            .serialize(
+                &body,
+                &PageAnnotations::new(schedule.url.clone()).with_headers(page.headers.clone()),
+            )
+            .await?;
+        let old_hash = std::mem::replace(&mut schedule.entry, entry).hash;
+        Ok(Some(PageChange {
+            url: schedule.url.clone(),
+            detected_at: chrono::Utc::now().timestamp_millis(),
+            old_hash,
+            new_hash,
+            diff,
+            interval_secs: schedule.interval.as_secs(),
+            changefreq: changefreq(schedule.interval).to_string(),
+        }))
+    }
+}
+
+/// Map a revisit interval to the closest sitemap `changefreq` value
+pub fn changefreq(interval: Duration) -> &'static str {
+    const HOUR: u64 = 60 * 60;
+    match interval.as_secs() {
+        secs if secs < HOUR => "always",
+        secs if secs < 24 * HOUR => "hourly",
+        secs if secs < 7 * 24 * HOUR => "daily",
+        secs if secs < 30 * 24 * HOUR => "weekly",
+        secs if secs < 365 * 24 * HOUR => "monthly",
+        _ => "yearly",
+    }
+}
@@ -0,0 +1,209 @@
+//! Readable file names of the stored pages.
+//!
+//! The pages of a flat or versioned storage are named by the hash of their
+//! url by default. A naming template names them instead by parts of their
+//! url, e.g. `{host}/{path}-{hash8}.html`, with the placeholders
+//!
+//! * `{host}`: the host of the url, with its port if not the default
+//! * `{path}`: the path of the url, `index` for a directory
+//! * `{query}`: the query of the url, if any
+//! * `{hash}` and `{hash8}`: the hash of the url, and its first 8 digits
+//!
+//! Every component of a rendered path is sanitized to letters, digits,
+//! `.`, `-` and `_`, and kept within the storage root.
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Max number of bytes of a component of a rendered path
+const MAX_COMPONENT_BYTES: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    Host,
+    Path,
+    Query,
+    Hash,
+    Hash8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// A template of the paths of the stored pages, relative to the storage root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamingTemplate {
+    template: String,
+    parts: Vec<Part>,
+}
+
+impl FromStr for NamingTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in naming template `{}`", s))?;
+            let placeholder = match &rest[start + 1..start + end] {
+                "host" => Placeholder::Host,
+                "path" => Placeholder::Path,
+                "query" => Placeholder::Query,
+                "hash" => Placeholder::Hash,
+                "hash8" => Placeholder::Hash8,
+                other => {
+                    return Err(format!(
+                        "unsupported naming placeholder `{{{}}}`, expected `{{host}}`, \
+                         `{{path}}`, `{{query}}`, `{{hash}}` or `{{hash8}}`",
+                        other
+                    ))
+                }
+            };
+            parts.push(Part::Placeholder(placeholder));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if !parts
+            .iter()
+            .any(|part| matches!(part, Part::Placeholder(_)))
+        {
+            return Err(format!(
+                "naming template `{}` names every page alike, expected a placeholder",
+                s
+            ));
+        }
+        Ok(Self {
+            template: s.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for NamingTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+impl NamingTemplate {
+    /// The path of the page of `url`, whose hash is the hex digest `hash`,
+    /// if any component remains once sanitized
+    pub fn render(&self, url: &url::Url, hash: &str) -> Option<PathBuf> {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => rendered.push_str(literal),
+                Part::Placeholder(Placeholder::Host) => {
+                    rendered.push_str(url.host_str().unwrap_or("_"));
+                    if let Some(port) = url.port() {
+                        rendered.push_str(&format!("_{}", port));
+                    }
+                }
+                Part::Placeholder(Placeholder::Path) => {
+                    let path = url.path().trim_start_matches('/');
+                    rendered.push_str(path);
+                    if path.is_empty() || path.ends_with('/') {
+                        rendered.push_str("index");
+                    }
+                }
+                Part::Placeholder(Placeholder::Query) => {
+                    // Kept within its component
+                    rendered.push_str(&url.query().unwrap_or_default().replace('/', "_"))
+                }
+                Part::Placeholder(Placeholder::Hash) => rendered.push_str(hash),
+                Part::Placeholder(Placeholder::Hash8) => {
+                    rendered.push_str(&hash[..hash.len().min(8)])
+                }
+            }
+        }
+        let path: PathBuf = rendered
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(sanitize)
+            .collect();
+        (!path.as_os_str().is_empty()).then_some(path)
+    }
+}
+
+/// A component of a path, restricted to a safe set of characters and
+/// never referring to the current or parent directory
+fn sanitize(component: &str) -> String {
+    let mut sanitized: String = component
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    sanitized.truncate(MAX_COMPONENT_BYTES);
+    if sanitized.chars().all(|c| c == '.') {
+        sanitized = sanitized.replace('.', "_");
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef";
+
+    fn render(template: &str, url: &str) -> Option<PathBuf> {
+        let template: NamingTemplate = template.parse().unwrap();
+        template.render(&url::Url::parse(url).unwrap(), HASH)
+    }
+
+    #[test]
+    fn placeholders_are_rendered_by_url() {
+        let template = "{host}/{path}-{hash8}.html";
+        assert_eq!(
+            render(template, "https://example.com/docs/intro"),
+            Some(PathBuf::from("example.com/docs/intro-01234567.html"))
+        );
+        assert_eq!(
+            render(template, "http://example.com:8080/docs/"),
+            Some(PathBuf::from("example.com_8080/docs/index-01234567.html"))
+        );
+        assert_eq!(
+            render("{path}_{query}", "https://example.com/?q=a/b"),
+            Some(PathBuf::from("index_q_a_b"))
+        );
+        assert_eq!(
+            render("{hash}", "https://example.com/"),
+            Some(PathBuf::from(HASH))
+        );
+    }
+
+    #[test]
+    fn components_are_sanitized() {
+        assert_eq!(sanitize("a b&c=d.html"), "a_b_c_d.html");
+        assert_eq!(sanitize("übersicht"), "_bersicht");
+        assert_eq!(sanitize("."), "_");
+        assert_eq!(sanitize(".."), "__");
+        assert_eq!(sanitize(&"a".repeat(300)).len(), MAX_COMPONENT_BYTES);
+        assert_eq!(
+            render("{query}/../{path}", "https://example.com/a?.."),
+            Some(PathBuf::from("__/__/a"))
+        );
+        assert_eq!(render("/{query}/", "https://example.com/"), None);
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        assert!("{host".parse::<NamingTemplate>().is_err());
+        assert!("{scheme}/{path}".parse::<NamingTemplate>().is_err());
+        assert!("page.html".parse::<NamingTemplate>().is_err());
+        let template: NamingTemplate = "{host}/{path}".parse().unwrap();
+        assert_eq!(template.to_string(), "{host}/{path}");
+    }
+}
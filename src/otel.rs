@@ -0,0 +1,38 @@
+//! Export of the tracing spans of the crawls to OpenTelemetry.
+//!
+//! The spans of a crawl, `crawl` carrying its run id, `page` carrying the
+//! url and depth of each visited page, and its `fetch`, `scrape` and
+//! `store` steps, are exported over OTLP/HTTP to a collector, so that
+//! crawls running inside services show up in distributed tracing. The
+//! standard `OTEL_EXPORTER_OTLP_*` environment variables take precedence
+//! over the given endpoint.
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::Tracer;
+use opentelemetry_sdk::Resource;
+
+use crate::error::{CrawlerError, Result};
+
+/// Default OTLP/HTTP endpoint of a local collector
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:4318";
+
+/// A tracer batching the spans to the collector at `endpoint`, which
+/// must be installed within a tokio runtime
+pub fn tracer(endpoint: &str) -> Result<Tracer> {
+    let resource = Resource::new(vec![KeyValue::new("service.name", env!("CARGO_PKG_NAME"))]);
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(opentelemetry_sdk::trace::config().with_resource(resource))
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+        .map_err(|err| CrawlerError::Telemetry(err.to_string()))
+}
+
+/// Export the spans still batched, before the process exits
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -0,0 +1,174 @@
+//! The result of visiting a webpage
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::{StatusCode, Version};
+use serde::Serialize;
+
+use crate::extract::Extraction;
+use crate::frontier::SeedMetadata;
+use crate::parse::{Alternate, ContentStats};
+use crate::robots::RobotsDirectives;
+
+/// Number of leading bytes of a body sniffed for NUL bytes
+const SNIFF_BYTES: usize = 1024;
+
+/// A fetched and scraped webpage
+#[derive(Debug, Clone)]
+pub struct CrawledPage {
+    /// The requested url
+    pub url: url::Url,
+    /// The url of the response, after following redirects
+    pub final_url: url::Url,
+    pub status: StatusCode,
+    /// The HTTP version of the response
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// The links found in the body
+    pub discovered_links: Vec<url::Url>,
+    /// The priorities of the discovered links to follow, if not the default
+    pub priorities: HashMap<url::Url, i32>,
+    /// The images referenced by the page, including responsive
+    /// candidates and the backgrounds of inline styles
+    pub assets: Vec<url::Url>,
+    /// The sources of the frames and iframes of the page
+    pub frames: Vec<url::Url>,
+    /// The alternate-language versions declared by the page
+    pub alternates: Vec<Alternate>,
+    /// The canonical url declared by the page
+    pub canonical: Option<url::Url>,
+    /// The AMP and mobile variants collapsed into the page, when
+    /// collapsing variants
+    pub variants: Vec<url::Url>,
+    /// The `X-Robots-Tag` and meta robots directives of the page
+    pub robots: RobotsDirectives,
+    /// The relevance of the page to the topic of a focused crawl
+    pub relevance: Option<u32>,
+    /// The records of the extractors
+    pub extractions: Vec<Extraction>,
+    /// The statistics of the content of an HTML page
+    pub stats: Option<ContentStats>,
+    /// The number of links followed from a seed to reach the page
+    pub depth: usize,
+    /// The metadata of the seed the page was reached from
+    pub metadata: SeedMetadata,
+    /// Time of the fetch in milliseconds since the epoch
+    pub fetched_at: i64,
+    /// Time from sending the request to receiving the response headers
+    pub waited: Duration,
+    /// Time from sending the request to receiving the whole body
+    pub latency: Duration,
+}
+
+impl CrawledPage {
+    /// The media type of the page, e.g. `application/json`, if declared
+    pub fn content_type(&self) -> Option<String> {
+        media_type(&self.headers)
+    }
+
+    /// Whether the body of the page is binary, see [`is_binary`]
+    pub fn is_binary(&self) -> bool {
+        is_binary(self.content_type().as_deref(), &self.body)
+    }
+}
+
+/// The lowercase media type of the `Content-Type` of `headers`,
+/// without its parameters
+pub fn media_type(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    let essence = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    (!essence.is_empty()).then_some(essence)
+}
+
+/// Whether a `body` of the media type `content_type` is binary. The body of
+/// a textual or undeclared type is binary only if its first bytes hold a
+/// NUL byte, while the body of any other type is binary.
+pub fn is_binary(content_type: Option<&str>, body: &[u8]) -> bool {
+    let textual = content_type.is_none_or(|content_type| {
+        content_type.starts_with("text/")
+            || content_type.ends_with("+json")
+            || content_type.ends_with("+xml")
+            || matches!(
+                content_type,
+                "application/json"
+                    | "application/xml"
+                    | "application/javascript"
+                    | "application/ecmascript"
+            )
+    });
+    !textual || body.iter().take(SNIFF_BYTES).any(|&byte| byte == 0)
+}
+
+/// The summary of a visited page published to the consumers of a crawl
+#[derive(Debug, Clone, Serialize)]
+pub struct PageRecord {
+    pub url: url::Url,
+    pub final_url: url::Url,
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// The length of the body in bytes
+    pub size: usize,
+    /// Whether the body is binary, and so not scraped
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub binary: bool,
+    pub depth: usize,
+    /// The metadata of the seed the page was reached from
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: SeedMetadata,
+    /// Time of the fetch in milliseconds since the epoch
+    pub fetched_at: i64,
+    pub links: Vec<url::Url>,
+}
+
+impl From<&CrawledPage> for PageRecord {
+    fn from(page: &CrawledPage) -> Self {
+        Self {
+            url: page.url.clone(),
+            final_url: page.final_url.clone(),
+            status: page.status.as_u16(),
+            content_type: page
+                .headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from),
+            size: page.body.len(),
+            binary: page.is_binary(),
+            depth: page.depth,
+            metadata: page.metadata.clone(),
+            fetched_at: page.fetched_at,
+            links: page.discovered_links.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_types_drop_their_parameters() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(media_type(&headers), None);
+        headers.insert(CONTENT_TYPE, "Text/HTML; charset=utf-8".parse().unwrap());
+        assert_eq!(media_type(&headers).as_deref(), Some("text/html"));
+    }
+
+    #[test]
+    fn binary_bodies_are_told_by_type_then_by_nul_bytes() {
+        assert!(is_binary(Some("image/png"), b"plain"));
+        assert!(is_binary(Some("application/octet-stream"), b""));
+        assert!(!is_binary(Some("text/html"), b"<p>text</p>"));
+        assert!(!is_binary(Some("application/ld+json"), b"{}"));
+        assert!(!is_binary(None, b"undeclared text"));
+        assert!(is_binary(None, b"PK\x03\x04\0\0"));
+        assert!(is_binary(Some("text/plain"), b"mislabelled\0"));
+
+        // Only the sniffed bytes are looked at
+        let mut body = vec![b'a'; SNIFF_BYTES];
+        body.push(0);
+        assert!(!is_binary(Some("text/plain"), &body));
+    }
+}
@@ -0,0 +1,811 @@
+//! Extraction of information from page bodies and headers
+use std::sync::OnceLock;
+
+use regex::Regex;
+use reqwest::header::{HeaderMap, LINK};
+use scraper::html::Select;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::robots::RobotsDirectives;
+use crate::simhash::Simhash;
+
+/// Max delay of a `<meta http-equiv="refresh">` to be treated as a redirect,
+/// rather than a periodic reload of the page
+pub const MAX_REFRESH_SECS: f64 = 30.0;
+
+/// Navigations of `onclick` handlers, e.g. `location.href='...'`,
+/// `window.location = "..."` or `location.assign('...')`
+fn onclick_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r#"location(?:\.href)?\s*=\s*['"]([^'"]+)['"]|location\.(?:assign|replace)\(\s*['"]([^'"]+)['"]"#,
+        )
+        .unwrap()
+    })
+}
+
+/// An alternate-language version of a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alternate {
+    /// The language code of the `hreflang` attribute, e.g. `en-US`
+    pub hreflang: String,
+    pub url: url::Url,
+}
+
+/// A subresource declaring its expected digests with an `integrity`
+/// attribute, for Subresource Integrity checks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityRef {
+    pub url: url::Url,
+    /// The hash expressions of the attribute, e.g. `sha384-<base64 digest>`
+    pub integrity: Vec<String>,
+    /// The `crossorigin` attribute, if any
+    pub crossorigin: Option<String>,
+}
+
+/// A form of a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Form {
+    /// The `action` attribute, as written in the page
+    pub action: Option<String>,
+    /// The lowercase `method` attribute, `get` by default
+    pub method: String,
+    pub inputs: Vec<FormInput>,
+}
+
+/// A named control of a form
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormInput {
+    pub name: String,
+    /// The `type` of an `input`, or the element name of other controls
+    pub kind: String,
+}
+
+/// Statistics of the content of a page, for content audits
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentStats {
+    /// Number of words of the visible text
+    pub words: usize,
+    /// Number of `<h1>` to `<h6>` headings, by level
+    pub headings: [usize; 6],
+    /// Number of links to the host of the page
+    pub internal_links: usize,
+    /// Number of links to other hosts
+    pub external_links: usize,
+    /// Ratio of the bytes of the visible text to the bytes of the page
+    pub text_ratio: f64,
+    /// The fingerprint of the visible text, if any,
+    /// to find [near-duplicates](crate::simhash)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simhash: Option<Simhash>,
+}
+
+/// Elements whose text is not displayed
+const HIDDEN_ELEMENTS: [&str; 5] = ["script", "style", "noscript", "template", "head"];
+
+/// An HTML page parsed once for all the extractions.
+///
+/// The contents of the `<template>` elements, including the declarative
+/// shadow roots of `<template shadowrootmode>`, are parsed along with the
+/// page, so that their elements are selected as those of the page.
+///
+/// The urls referenced by the page are absolute ones only, unless the
+/// document knows the url of the page with [`with_url`](Self::with_url),
+/// in which case relative references are resolved against its
+/// [`base`](Self::base).
+pub struct Document {
+    html: Html,
+    base_url: Option<url::Url>,
+}
+
+impl Document {
+    pub fn parse(page: &[u8]) -> Self {
+        Self {
+            html: Html::parse_document(&String::from_utf8_lossy(page)),
+            base_url: None,
+        }
+    }
+
+    /// Resolve the relative references of the page found at `url`
+    pub fn with_url(mut self, url: &url::Url) -> Self {
+        self.base_url = Some(self.base(url));
+        self
+    }
+
+    /// The url of `reference`, resolved against the base url of the page,
+    /// if known, without its fragment, which points within a page rather
+    /// than to another one
+    fn resolve(&self, reference: &str) -> Option<url::Url> {
+        let reference = reference.trim();
+        let mut url = match &self.base_url {
+            Some(base) => base.join(reference).ok()?,
+            None => url::Url::parse(reference).ok()?,
+        };
+        url.set_fragment(None);
+        Some(url)
+    }
+
+    /// The serialized HTML of the page
+    pub fn html(&self) -> String {
+        self.html.root_element().html()
+    }
+
+    /// The text of the page
+    pub fn text(&self) -> String {
+        self.html
+            .root_element()
+            .text()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The elements of the page matching `selector`
+    pub fn select<'a>(&'a self, selector: &'a Selector) -> Select<'a, 'a> {
+        self.html.select(selector)
+    }
+
+    /// Whether an element of the page matches `selector`
+    pub fn contains(&self, selector: &Selector) -> bool {
+        self.html.select(selector).next().is_some()
+    }
+
+    /// The absolute urls in the `attr` attribute of the elements matching `selector`
+    fn urls(&self, selector: &str, attr: &str) -> Vec<url::Url> {
+        let selector = Selector::parse(selector).unwrap();
+        self.html
+            .select(&selector)
+            .filter_map(|element| element.value().attr(attr))
+            .filter_map(|href| self.resolve(href))
+            .collect()
+    }
+
+    /// The absolute urls linked by the anchors of the page
+    pub fn links(&self) -> Vec<url::Url> {
+        self.urls("a", "href")
+    }
+
+    /// The absolute urls linked by the anchors of the page, with their
+    /// text, or the `alt` text of their images if they have none
+    pub fn anchors(&self) -> Vec<(url::Url, String)> {
+        let selector = Selector::parse("a[href]").unwrap();
+        let images = Selector::parse("img[alt]").unwrap();
+        self.html
+            .select(&selector)
+            .filter_map(|element| {
+                let url = self.resolve(element.value().attr("href")?)?;
+                let mut text = element.text().collect::<Vec<_>>().join(" ");
+                if text.trim().is_empty() {
+                    text = element
+                        .select(&images)
+                        .filter_map(|image| image.value().attr("alt"))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                }
+                Some((url, text.split_whitespace().collect::<Vec<_>>().join(" ")))
+            })
+            .collect()
+    }
+
+    /// The absolute urls linked by the areas of the image maps of the page
+    pub fn areas(&self) -> Vec<url::Url> {
+        self.urls("area", "href")
+    }
+
+    /// The absolute urls of the actions of the `GET` forms of the page
+    pub fn form_actions(&self) -> Vec<url::Url> {
+        self.forms()
+            .into_iter()
+            .filter(|form| form.method == "get")
+            .filter_map(|form| self.resolve(form.action.as_deref()?))
+            .collect()
+    }
+
+    /// The absolute urls navigated to by the simple `onclick` handlers
+    /// of the page, e.g. `location.href='...'`
+    pub fn onclick_links(&self) -> Vec<url::Url> {
+        let selector = Selector::parse("[onclick]").unwrap();
+        self.html
+            .select(&selector)
+            .filter_map(|element| element.value().attr("onclick"))
+            .flat_map(|onclick| onclick_regex().captures_iter(onclick))
+            .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+            .filter_map(|target| self.resolve(target.as_str()))
+            .collect()
+    }
+
+    /// The absolute urls of the alternate-language versions of the
+    /// page, declared by `<link rel="alternate" hreflang="...">`
+    pub fn alternates(&self) -> Vec<Alternate> {
+        let selector = Selector::parse(r#"link[rel~="alternate"][hreflang][href]"#).unwrap();
+        self.html
+            .select(&selector)
+            .filter_map(|element| {
+                let url = self.resolve(element.value().attr("href")?)?;
+                Some(Alternate {
+                    hreflang: element.value().attr("hreflang")?.to_string(),
+                    url,
+                })
+            })
+            .collect()
+    }
+
+    /// The directives of the `<meta name="robots">` tags of the page
+    pub fn robots(&self) -> RobotsDirectives {
+        let selector = Selector::parse(r#"meta[name][content]"#).unwrap();
+        self.html
+            .select(&selector)
+            .filter(|element| {
+                element
+                    .value()
+                    .attr("name")
+                    .is_some_and(|name| name.eq_ignore_ascii_case("robots"))
+            })
+            .filter_map(|element| element.value().attr("content"))
+            .map(RobotsDirectives::parse)
+            .fold(RobotsDirectives::default(), RobotsDirectives::union)
+    }
+
+    /// The trimmed text of the first element matching `selector`, if not empty
+    fn first_text(&self, selector: &str) -> Option<String> {
+        let selector = Selector::parse(selector).unwrap();
+        let element = self.html.select(&selector).next()?;
+        let text = element.text().collect::<String>().trim().to_string();
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// The title of the page, if any
+    pub fn title(&self) -> Option<String> {
+        self.first_text("title")
+    }
+
+    /// The `<meta name="description">` of the page, if any
+    pub fn description(&self) -> Option<String> {
+        let selector = Selector::parse("meta[name][content]").unwrap();
+        self.html
+            .select(&selector)
+            .filter(|element| {
+                element
+                    .value()
+                    .attr("name")
+                    .is_some_and(|name| name.eq_ignore_ascii_case("description"))
+            })
+            .filter_map(|element| element.value().attr("content"))
+            .map(str::trim)
+            .find(|content| !content.is_empty())
+            .map(str::to_string)
+    }
+
+    /// The text of the `h1` headings of the page
+    pub fn h1(&self) -> Vec<String> {
+        let selector = Selector::parse("h1").unwrap();
+        self.html
+            .select(&selector)
+            .map(|element| element.text().collect::<String>().trim().to_string())
+            .filter(|text| !text.is_empty())
+            .collect()
+    }
+
+    /// The content statistics of the page found at `url`, of `len` bytes
+    pub fn content_stats(&self, url: &url::Url, len: usize) -> ContentStats {
+        let mut stats = ContentStats::default();
+        let mut text_bytes = 0;
+        let mut words = Vec::new();
+        for node in self.html.root_element().descendants() {
+            if let Some(element) = node.value().as_element() {
+                if let Some(level) = heading_level(element.name()) {
+                    stats.headings[level - 1] += 1;
+                }
+                continue;
+            }
+            let Some(text) = node.value().as_text() else {
+                continue;
+            };
+            let hidden = node.ancestors().any(|ancestor| {
+                ancestor
+                    .value()
+                    .as_element()
+                    .is_some_and(|element| HIDDEN_ELEMENTS.contains(&element.name()))
+            });
+            if !hidden {
+                words.extend(text.split_whitespace());
+                text_bytes += text.trim().len();
+            }
+        }
+        stats.words = words.len();
+        stats.simhash = Simhash::of(words);
+        for link in self.links() {
+            match link.host_str() == url.host_str() {
+                true => stats.internal_links += 1,
+                false => stats.external_links += 1,
+            }
+        }
+        if len > 0 {
+            // Rounded to 3 decimals, enough for audits
+            stats.text_ratio = (text_bytes as f64 * 1000.0 / len as f64).round() / 1000.0;
+        }
+        stats
+    }
+
+    /// The url relative references of the page found at `url` resolve
+    /// against: the `href` of its first `<base>` element, if any, or `url`
+    pub fn base(&self, url: &url::Url) -> url::Url {
+        let selector = Selector::parse("base[href]").unwrap();
+        self.html
+            .select(&selector)
+            .next()
+            .and_then(|element| url.join(element.value().attr("href")?.trim()).ok())
+            .filter(|base| matches!(base.scheme(), "http" | "https"))
+            .unwrap_or_else(|| url.clone())
+    }
+
+    /// The target of a `<meta http-equiv="refresh">` redirect of the page
+    /// found at `url` within [`MAX_REFRESH_SECS`], resolved against the
+    /// [`base`](Self::base) of the page
+    pub fn refresh(&self, url: &url::Url) -> Option<url::Url> {
+        let selector = Selector::parse("meta[http-equiv][content]").unwrap();
+        let content = self
+            .html
+            .select(&selector)
+            .find(|element| {
+                element
+                    .value()
+                    .attr("http-equiv")
+                    .is_some_and(|name| name.eq_ignore_ascii_case("refresh"))
+            })?
+            .value()
+            .attr("content")?;
+        let (delay, target) = refresh(content)?;
+        if delay > MAX_REFRESH_SECS {
+            return None;
+        }
+        self.base(url)
+            .join(target)
+            .ok()
+            .filter(|target| target != url)
+    }
+
+    /// The canonical url declared by `<link rel="canonical">`, if any
+    pub fn canonical(&self) -> Option<url::Url> {
+        self.urls(r#"link[rel~="canonical"]"#, "href")
+            .into_iter()
+            .next()
+    }
+
+    /// The absolute urls of the AMP and mobile variants of the page, declared
+    /// by `<link rel="amphtml">` and `<link rel="alternate" media="...">`
+    pub fn variants(&self) -> Vec<url::Url> {
+        self.urls(
+            r#"link[rel~="amphtml"], link[rel~="alternate"][media]"#,
+            "href",
+        )
+    }
+
+    /// The absolute urls of the `frame` and `iframe` elements of the page
+    pub fn frames(&self) -> Vec<url::Url> {
+        self.urls("frame, iframe", "src")
+    }
+
+    /// The fallback contents of the `<noscript>` elements of the page,
+    /// which are parsed as text when the page itself is parsed
+    pub fn noscript(&self) -> Vec<Document> {
+        let selector = Selector::parse("noscript").unwrap();
+        self.html
+            .select(&selector)
+            .map(|element| element.text().collect::<String>())
+            .filter(|fallback| !fallback.trim().is_empty())
+            .map(|fallback| Self {
+                html: Html::parse_fragment(&fallback),
+                base_url: self.base_url.clone(),
+            })
+            .collect()
+    }
+
+    /// The absolute urls of the `data-href` attributes of the page,
+    /// linked by elements made clickable by scripts
+    pub fn lazy_links(&self) -> Vec<url::Url> {
+        self.urls("[data-href]", "data-href")
+    }
+
+    /// The absolute urls of the images loaded lazily by scripts, from their
+    /// `data-src`, `data-lazy-src`, `data-original` and `data-srcset`
+    /// attributes
+    pub fn lazy_assets(&self) -> Vec<url::Url> {
+        let mut assets = Vec::new();
+        for attr in ["data-src", "data-lazy-src", "data-original"] {
+            assets.extend(self.urls(&format!("img[{}]", attr), attr));
+        }
+        let selector = Selector::parse("img[data-srcset], picture source[data-srcset]").unwrap();
+        for element in self.html.select(&selector) {
+            let value = element.value().attr("data-srcset").unwrap_or_default();
+            assets.extend(srcset(value).into_iter().filter_map(|r| self.resolve(r)));
+        }
+        assets
+    }
+
+    /// The absolute urls of the frames loaded lazily by scripts,
+    /// from their `data-src` attribute
+    pub fn lazy_frames(&self) -> Vec<url::Url> {
+        self.urls("iframe[data-src]", "data-src")
+    }
+
+    /// The absolute urls of the subresources loaded by the page: scripts,
+    /// stylesheets, media, embedded objects, frames and images
+    pub fn subresources(&self) -> Vec<url::Url> {
+        let mut subresources = self.urls(
+            "script, video, audio, source, track, embed, frame, iframe",
+            "src",
+        );
+        subresources.extend(self.urls(r#"link[rel~="stylesheet"], link[rel~="icon"]"#, "href"));
+        subresources.extend(self.urls("object", "data"));
+        for asset in self.assets() {
+            if !subresources.contains(&asset) {
+                subresources.push(asset);
+            }
+        }
+        subresources
+    }
+
+    /// The absolute urls of the scripts and stylesheets of the page
+    /// declaring an `integrity` attribute, along with its digests
+    pub fn integrity(&self) -> Vec<IntegrityRef> {
+        let selector = Selector::parse("script[src][integrity], link[href][integrity]").unwrap();
+        self.html
+            .select(&selector)
+            .filter_map(|element| {
+                let element = element.value();
+                let url = element.attr("src").or_else(|| element.attr("href"))?;
+                Some(IntegrityRef {
+                    url: self.resolve(url)?,
+                    integrity: element
+                        .attr("integrity")?
+                        .split_ascii_whitespace()
+                        .map(str::to_string)
+                        .collect(),
+                    crossorigin: element.attr("crossorigin").map(str::to_string),
+                })
+            })
+            .collect()
+    }
+
+    /// The forms of the page, along with their named controls
+    pub fn forms(&self) -> Vec<Form> {
+        let selector = Selector::parse("form").unwrap();
+        let controls =
+            Selector::parse("input[name], select[name], textarea[name], button[name]").unwrap();
+        self.html
+            .select(&selector)
+            .map(|form| Form {
+                action: form.value().attr("action").map(str::to_string),
+                method: form
+                    .value()
+                    .attr("method")
+                    .unwrap_or("get")
+                    .to_ascii_lowercase(),
+                inputs: form
+                    .select(&controls)
+                    .map(|control| {
+                        let element = control.value();
+                        let kind = match element.name() {
+                            "input" => element.attr("type").unwrap_or("text"),
+                            other => other,
+                        };
+                        FormInput {
+                            name: element.attr("name").unwrap_or_default().to_string(),
+                            kind: kind.to_ascii_lowercase(),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// The names and values of the hidden inputs of the login form of
+    /// the page, the first form with a password input, e.g. its CSRF token
+    pub fn login_fields(&self) -> Vec<(String, String)> {
+        let forms = Selector::parse("form").unwrap();
+        let password = Selector::parse(r#"input[type="password" i]"#).unwrap();
+        let hidden = Selector::parse(r#"input[type="hidden" i][name]"#).unwrap();
+        self.html
+            .select(&forms)
+            .find(|form| form.select(&password).next().is_some())
+            .map(|form| {
+                form.select(&hidden)
+                    .map(|input| {
+                        let element = input.value();
+                        let name = element.attr("name").unwrap_or_default();
+                        let value = element.attr("value").unwrap_or_default();
+                        (name.to_string(), value.to_string())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The absolute urls of the images referenced by the page, including
+    /// the responsive candidates of `srcset` attributes and `<picture>`
+    /// sources, and the `url()` references of inline styles
+    pub fn assets(&self) -> Vec<url::Url> {
+        let mut references: Vec<String> = Vec::new();
+        let selector = Selector::parse("img[src]").unwrap();
+        for element in self.html.select(&selector) {
+            references.extend(element.value().attr("src").map(str::to_string));
+        }
+        let selector = Selector::parse("img[srcset], picture source[srcset]").unwrap();
+        for element in self.html.select(&selector) {
+            let value = element.value().attr("srcset").unwrap_or_default();
+            references.extend(srcset(value).into_iter().map(str::to_string));
+        }
+        let selector = Selector::parse("[style]").unwrap();
+        for element in self.html.select(&selector) {
+            let value = element.value().attr("style").unwrap_or_default();
+            references.extend(css_urls(value).into_iter().map(str::to_string));
+        }
+        let selector = Selector::parse("style").unwrap();
+        for element in self.html.select(&selector) {
+            let css: String = element.text().collect();
+            references.extend(css_urls(&css).into_iter().map(str::to_string));
+        }
+        let mut assets: Vec<url::Url> = Vec::new();
+        for url in references.iter().filter_map(|r| self.resolve(r)) {
+            if !assets.contains(&url) {
+                assets.push(url);
+            }
+        }
+        assets
+    }
+}
+
+/// The level of a heading element, e.g. 1 for `h1`
+fn heading_level(name: &str) -> Option<usize> {
+    match name.as_bytes() {
+        [b'h', level @ b'1'..=b'6'] => Some((level - b'0') as usize),
+        _ => None,
+    }
+}
+
+/// The urls of the image candidates of a `srcset` attribute,
+/// e.g. `small.jpg 480w, large.jpg 1080w`
+pub fn srcset(value: &str) -> Vec<&str> {
+    let mut urls = Vec::new();
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+        let end = rest
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let candidate = &rest[..end];
+        let url = candidate.trim_end_matches(',');
+        urls.push(url);
+        rest = &rest[end..];
+        // A url followed by a comma has no descriptors to skip
+        if url.len() == candidate.len() {
+            rest = rest.find(',').map_or("", |i| &rest[i + 1..]);
+        }
+    }
+    urls
+}
+
+/// The delay and the target of the `content` of a `<meta http-equiv="refresh">`,
+/// e.g. `0; url=https://example.com/`, if it has a target
+pub fn refresh(content: &str) -> Option<(f64, &str)> {
+    let (delay, target) = content.trim().split_once([';', ','])?;
+    let delay = delay.trim().parse().ok()?;
+    let target = target.trim_start();
+    let target = match target.get(..3) {
+        Some(name) if name.eq_ignore_ascii_case("url") => {
+            target[3..].trim_start().strip_prefix('=')?.trim()
+        }
+        _ => target.trim(),
+    };
+    let target = target.trim_matches(|c| c == '"' || c == '\'');
+    (!target.is_empty()).then_some((delay, target))
+}
+
+/// The references of the `url()` functions of a stylesheet
+pub fn css_urls(css: &str) -> Vec<&str> {
+    let lowercase = css.to_ascii_lowercase();
+    let mut urls = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = lowercase[offset..].find("url(") {
+        let rest = css[offset + start + "url(".len()..].trim_start();
+        let begin = css.len() - rest.len();
+        let (url, end) = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => match rest[1..].find(quote) {
+                Some(len) => (&rest[1..len + 1], begin + len + 2),
+                None => break,
+            },
+            _ => match rest.find(')') {
+                Some(len) => (rest[..len].trim_end(), begin + len + 1),
+                None => break,
+            },
+        };
+        if !url.is_empty() {
+            urls.push(url);
+        }
+        offset = end;
+    }
+    urls
+}
+
+/// The relations of the `Link` header targets that are followed
+pub const FOLLOWED_RELS: [&str; 5] = ["next", "prev", "previous", "first", "last"];
+
+/// The urls of the pagination targets of the `Link` headers,
+/// e.g. `<https://example.com/?page=2>; rel="next"`, resolved against `base`
+pub fn header_links(headers: &HeaderMap, base: &url::Url) -> Vec<url::Url> {
+    let mut links: Vec<url::Url> = Vec::new();
+    let values = headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok());
+    for value in values {
+        for (target, rels) in link_values(value) {
+            let followed = rels
+                .split_ascii_whitespace()
+                .any(|rel| FOLLOWED_RELS.iter().any(|f| rel.eq_ignore_ascii_case(f)));
+            let Some(url) = followed.then(|| base.join(target).ok()).flatten() else {
+                continue;
+            };
+            if !links.contains(&url) {
+                links.push(url);
+            }
+        }
+    }
+    links
+}
+
+/// The targets of a `Link` header value, along with their `rel` parameter
+fn link_values(value: &str) -> Vec<(&str, &str)> {
+    let mut values = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        let Some(len) = rest[start + 1..].find('>') else {
+            break;
+        };
+        let target = rest[start + 1..start + 1 + len].trim();
+        rest = &rest[start + len + 2..];
+        let params = &rest[..rest.find('<').unwrap_or(rest.len())];
+        let rel = params
+            .split([';', ','])
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("rel"))
+            .map(|(_, rel)| rel.trim().trim_matches('"'));
+        if let Some(rel) = rel {
+            values.push((target, rel));
+        }
+    }
+    values
+}
+
+/// The absolute urls linked by the anchors of an HTML page
+pub fn links(page: &[u8]) -> Vec<url::Url> {
+    Document::parse(page).links()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(value: &str) -> url::Url {
+        url::Url::parse(value).unwrap()
+    }
+
+    #[test]
+    fn base_is_the_first_base_href_or_the_page_url() {
+        let page = url("https://example.com/docs/page.html");
+        let document =
+            Document::parse(br#"<head><base href="/static/"><base href="/other/"></head>"#);
+        assert_eq!(document.base(&page), url("https://example.com/static/"));
+        assert_eq!(Document::parse(b"<p>no base</p>").base(&page), page);
+        let document = Document::parse(br#"<base href="javascript:void(0)">"#);
+        assert_eq!(document.base(&page), page);
+    }
+
+    #[test]
+    fn references_resolve_against_the_base_without_fragments() {
+        let page = url("https://example.com/docs/page.html");
+        let document = Document::parse(
+            br##"<base href="https://cdn.example.com/a/">
+            <a href="b.html#top">B</a><a href=" /c.html ">C</a>"##,
+        )
+        .with_url(&page);
+        assert_eq!(
+            document.links(),
+            [
+                url("https://cdn.example.com/a/b.html"),
+                url("https://cdn.example.com/c.html")
+            ]
+        );
+        let document = Document::parse(br#"<a href="relative.html">R</a>"#);
+        assert!(document.links().is_empty());
+    }
+
+    #[test]
+    fn refresh_contents_are_parsed_for_their_targets() {
+        assert_eq!(refresh("0; url=/next"), Some((0.0, "/next")));
+        assert_eq!(refresh(" 5 , URL = 'next.html' "), Some((5.0, "next.html")));
+        assert_eq!(
+            refresh("1;\"https://example.com/\""),
+            Some((1.0, "https://example.com/"))
+        );
+        assert_eq!(refresh("30"), None);
+        assert_eq!(refresh("0; url="), None);
+        assert_eq!(refresh("soon; url=/next"), None);
+    }
+
+    #[test]
+    fn refreshes_redirect_within_the_max_delay() {
+        let page = url("https://example.com/a/page.html");
+        let document =
+            Document::parse(br#"<meta http-equiv="Refresh" content="0; url=next.html">"#);
+        assert_eq!(
+            document.refresh(&page),
+            Some(url("https://example.com/a/next.html"))
+        );
+        let document =
+            Document::parse(br#"<meta http-equiv="refresh" content="60; url=next.html">"#);
+        assert_eq!(document.refresh(&page), None);
+        let document =
+            Document::parse(br#"<meta http-equiv="refresh" content="0; url=page.html">"#);
+        assert_eq!(document.refresh(&page), None);
+    }
+
+    #[test]
+    fn srcset_candidates_are_split_from_their_descriptors() {
+        assert_eq!(
+            srcset("small.jpg 480w, large.jpg 1080w"),
+            ["small.jpg", "large.jpg"]
+        );
+        assert_eq!(
+            srcset("a.png, b.png 2x ,  c.png"),
+            ["a.png", "b.png", "c.png"]
+        );
+        assert_eq!(
+            srcset("https://example.com/img,1.png 1x"),
+            ["https://example.com/img,1.png"]
+        );
+        assert!(srcset(" , ").is_empty());
+    }
+
+    #[test]
+    fn css_urls_are_unquoted() {
+        let css = r#"body { background: URL("bg.png") }
+            .a { background: url( 'a.png' ) } .b { background: url(b.png) }
+            .c { background: url() }"#;
+        assert_eq!(css_urls(css), ["bg.png", "a.png", "b.png"]);
+        assert_eq!(
+            css_urls("a { background: url('unterminated) }"),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn header_links_follow_the_pagination_relations() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            LINK,
+            r#"<?page=2>; rel="next", </style.css>; rel=preload, <?page=1>; rel="prev first""#
+                .parse()
+                .unwrap(),
+        );
+        headers.append(
+            LINK,
+            "<https://other.test/?page=2>; REL=Next".parse().unwrap(),
+        );
+        headers.append(LINK, "<?page=2>; rel=next".parse().unwrap());
+        let base = url("https://example.com/list?page=1");
+        assert_eq!(
+            header_links(&headers, &base),
+            [
+                url("https://example.com/list?page=2"),
+                url("https://example.com/list?page=1"),
+                url("https://other.test/?page=2"),
+            ]
+        );
+    }
+}
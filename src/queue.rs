@@ -0,0 +1,202 @@
+//! Integration with a NATS message queue.
+//!
+//! The crawler consumes the seeds published on a subject, one url per
+//! message, and publishes a JSON [`PageRecord`] on another subject for
+//! every visited page, so that it can be plugged into an existing data
+//! pipeline. Only the core NATS protocol is spoken: messages are
+//! delivered at most once, and the credentials of the server url, if
+//! any, are sent as a user and password, or as a token.
+use std::sync::Arc;
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use crate::error::{CrawlerError, Result};
+use crate::executor::{self, Executor, TaskHandle};
+use crate::page::{CrawledPage, PageRecord};
+
+/// Default subject of the seeds
+pub const SEEDS_SUBJECT: &str = "webcrawler.seeds";
+
+/// Default subject of the page records
+pub const PAGES_SUBJECT: &str = "webcrawler.pages";
+
+const DEFAULT_PORT: u16 = 4222;
+const SEEDS_SID: &str = "1";
+
+/// The options sent to the server upon connecting
+#[derive(Serialize)]
+struct ConnectOptions {
+    verbose: bool,
+    pedantic: bool,
+    name: &'static str,
+    lang: &'static str,
+    version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pass: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_token: Option<String>,
+}
+
+/// The channels connecting a crawl to a NATS server
+pub struct NatsBridge {
+    /// The seeds received from the server, to pass to
+    /// [`Crawler::with_seeds`](crate::Crawler::with_seeds)
+    pub seeds: UnboundedReceiver<url::Url>,
+    /// The pages to publish, to pass to
+    /// [`Crawler::with_pages`](crate::Crawler::with_pages)
+    pub pages: UnboundedSender<CrawledPage>,
+    /// Completes with the number of published records once
+    /// every page has been published
+    pub published: TaskHandle<Result<usize>>,
+    /// Completes once the seeds are no longer received, with the error
+    /// that interrupted their reception, if any. Dropping it stops the
+    /// reception.
+    pub received: TaskHandle<Result<()>>,
+}
+
+/// Connect to the NATS server at `server`, e.g. `nats://localhost:4222`,
+/// subscribing to the seeds of `seeds_subject` and publishing the pages
+/// on `pages_subject`, with tasks spawned on `executor`.
+///
+/// The seeds are received until the server closes the connection.
+pub async fn connect(
+    executor: &dyn Executor,
+    server: &str,
+    seeds_subject: &str,
+    pages_subject: &str,
+) -> Result<NatsBridge> {
+    let server = match server.contains("://") {
+        true => url::Url::parse(server)?,
+        false => url::Url::parse(&format!("nats://{}", server))?,
+    };
+    let host = server.host_str().ok_or(CrawlerError::NoUrlHost)?;
+    let stream = TcpStream::connect((host, server.port().unwrap_or(DEFAULT_PORT))).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let mut info = String::new();
+    reader.read_line(&mut info).await?;
+    if !info.starts_with("INFO") {
+        return Err(CrawlerError::Queue(format!(
+            "unexpected greeting {:?}",
+            info.trim_end()
+        )));
+    }
+    let (user, pass, auth_token) = match (server.username(), server.password()) {
+        ("", _) => (None, None, None),
+        (token, None) => (None, None, Some(token.to_string())),
+        (user, Some(pass)) => (Some(user.to_string()), Some(pass.to_string()), None),
+    };
+    let options = ConnectOptions {
+        verbose: false,
+        pedantic: false,
+        name: env!("CARGO_PKG_NAME"),
+        lang: "rust",
+        version: env!("CARGO_PKG_VERSION"),
+        user,
+        pass,
+        auth_token,
+    };
+    let handshake = format!(
+        "CONNECT {}\r\nSUB {} {}\r\n",
+        serde_json::to_string(&options)?,
+        seeds_subject,
+        SEEDS_SID
+    );
+    writer.write_all(handshake.as_bytes()).await?;
+    let writer = Arc::new(Mutex::new(writer));
+
+    let (seeds_tx, seeds) = mpsc::unbounded();
+    let reception = receive(reader, Arc::clone(&writer), seeds_tx);
+    let received = executor::spawn(executor, async move {
+        let result = reception.await;
+        if let Err(err) = &result {
+            tracing::warn!("Stopped receiving the seeds from NATS: {}", err);
+        }
+        result
+    });
+
+    let (pages, pages_rx) = mpsc::unbounded();
+    let published = executor::spawn(
+        executor,
+        publish(pages_rx, writer, pages_subject.to_string()),
+    );
+    Ok(NatsBridge {
+        seeds,
+        pages,
+        published,
+        received,
+    })
+}
+
+/// Forward the seeds of the received messages to `seeds`, and answer
+/// the pings of the server, until it closes the connection
+async fn receive(
+    mut reader: BufReader<OwnedReadHalf>,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    seeds: UnboundedSender<url::Url>,
+) -> Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            tracing::warn!("NATS server closed the connection");
+            return Ok(());
+        }
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            // MSG <subject> <sid> [reply-to] <#bytes>
+            Some("MSG") => {
+                let fields: Vec<&str> = fields.collect();
+                let (sid, len) = match fields.as_slice() {
+                    [_, sid, len] | [_, sid, _, len] => (*sid, len.parse::<usize>()),
+                    _ => return Err(CrawlerError::Queue(format!("invalid message {:?}", line))),
+                };
+                let len =
+                    len.map_err(|_| CrawlerError::Queue(format!("invalid message {:?}", line)))?;
+                let mut payload = vec![0; len + 2];
+                reader.read_exact(&mut payload).await?;
+                if sid != SEEDS_SID {
+                    continue;
+                }
+                match crate::seed::parse_seed(String::from_utf8_lossy(&payload[..len]).trim()) {
+                    Ok(seed) => {
+                        // Fails once the crawl is over
+                        let _ = seeds.unbounded_send(seed);
+                    }
+                    Err(err) => tracing::warn!("skipping seed: {}", err),
+                }
+            }
+            Some("PING") => writer.lock().await.write_all(b"PONG\r\n").await?,
+            Some("-ERR") => tracing::warn!("NATS server error: {}", line.trim_end()),
+            _ => {}
+        }
+    }
+}
+
+/// Publish a record of each page on `subject`, returning their number
+async fn publish(
+    mut pages: UnboundedReceiver<CrawledPage>,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    subject: String,
+) -> Result<usize> {
+    let mut published = 0;
+    while let Some(page) = pages.next().await {
+        let payload = serde_json::to_vec(&PageRecord::from(&page))?;
+        let mut frame = format!("PUB {} {}\r\n", subject, payload.len()).into_bytes();
+        frame.extend_from_slice(&payload);
+        frame.extend_from_slice(b"\r\n");
+        writer.lock().await.write_all(&frame).await?;
+        published += 1;
+    }
+    writer.lock().await.flush().await?;
+    Ok(published)
+}
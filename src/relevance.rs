@@ -0,0 +1,126 @@
+//! Relevance of pages to the topic of a focused crawl.
+//!
+//! A focused crawl scores the text of every page with a
+//! [`RelevanceScorer`], and follows the links of the irrelevant pages
+//! after those of the relevant ones, or not at all, so that the crawl
+//! builds a topical corpus instead of covering the site breadth-first.
+use std::fmt;
+use std::sync::Arc;
+
+use crate::config::FocusConfig;
+
+/// The priority penalty of the links of an irrelevant page
+pub const IRRELEVANT_PENALTY: i32 = 1;
+
+/// A score of the relevance of the url and text of a page
+pub type ScoreFn = dyn Fn(&url::Url, &str) -> u32 + Send + Sync;
+
+/// Scores the relevance of pages
+#[derive(Clone)]
+pub enum RelevanceScorer {
+    /// The number of the keywords found in the text, in any case
+    Keywords(Vec<String>),
+    /// A custom score
+    Custom(Arc<ScoreFn>),
+}
+
+impl RelevanceScorer {
+    /// Score pages by the number of the `keywords` in their text
+    pub fn keywords<I, S>(keywords: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self::Keywords(
+            keywords
+                .into_iter()
+                .map(|keyword| keyword.as_ref().to_lowercase())
+                .collect(),
+        )
+    }
+
+    /// Score pages with `score`
+    pub fn custom<F>(score: F) -> Self
+    where
+        F: Fn(&url::Url, &str) -> u32 + Send + Sync + 'static,
+    {
+        Self::Custom(Arc::new(score))
+    }
+
+    /// The relevance of the page of `url` with `text`
+    pub fn score(&self, url: &url::Url, text: &str) -> u32 {
+        match self {
+            Self::Keywords(keywords) => {
+                let text = text.to_lowercase();
+                keywords
+                    .iter()
+                    .filter(|keyword| text.contains(keyword.as_str()))
+                    .count() as u32
+            }
+            Self::Custom(score) => score(url, text),
+        }
+    }
+}
+
+impl fmt::Debug for RelevanceScorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keywords(keywords) => f.debug_tuple("Keywords").field(keywords).finish(),
+            Self::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// How a focused crawl treats the links of irrelevant pages
+#[derive(Debug, Clone)]
+pub struct FocusPolicy {
+    scorer: RelevanceScorer,
+    min_score: u32,
+    drop_irrelevant: bool,
+}
+
+impl FocusPolicy {
+    /// Focus on the pages that `scorer` scores at least 1
+    pub fn new(scorer: RelevanceScorer) -> Self {
+        Self {
+            scorer,
+            min_score: 1,
+            drop_irrelevant: false,
+        }
+    }
+
+    /// The policy described by `config`
+    pub fn from_config(config: &FocusConfig) -> Self {
+        Self::new(RelevanceScorer::keywords(&config.keywords))
+            .with_min_score(config.min_score)
+            .with_drop_irrelevant(config.drop_irrelevant)
+    }
+
+    /// Consider relevant the pages scored at least `min_score`
+    pub fn with_min_score(mut self, min_score: u32) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    /// Drop the links of irrelevant pages, instead of lowering
+    /// their priority
+    pub fn with_drop_irrelevant(mut self, drop_irrelevant: bool) -> Self {
+        self.drop_irrelevant = drop_irrelevant;
+        self
+    }
+
+    /// The relevance of the page of `url` with `text`
+    pub fn score(&self, url: &url::Url, text: &str) -> u32 {
+        self.scorer.score(url, text)
+    }
+
+    /// Whether a page scored `score` is relevant
+    pub fn is_relevant(&self, score: u32) -> bool {
+        score >= self.min_score
+    }
+
+    /// Whether the links of irrelevant pages are dropped
+    pub fn drops_irrelevant(&self) -> bool {
+        self.drop_irrelevant
+    }
+}
@@ -0,0 +1,98 @@
+//! Mirroring of a storage directory to a cloud object store.
+//!
+//! A crawl stored to an `s3://`, `gs://` or `az://` uri still writes its
+//! pages to a local storage directory, which stages them: every page is
+//! uploaded to the bucket as soon as it is written, and the files of the
+//! crawl, such as its manifest, state and run metadata, are uploaded
+//! once the crawl is over, so that the bucket holds the same files as
+//! the directory. The credentials of the store are read from the
+//! environment, e.g. `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`,
+//! `GOOGLE_SERVICE_ACCOUNT` or `AZURE_STORAGE_ACCOUNT_NAME` and
+//! `AZURE_STORAGE_ACCOUNT_KEY`.
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload};
+
+use crate::error::Result;
+
+/// A bucket, or a prefix of a bucket, mirroring a storage directory
+#[derive(Debug)]
+pub struct RemoteStorage {
+    uri: String,
+    store: Box<dyn ObjectStore>,
+    prefix: ObjectPath,
+    /// The size of every uploaded file, by path in the storage
+    uploaded: Mutex<HashMap<PathBuf, u64>>,
+}
+
+impl RemoteStorage {
+    /// The object store of `uri`, e.g. `s3://bucket/crawls/example`,
+    /// configured by the environment
+    pub fn parse(uri: &str) -> Result<Self> {
+        let url = url::Url::parse(uri)?;
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, prefix) = object_store::parse_url_opts(&url, options)?;
+        Ok(Self {
+            uri: uri.to_string(),
+            store,
+            prefix,
+            uploaded: Default::default(),
+        })
+    }
+
+    /// Upload the file at `path` in the storage directory `root`
+    pub async fn upload(&self, root: &Path, path: &Path) -> Result<()> {
+        let content = tokio::fs::read(root.join(path)).await?;
+        let len = content.len() as u64;
+        self.store
+            .put(&self.object_path(path), PutPayload::from(content))
+            .await?;
+        self.uploaded
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), len);
+        Ok(())
+    }
+
+    /// Upload the files of the storage directory `root` not uploaded
+    /// yet, or changed since, returning their number
+    pub async fn sync(&self, root: &Path) -> Result<usize> {
+        let mut n_files = 0;
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = tokio::fs::read_dir(root.join(&dir)).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = dir.join(entry.file_name());
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let uploaded = self.uploaded.lock().unwrap().get(&path).copied();
+                if uploaded != Some(metadata.len()) {
+                    self.upload(root, &path).await?;
+                    n_files += 1;
+                }
+            }
+        }
+        Ok(n_files)
+    }
+
+    /// The location in the store of the file at `path` in the storage
+    fn object_path(&self, path: &Path) -> ObjectPath {
+        path.components()
+            .fold(self.prefix.clone(), |location, component| {
+                location.child(component.as_os_str().to_string_lossy().as_ref())
+            })
+    }
+}
+
+impl fmt::Display for RemoteStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.uri)
+    }
+}
@@ -0,0 +1,182 @@
+//! Replay of a stored crawl over HTTP.
+//!
+//! The server serves the latest stored version of every page of a crawl
+//! at `/<url>`, e.g. `http://localhost:8080/https://example.com/`, with an
+//! index of the crawled pages at `/`. The links and sources of the served
+//! HTML pages are rewritten to point back to the server, so that the
+//! capture is browsed in a normal browser without reaching the live site.
+//! Links to pages that were not crawled are answered with a `404`.
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use crate::error::Result;
+use crate::index::escape;
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::parse::Document;
+use crate::Storage;
+
+/// The attributes whose urls are rewritten
+const REWRITTEN_ATTRIBUTES: [&str; 3] = ["href", "src", "action"];
+
+/// A server replaying the crawl of a storage
+#[derive(Clone)]
+pub struct ReplayServer {
+    storage: Arc<Storage>,
+    pages: Arc<HashMap<String, ManifestEntry>>,
+}
+
+impl ReplayServer {
+    /// A server replaying the pages recorded in the manifest of `storage`
+    pub async fn load(storage: Storage) -> Result<Self> {
+        let manifest = Manifest::load(storage.path()).await?;
+        let pages = manifest
+            .latest()
+            .into_iter()
+            .map(|(url, entry)| (url.to_string(), entry.clone()))
+            .collect();
+        Ok(Self {
+            storage: Arc::new(storage),
+            pages: Arc::new(pages),
+        })
+    }
+
+    /// Serve the crawl on `addr` until the process stops
+    pub async fn run(self, addr: SocketAddr) -> Result<()> {
+        let make_service = make_service_fn(move |_| {
+            let server = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.handle(request).await) }
+                }))
+            }
+        });
+        let server = hyper::Server::try_bind(&addr)?.serve(make_service);
+        tracing::info!("==> Replaying the crawl on http://{}/", server.local_addr());
+        server.await?;
+        Ok(())
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::GET {
+            return text(StatusCode::METHOD_NOT_ALLOWED, "only GET is supported");
+        }
+        let target = request
+            .uri()
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/")
+            .trim_start_matches('/');
+        if target.is_empty() {
+            return self.index();
+        }
+        let entry = match url::Url::parse(target)
+            .ok()
+            .and_then(|url| self.pages.get(url.as_str()))
+        {
+            Some(entry) => entry,
+            None => return text(StatusCode::NOT_FOUND, "page not crawled"),
+        };
+        let body = match self.storage.read(entry).await {
+            Ok(body) => body,
+            Err(e) => return text(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        };
+        let content_type = entry
+            .headers
+            .get("content-type")
+            .map(String::as_str)
+            .unwrap_or("text/html");
+        let body = match !entry.binary && content_type.starts_with("text/html") {
+            true => rewrite(&String::from_utf8_lossy(&body), &entry.url).into_bytes(),
+            false => body,
+        };
+        Response::builder()
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    /// A page linking to every crawled page
+    fn index(&self) -> Response<Body> {
+        let mut urls: Vec<&String> = self.pages.keys().collect();
+        urls.sort();
+        let mut html = String::from("<!DOCTYPE html>\n<title>Crawled pages</title>\n<ul>\n");
+        for url in urls {
+            let url = escape(url);
+            writeln!(html, "<li><a href=\"/{}\">{}</a></li>", url, url).unwrap();
+        }
+        html.push_str("</ul>\n");
+        Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(Body::from(html))
+            .unwrap()
+    }
+}
+
+/// Point the http(s) urls of the rewritten attributes of the `html`
+/// page found at `url` to the replay server
+pub fn rewrite(html: &str, url: &url::Url) -> String {
+    let base = Document::parse(html.as_bytes()).base(url);
+    let lowercase = html.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(html.len());
+    let mut copied = 0;
+    let mut position = 0;
+    while let Some((start, end)) = next_attribute_value(&lowercase, position) {
+        position = end;
+        let value = html[start..end].trim().replace("&amp;", "&");
+        let target = match base.join(&value) {
+            Ok(target) if matches!(target.scheme(), "http" | "https") => target,
+            _ => continue,
+        };
+        rewritten.push_str(&html[copied..start]);
+        rewritten.push('/');
+        rewritten.push_str(&escape(target.as_str()));
+        copied = end;
+    }
+    rewritten.push_str(&html[copied..]);
+    rewritten
+}
+
+/// The bounds of the next quoted value of a rewritten
+/// attribute from `position` of the `lowercase` page
+fn next_attribute_value(lowercase: &str, mut position: usize) -> Option<(usize, usize)> {
+    let bytes = lowercase.as_bytes();
+    while let Some(offset) = lowercase[position..].find('=') {
+        let equals = position + offset;
+        position = equals + 1;
+        let name = lowercase[..equals].trim_end();
+        let Some(attribute) = REWRITTEN_ATTRIBUTES
+            .iter()
+            .find(|attribute| name.ends_with(*attribute))
+        else {
+            continue;
+        };
+        let preceding = name.len() - attribute.len();
+        if preceding == 0 || !bytes[preceding - 1].is_ascii_whitespace() {
+            continue;
+        }
+        let value = lowercase[position..].trim_start();
+        let quote = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => continue,
+        };
+        let start = lowercase.len() - value.len() + 1;
+        let end = start + lowercase[start..].find(quote)?;
+        return Some((start, end));
+    }
+    None
+}
+
+fn text(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(message.to_string()))
+        .unwrap()
+}
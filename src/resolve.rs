@@ -0,0 +1,61 @@
+//! Resolution of the hosts fetched, restricted to or ordered by an IP family.
+//!
+//! The connector of the client tries the resolved addresses of the first
+//! family first, and falls back to the other family only if they do not
+//! connect promptly. Ordering the addresses thus prefers a family, e.g.
+//! IPv4 for sites whose IPv6 is broken, without waiting for a timeout on
+//! every connection.
+use std::io;
+use std::net::SocketAddr;
+
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+
+use crate::config::IpFamily;
+
+/// A resolver of the system, filtering and ordering the addresses by family
+#[derive(Debug, Clone, Copy)]
+pub struct FamilyResolver {
+    family: IpFamily,
+}
+
+impl FamilyResolver {
+    pub fn new(family: IpFamily) -> Self {
+        Self { family }
+    }
+}
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.family;
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            let addrs = order(family, addrs);
+            if addrs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "`{}` resolves to no address of family `{}`",
+                        name.as_str(),
+                        family
+                    ),
+                )
+                .into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// The `addrs` of `family`, with the preferred family first
+fn order(family: IpFamily, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    match family {
+        IpFamily::Any => {}
+        IpFamily::Ipv4 => addrs.retain(SocketAddr::is_ipv4),
+        IpFamily::Ipv6 => addrs.retain(SocketAddr::is_ipv6),
+        IpFamily::PreferIpv4 => addrs.sort_by_key(SocketAddr::is_ipv6),
+        IpFamily::PreferIpv6 => addrs.sort_by_key(SocketAddr::is_ipv4),
+    }
+    addrs
+}
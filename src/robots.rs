@@ -0,0 +1,72 @@
+//! Indexing directives of pages.
+//!
+//! Publishers ask crawlers not to index a page or not to follow its
+//! links with `noindex` and `nofollow` directives, given either in
+//! `X-Robots-Tag` response headers or in `<meta name="robots">` tags.
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+/// Name of the header carrying the directives of a response
+pub const X_ROBOTS_TAG: &str = "x-robots-tag";
+
+/// Directives taking a value after a colon, which is not to be
+/// mistaken for the name of the user agent the directives apply to
+const VALUED_DIRECTIVES: &[&str] = &[
+    "max-image-preview",
+    "max-snippet",
+    "max-video-preview",
+    "unavailable_after",
+];
+
+/// The directives of a page that the crawl complies with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RobotsDirectives {
+    /// Do not store the page
+    pub noindex: bool,
+    /// Do not follow the links of the page
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /// The directives of a comma-separated list, such as
+    /// `noindex, nofollow`, addressed to all user agents
+    pub fn parse(value: &str) -> Self {
+        let mut directives = Self::default();
+        if let Some((agent, _)) = value.split_once(':') {
+            let agent = agent.trim().to_ascii_lowercase();
+            if !VALUED_DIRECTIVES.contains(&agent.as_str()) {
+                return directives;
+            }
+        }
+        for directive in value.split(',').map(str::trim) {
+            match directive.to_ascii_lowercase().as_str() {
+                "noindex" => directives.noindex = true,
+                "nofollow" => directives.nofollow = true,
+                "none" => {
+                    directives.noindex = true;
+                    directives.nofollow = true;
+                }
+                _ => {}
+            }
+        }
+        directives
+    }
+
+    /// The directives of all the `X-Robots-Tag` headers of a response
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get_all(X_ROBOTS_TAG)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(Self::parse)
+            .fold(Self::default(), Self::union)
+    }
+
+    /// The directives given by either `self` or `other`
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Metadata describing a crawl run.
+//!
+//! Every run gets a unique id and records in the `run.json` file of the
+//! storage root its seeds, configuration, timing and final stats, so
+//! that a storage directory describes the crawl that produced it. The
+//! file is written when the run starts and rewritten when it ends; a
+//! missing `finished_at` marks a run that did not complete.
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::Limits;
+use crate::error::Result;
+use crate::frontier::RejectedUrls;
+use crate::hosts::HostTable;
+use crate::latency::LatencySummary;
+use crate::StorageLayout;
+
+/// Name of the run metadata file in the storage root
+pub const RUN_FILE: &str = "run.json";
+
+/// The configuration a run was started with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunConfig {
+    pub limits: Limits,
+    pub n_writers: usize,
+    pub layout: StorageLayout,
+}
+
+/// The outcome of a run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunStats {
+    /// Pages fetched and scraped
+    pub visited: usize,
+    /// Pages that could not be fetched, including those whose task panicked
+    pub failed: usize,
+    /// Pages whose task panicked
+    #[serde(default)]
+    pub panicked: usize,
+    /// Pages whose visit timed out
+    #[serde(default)]
+    pub timed_out: usize,
+    /// Pages written to the storage
+    pub stored: usize,
+    /// Pages that could not be written to the storage
+    pub store_failed: usize,
+    /// Distinct urls left in the frontier
+    pub pending: usize,
+    /// The visits of each host
+    #[serde(default)]
+    pub hosts: HostTable,
+    /// The distribution of the fetch latencies, if any page was fetched
+    #[serde(default)]
+    pub latency: Option<LatencySummary>,
+    /// The urls rejected by the url limits
+    #[serde(default)]
+    pub rejected: RejectedUrls,
+    /// The number of discovered urls left out of a sampled crawl
+    #[serde(default)]
+    pub sampled_out: usize,
+}
+
+/// The `run.json` record of a crawl run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub run_id: Uuid,
+    /// The tag given to the crawl, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// The version of the crawler that ran
+    pub crate_version: String,
+    pub seeds: Vec<url::Url>,
+    /// Whether the run resumed a previous crawl
+    pub resumed: bool,
+    pub config: RunConfig,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub stats: Option<RunStats>,
+}
+
+impl RunMetadata {
+    /// The metadata of run `run_id`, starting now
+    pub fn start(run_id: Uuid, seeds: Vec<url::Url>, resumed: bool, config: RunConfig) -> Self {
+        Self {
+            run_id,
+            tag: None,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            seeds,
+            resumed,
+            config,
+            started_at: chrono::Utc::now().timestamp_millis(),
+            finished_at: None,
+            stats: None,
+        }
+    }
+
+    pub fn with_tag(mut self, tag: Option<String>) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Mark the run as finished now, with `stats`
+    pub fn finish(&mut self, stats: RunStats) {
+        self.finished_at = Some(chrono::Utc::now().timestamp_millis());
+        self.stats = Some(stats);
+    }
+
+    /// Load the metadata of the last run stored in `root`
+    pub async fn load(root: &Path) -> Result<Self> {
+        let contents = tokio::fs::read(root.join(RUN_FILE)).await?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    /// Save the metadata in the storage directory `root`
+    pub async fn save(&self, root: &Path) -> Result<()> {
+        let tmp_path = root.join(RUN_FILE).with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?).await?;
+        tokio::fs::rename(tmp_path, root.join(RUN_FILE)).await?;
+        Ok(())
+    }
+}
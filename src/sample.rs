@@ -0,0 +1,213 @@
+//! Sampling of the discovered links.
+//!
+//! A sampled crawl visits a fraction of the links it discovers instead of
+//! all of them, to estimate the characteristics of a site too large to
+//! crawl, e.g. its page types, sizes or latency, from a bounded number of
+//! visits. Every link is visited with a probability given by the first
+//! url pattern it matches, if any, or else by its depth, the probability
+//! decaying at every level, so that the sample spreads across the site
+//! instead of exhausting its first levels. The seeds are always visited.
+//!
+//! The draw of a url is a hash of the url and the seed of the sample, so
+//! that the same sample is drawn by every crawl of the same seed, and a
+//! url discovered several times is drawn once.
+use std::fmt;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::SamplingConfig;
+use crate::error::{CrawlerError, Result};
+use crate::simhash::fnv1a;
+
+/// A probability, from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct Probability(f64);
+
+// Never NaN
+impl Eq for Probability {}
+
+impl Probability {
+    pub const ALWAYS: Self = Self(1.0);
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+impl Default for Probability {
+    fn default() -> Self {
+        Self::ALWAYS
+    }
+}
+
+impl TryFrom<f64> for Probability {
+    type Error = String;
+
+    fn try_from(value: f64) -> std::result::Result<Self, Self::Error> {
+        match (0.0..=1.0).contains(&value) {
+            true => Ok(Self(value)),
+            false => Err(format!(
+                "unsupported probability `{}`, expected a number from 0 to 1",
+                value
+            )),
+        }
+    }
+}
+
+impl From<Probability> for f64 {
+    fn from(probability: Probability) -> Self {
+        probability.0
+    }
+}
+
+impl FromStr for Probability {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        s.parse::<f64>()
+            .map_err(|_| {
+                format!(
+                    "unsupported probability `{}`, expected a number from 0 to 1",
+                    s
+                )
+            })?
+            .try_into()
+    }
+}
+
+impl fmt::Display for Probability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Draws the sample of the discovered links of a [`SamplingConfig`]
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    rate: f64,
+    depth_decay: f64,
+    patterns: Vec<(Regex, f64)>,
+    seed: u64,
+}
+
+impl Sampler {
+    pub fn new(config: &SamplingConfig) -> Result<Self> {
+        let patterns = config
+            .patterns
+            .iter()
+            .map(|pattern| {
+                let regex = Regex::new(&pattern.pattern).map_err(|err| {
+                    CrawlerError::InvalidConfig(vec![format!(
+                        "`policy.sampling.patterns`: {}",
+                        err
+                    )])
+                })?;
+                Ok((regex, pattern.rate.value()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            rate: config.rate.value(),
+            depth_decay: config.depth_decay.value(),
+            patterns,
+            seed: config.seed,
+        })
+    }
+
+    /// The probability of visiting the link to `url` found at `depth`
+    pub fn probability(&self, url: &url::Url, depth: usize) -> f64 {
+        match self
+            .patterns
+            .iter()
+            .find(|(regex, _)| regex.is_match(url.as_str()))
+        {
+            Some((_, rate)) => *rate,
+            None => {
+                let levels = depth.saturating_sub(1).min(i32::MAX as usize) as i32;
+                self.rate * self.depth_decay.powi(levels)
+            }
+        }
+    }
+
+    /// Whether the link to `url` found at `depth` is drawn in the sample
+    pub fn samples(&self, url: &url::Url, depth: usize) -> bool {
+        let probability = self.probability(url, depth);
+        probability >= 1.0 || (self.draw(url) as f64) < probability * u64::MAX as f64
+    }
+
+    /// The draw of `url`, uniform over the `u64` range
+    pub fn draw(&self, url: &url::Url) -> u64 {
+        // The hash is mixed so that urls differing by a last character
+        // still differ in their high bits
+        let mut draw = fnv1a(&format!("{}:{}", self.seed, url));
+        draw ^= draw >> 33;
+        draw = draw.wrapping_mul(0xff51afd7ed558ccd);
+        draw ^= draw >> 33;
+        draw = draw.wrapping_mul(0xc4ceb9fe1a85ec53);
+        draw ^ draw >> 33
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampler(rate: f64, depth_decay: f64, patterns: &[&str]) -> Sampler {
+        Sampler::new(&SamplingConfig {
+            rate: rate.try_into().unwrap(),
+            depth_decay: depth_decay.try_into().unwrap(),
+            patterns: patterns.iter().map(|p| p.parse().unwrap()).collect(),
+            seed: 7,
+        })
+        .unwrap()
+    }
+
+    fn url(path: &str) -> url::Url {
+        url::Url::parse("https://example.com/")
+            .unwrap()
+            .join(path)
+            .unwrap()
+    }
+
+    #[test]
+    fn probabilities_decay_with_depth_unless_a_pattern_matches() {
+        let sampler = sampler(0.5, 0.5, &["/products/=0.01", "/docs/=1"]);
+        assert_eq!(sampler.probability(&url("a"), 1), 0.5);
+        assert_eq!(sampler.probability(&url("a"), 3), 0.125);
+        assert_eq!(sampler.probability(&url("products/1"), 1), 0.01);
+        assert_eq!(sampler.probability(&url("docs/a"), 5), 1.0);
+        assert!(sampler.samples(&url("docs/a"), 5));
+        assert!(!self::sampler(0.0, 1.0, &[]).samples(&url("a"), 1));
+    }
+
+    #[test]
+    fn samples_follow_their_probability() {
+        let sampler = sampler(0.25, 1.0, &[]);
+        let n_urls = 10_000;
+        let drawn = (0..n_urls)
+            .filter(|i| sampler.samples(&url(&format!("page/{}", i)), 1))
+            .count();
+        let fraction = drawn as f64 / n_urls as f64;
+        assert!((0.23..0.27).contains(&fraction), "drew {}", fraction);
+    }
+
+    #[test]
+    fn draws_depend_on_the_seed_only() {
+        let (first, second) = (sampler(0.5, 1.0, &[]), sampler(0.1, 0.1, &[]));
+        assert_eq!(first.draw(&url("a")), second.draw(&url("a")));
+        assert_ne!(first.draw(&url("a")), first.draw(&url("b")));
+        let other = Sampler::new(&SamplingConfig::default()).unwrap();
+        assert_ne!(first.draw(&url("a")), other.draw(&url("a")));
+    }
+
+    #[test]
+    fn probabilities_are_bounded() {
+        assert_eq!("0.5".parse(), Ok(Probability(0.5)));
+        assert!("1.5".parse::<Probability>().is_err());
+        assert!("-0.1".parse::<Probability>().is_err());
+        assert!("NaN".parse::<Probability>().is_err());
+        assert!(serde_json::from_str::<Probability>("2").is_err());
+    }
+}
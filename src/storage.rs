@@ -0,0 +1,803 @@
+//! Persistence of webpages on disk.
+//!
+//! A storage directory is leased by a single crawl at a time: several
+//! crawls may run in the same process, each in its own directory.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::OnceLock;
+
+use base64::Engine;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::config::{HeaderCapture, DEFAULT_MIN_FREE_BYTES};
+pub use crate::config::{
+    OversizePolicy, OverwritePolicy, RouteTarget, StorageConfig, StorageLayout, StorageRoute,
+};
+#[cfg(feature = "encryption")]
+use crate::crypt::EncryptionKey;
+use crate::error::{CrawlerError, Result};
+use crate::frontier::SeedMetadata;
+use crate::manifest::{self, CacheHeaders, Manifest, ManifestEntry};
+use crate::naming::NamingTemplate;
+use crate::page;
+use crate::parse::ContentStats;
+#[cfg(feature = "object-store")]
+use crate::remote::RemoteStorage;
+
+/// Name of the directory of the objects of a content-addressed storage
+pub const OBJECTS_DIR: &str = "objects";
+
+/// The canonical paths of the storage directories leased in the process
+fn leased() -> &'static std::sync::Mutex<HashSet<PathBuf>> {
+    static LEASED: OnceLock<std::sync::Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    LEASED.get_or_init(Default::default)
+}
+
+/// The exclusive use of a storage directory by a crawl in the
+/// process, released when dropped
+#[derive(Debug)]
+pub struct StorageLease {
+    path: PathBuf,
+}
+
+impl Drop for StorageLease {
+    fn drop(&mut self) {
+        leased().lock().unwrap().remove(&self.path);
+    }
+}
+
+/// The paths named by a naming template, each claimed by
+/// the first url rendering it
+#[derive(Debug, Default)]
+struct Names {
+    files: HashMap<PathBuf, url::Url>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl Names {
+    /// Claim `path` for `url`, unless claimed by another url or
+    /// conflicting with the directories of the claimed paths
+    fn claim(&mut self, path: &Path, url: &url::Url) -> bool {
+        if let Some(owner) = self.files.get(path) {
+            return owner == url;
+        }
+        let dirs = path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty());
+        if self.dirs.contains(path) || dirs.clone().any(|dir| self.files.contains_key(dir)) {
+            return false;
+        }
+        self.dirs.extend(dirs.map(Path::to_path_buf));
+        self.files.insert(path.to_path_buf(), url.clone());
+        true
+    }
+}
+
+/// What a stored page is recorded with besides its content
+#[derive(Debug, Clone)]
+pub struct PageAnnotations {
+    pub url: url::Url,
+    /// The headers the page was served with, whose caching headers and
+    /// captured ones are recorded
+    pub headers: HeaderMap,
+    /// The metadata of the seed the page was reached from
+    pub metadata: SeedMetadata,
+    /// The statistics of the content of the page
+    pub stats: Option<ContentStats>,
+}
+
+impl PageAnnotations {
+    /// The annotations of the page of `url`, served with no header
+    pub fn new(url: url::Url) -> Self {
+        Self {
+            url,
+            headers: HeaderMap::new(),
+            metadata: SeedMetadata::new(),
+            stats: None,
+        }
+    }
+
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: SeedMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn with_stats(mut self, stats: Option<ContentStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+}
+
+/// What the manifest entry of a stored page records besides its file
+#[derive(Debug, Default)]
+struct Annotations {
+    cache: CacheHeaders,
+    headers: BTreeMap<String, String>,
+    metadata: SeedMetadata,
+    stats: Option<ContentStats>,
+}
+
+/// The storage for persisting webpages
+#[derive(Debug)]
+pub struct Storage {
+    path: PathBuf,
+    layout: StorageLayout,
+    overwrite: OverwritePolicy,
+    max_page_bytes: Option<usize>,
+    oversize: OversizePolicy,
+    routes: Vec<StorageRoute>,
+    headers: HeaderCapture,
+    min_free_bytes: u64,
+    sequence: Option<AtomicI64>,
+    naming: Option<NamingTemplate>,
+    names: std::sync::Mutex<Names>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<EncryptionKey>,
+    #[cfg(feature = "object-store")]
+    remote: Option<RemoteStorage>,
+    records_lock: Mutex<()>,
+    /// The latest entry of every stored url, tracked unless pages
+    /// are overwritten
+    stored: std::sync::Mutex<HashMap<url::Url, ManifestEntry>>,
+}
+
+impl Storage {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            layout: StorageLayout::default(),
+            overwrite: OverwritePolicy::default(),
+            max_page_bytes: None,
+            oversize: OversizePolicy::default(),
+            routes: Vec::new(),
+            headers: HeaderCapture::default(),
+            min_free_bytes: DEFAULT_MIN_FREE_BYTES,
+            sequence: None,
+            naming: None,
+            names: Default::default(),
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            #[cfg(feature = "object-store")]
+            remote: None,
+            records_lock: Mutex::new(()),
+            stored: Default::default(),
+        }
+    }
+
+    pub fn with_layout(mut self, layout: StorageLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Apply `overwrite` to the pages of the urls already stored
+    pub fn with_overwrite(mut self, overwrite: OverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Apply `oversize` to the pages of more than `max_page_bytes`,
+    /// if given
+    pub fn with_max_page_bytes(
+        mut self,
+        max_page_bytes: Option<usize>,
+        oversize: OversizePolicy,
+    ) -> Self {
+        self.max_page_bytes = max_page_bytes;
+        self.oversize = oversize;
+        self
+    }
+
+    /// Store the pages of the media types of `routes` in their
+    /// destinations, in place of the storage root
+    pub fn with_routes(mut self, routes: Vec<StorageRoute>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Record the `headers` captured from the response of each page
+    pub fn with_headers(mut self, headers: HeaderCapture) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Refuse to store pages that would leave less than
+    /// `min_free_bytes` free on the storage disk
+    pub fn with_min_free_bytes(mut self, min_free_bytes: u64) -> Self {
+        self.min_free_bytes = min_free_bytes;
+        self
+    }
+
+    /// Record the sequence number of each write as the fetch time of the
+    /// page, instead of the current time, so that crawls writing the same
+    /// pages in the same order record identical manifests
+    pub fn with_sequential_timestamps(mut self) -> Self {
+        self.sequence = Some(AtomicI64::new(0));
+        self
+    }
+
+    /// Name the pages of a flat or versioned storage by `naming`, if given,
+    /// instead of the hash of their url
+    pub fn with_naming(mut self, naming: Option<NamingTemplate>) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Store the pages as configured by `config`, but for its directory
+    pub fn with_config(self, config: &StorageConfig) -> Result<Self> {
+        Ok(self
+            .with_layout(config.layout)
+            .with_overwrite(config.overwrite)
+            .with_max_page_bytes(config.max_page_bytes, config.oversize)
+            .with_routes(config.routes.clone())
+            .with_headers(config.headers.clone())
+            .with_min_free_bytes(config.min_free_bytes)
+            .with_naming(config.naming()?))
+    }
+
+    /// Encrypt the stored pages with `key`, and decrypt them on reads
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, key: EncryptionKey) -> Self {
+        self.encryption = Some(key);
+        self
+    }
+
+    /// Mirror the storage directory to the object store `remote`
+    #[cfg(feature = "object-store")]
+    pub fn with_remote(mut self, remote: RemoteStorage) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn layout(&self) -> StorageLayout {
+        self.layout
+    }
+
+    pub fn overwrite(&self) -> OverwritePolicy {
+        self.overwrite
+    }
+
+    pub fn min_free_bytes(&self) -> u64 {
+        self.min_free_bytes
+    }
+
+    /// Create the storage directory, and load the pages already
+    /// stored in it unless they are overwritten
+    pub async fn setup(&self) -> Result<()> {
+        if let Some(route) = self
+            .routes
+            .iter()
+            .find(|route| !route.target.is_within_root())
+        {
+            return Err(CrawlerError::InvalidConfig(vec![format!(
+                "the storage route `{}` leaves the storage root",
+                route
+            )]));
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(route) = self.routes.iter().find(|route| {
+            self.encryption.is_some() && matches!(route.target, RouteTarget::Jsonl(_))
+        }) {
+            return Err(CrawlerError::Encryption(format!(
+                "the pages routed by `{}` cannot be encrypted",
+                route
+            )));
+        }
+        tokio::fs::create_dir_all(&self.path).await?;
+        if self.overwrite != OverwritePolicy::Overwrite || self.naming.is_some() {
+            let manifest = Manifest::load(&self.path).await?;
+            // The names of the stored pages are claimed again in the order
+            // they were stored, so that every url keeps its name
+            if self.naming.is_some() {
+                for entry in manifest.entries() {
+                    self.url_to_path(&entry.url);
+                }
+            }
+            if self.overwrite != OverwritePolicy::Overwrite {
+                *self.stored.lock().unwrap() = manifest
+                    .latest()
+                    .into_iter()
+                    .map(|(url, entry)| (url.clone(), entry.clone()))
+                    .collect();
+            }
+        }
+        self.check_space(0)
+    }
+
+    /// Lease the storage directory, once set up, for the exclusive use of
+    /// a crawl, or fail with [`CrawlerError::StorageInUse`] if another
+    /// crawl or monitor of the process uses it
+    pub async fn lease(&self) -> Result<StorageLease> {
+        let path = tokio::fs::canonicalize(&self.path).await?;
+        if !leased().lock().unwrap().insert(path.clone()) {
+            return Err(CrawlerError::StorageInUse(self.path.clone()));
+        }
+        Ok(StorageLease { path })
+    }
+
+    /// Check that storing `len` more bytes leaves enough free space on
+    /// the storage disk, or fail with [`CrawlerError::DiskFull`]
+    pub fn check_space(&self, len: usize) -> Result<()> {
+        let required = self.min_free_bytes.saturating_add(len as u64);
+        match available_space(&self.path) {
+            Some(available) if available < required => Err(CrawlerError::DiskFull {
+                path: self.path.clone(),
+                available,
+                required,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The path of the page of `url`, named by the naming template if
+    /// any, or by the hash of the url if the template names it like the
+    /// page of another url
+    pub fn url_to_path(&self, url: &url::Url) -> PathBuf {
+        let hash = hex::encode(Sha1::digest(url.as_str().as_bytes()).as_slice());
+        let named = self
+            .naming
+            .as_ref()
+            .and_then(|naming| naming.render(url, &hash))
+            .filter(|path| self.names.lock().unwrap().claim(path, url));
+        named.unwrap_or_else(|| {
+            let mut path = PathBuf::from(hash);
+            path.set_extension("html");
+            path
+        })
+    }
+
+    /// The path of the version of `url` fetched at `timestamp`
+    pub fn version_path(&self, url: &url::Url, timestamp: i64) -> PathBuf {
+        let mut path = self.url_to_path(url).with_extension("");
+        path.push(timestamp.to_string());
+        path.set_extension("html");
+        path
+    }
+
+    /// The path of the object holding the content of `hash`
+    pub fn object_path(&self, hash: &str) -> PathBuf {
+        let mut path = PathBuf::from(OBJECTS_DIR);
+        path.push(hash);
+        path.set_extension("html");
+        path
+    }
+
+    /// Persist the page and record it in the manifest, along with its
+    /// `annotations`: the caching headers and the captured ones of the
+    /// headers it was served with, the metadata of the seed it was
+    /// reached from and the statistics of its content.
+    ///
+    /// A page of a routed media type is stored in the directory of its
+    /// route, or appended to the JSON lines file of its route without
+    /// being recorded in the manifest.
+    pub async fn serialize(
+        &self,
+        page: impl AsRef<[u8]>,
+        annotations: &PageAnnotations,
+    ) -> Result<ManifestEntry> {
+        let url = &annotations.url;
+        let content_type = page::media_type(&annotations.headers);
+        if let Some(RouteTarget::Jsonl(file)) = content_type.as_deref().and_then(|t| self.route(t))
+        {
+            return self
+                .append_page(
+                    file,
+                    page.as_ref(),
+                    url,
+                    content_type,
+                    annotations.metadata.clone(),
+                )
+                .await;
+        }
+        let recorded = Annotations {
+            cache: CacheHeaders::from_headers(&annotations.headers),
+            headers: self.headers.capture(&annotations.headers),
+            metadata: annotations.metadata.clone(),
+            stats: annotations.stats.clone(),
+        };
+        self.store(page.as_ref(), url, content_type.as_deref(), recorded)
+            .await
+    }
+
+    /// The destination of the pages of the media type `content_type`,
+    /// preferring an exact match over a whole type
+    pub fn route(&self, content_type: &str) -> Option<&RouteTarget> {
+        let whole_type = content_type
+            .split_once('/')
+            .map(|(kind, _)| format!("{}/*", kind))?;
+        self.routes
+            .iter()
+            .find(|route| route.content_type == content_type)
+            .or_else(|| {
+                self.routes
+                    .iter()
+                    .find(|route| route.content_type == whole_type)
+            })
+            .map(|route| &route.target)
+    }
+
+    /// The part of `page` within the max page size, along with its
+    /// size if truncated, or [`CrawlerError::PageTooLarge`] if the page
+    /// is skipped
+    fn fit<'a>(&self, page: &'a [u8], url: &url::Url) -> Result<(&'a [u8], Option<usize>)> {
+        match self.max_page_bytes {
+            Some(max) if page.len() > max => match self.oversize {
+                OversizePolicy::Skip => Err(CrawlerError::PageTooLarge {
+                    url: url.to_string(),
+                    size: page.len(),
+                    max,
+                }),
+                OversizePolicy::Truncate => Ok((&page[..max], Some(page.len()))),
+                OversizePolicy::HeadersOnly => Ok((&page[..0], Some(page.len()))),
+            },
+            _ => Ok((page, None)),
+        }
+    }
+
+    /// The fetch time recorded for a page written now
+    fn fetched_at(&self) -> i64 {
+        match &self.sequence {
+            Some(sequence) => sequence.fetch_add(1, Ordering::Relaxed),
+            None => chrono::Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Store the page of `content_type`, if declared, in the directory
+    /// of its route or the storage root
+    async fn store(
+        &self,
+        page: &[u8],
+        url: &url::Url,
+        content_type: Option<&str>,
+        annotations: Annotations,
+    ) -> Result<ManifestEntry> {
+        let binary = page::is_binary(content_type, page);
+        let (page, truncated_from) = self.fit(page, url)?;
+        let previous = match self.overwrite {
+            OverwritePolicy::Overwrite => None,
+            _ => self.stored.lock().unwrap().get(url).cloned(),
+        };
+        if let Some(previous) = previous
+            .as_ref()
+            .filter(|previous| self.keeps(previous, page, &annotations.cache))
+        {
+            tracing::debug!("  -> Keeping the stored page of {}", url);
+            return Ok(previous.clone());
+        }
+        #[cfg(feature = "encryption")]
+        let encrypted = match &self.encryption {
+            Some(key) => Some(key.encrypt(page)?),
+            None => None,
+        };
+        #[cfg(not(feature = "encryption"))]
+        let encrypted: Option<Vec<u8>> = None;
+        let content = encrypted.as_deref().unwrap_or(page);
+        self.check_space(content.len())?;
+        let fetched_at = self.fetched_at();
+        let path = match self.layout {
+            StorageLayout::Flat
+                if previous.is_some() && self.overwrite == OverwritePolicy::KeepBoth =>
+            {
+                self.version_path(url, fetched_at)
+            }
+            StorageLayout::Flat => self.url_to_path(url),
+            StorageLayout::Versioned => self.version_path(url, fetched_at),
+            StorageLayout::ContentAddressed => self.object_path(&manifest::content_hash(page)),
+        };
+        let path = match content_type.and_then(|t| self.route(t)) {
+            Some(RouteTarget::Dir(dir)) => dir.join(path),
+            _ => path,
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(self.path.join(parent)).await?;
+        }
+        // An object of the same hash and size holds the same content
+        let stored = self.layout == StorageLayout::ContentAddressed
+            && tokio::fs::metadata(self.path.join(&path))
+                .await
+                .is_ok_and(|metadata| metadata.len() == content.len() as u64);
+        if !stored {
+            let mut file = tokio::fs::File::create(self.path.join(&path)).await?;
+            file.write_all(content).await?;
+            file.flush().await?;
+            #[cfg(feature = "object-store")]
+            if let Some(remote) = &self.remote {
+                remote.upload(&self.path, &path).await?;
+            }
+        }
+        let entry = ManifestEntry::new(url.clone(), path, page, fetched_at)
+            .with_cache(annotations.cache)
+            .with_headers(annotations.headers)
+            .with_metadata(annotations.metadata)
+            .with_stats(annotations.stats)
+            .with_truncated_from(truncated_from)
+            .with_binary(binary)
+            .with_encrypted(encrypted.is_some());
+        self.append_record(manifest::MANIFEST_FILE, &entry).await?;
+        if self.overwrite != OverwritePolicy::Overwrite {
+            self.stored
+                .lock()
+                .unwrap()
+                .insert(url.clone(), entry.clone());
+        }
+        Ok(entry)
+    }
+
+    /// Append the record of a page of `content_type` to the JSON lines
+    /// `file` of the storage root, instead of storing it in a file of
+    /// its own and recording it in the manifest
+    async fn append_page(
+        &self,
+        file: &str,
+        page: &[u8],
+        url: &url::Url,
+        content_type: Option<String>,
+        metadata: SeedMetadata,
+    ) -> Result<ManifestEntry> {
+        let binary = page::is_binary(content_type.as_deref(), page);
+        let (page, truncated_from) = self.fit(page, url)?;
+        self.check_space(page.len())?;
+        let json = content_type
+            .as_deref()
+            .is_some_and(|t| t == "application/json" || t.ends_with("+json"));
+        let (body, base64) = match std::str::from_utf8(page) {
+            Ok(text) if !binary => match serde_json::from_str(text) {
+                Ok(body) if json => (body, false),
+                _ => (text.into(), false),
+            },
+            _ => (
+                base64::engine::general_purpose::STANDARD
+                    .encode(page)
+                    .into(),
+                true,
+            ),
+        };
+        let entry = ManifestEntry::new(url.clone(), file.into(), page, self.fetched_at())
+            .with_metadata(metadata)
+            .with_truncated_from(truncated_from)
+            .with_binary(binary);
+        let record = RoutedPage {
+            url: entry.url.clone(),
+            content_type,
+            fetched_at: entry.fetched_at,
+            hash: entry.hash.clone(),
+            truncated_from,
+            metadata: entry.metadata.clone(),
+            body,
+            base64,
+        };
+        self.append_record(file, &record).await?;
+        Ok(entry)
+    }
+
+    /// Whether the overwrite policy keeps the `previous` page stored
+    /// for the url of a new `page` served with `cache`
+    fn keeps(&self, previous: &ManifestEntry, page: &[u8], cache: &CacheHeaders) -> bool {
+        match self.overwrite {
+            OverwritePolicy::Overwrite | OverwritePolicy::KeepBoth => false,
+            OverwritePolicy::Skip => true,
+            OverwritePolicy::CompareHash => match (&previous.cache.etag, &cache.etag) {
+                (Some(stored), Some(etag)) => stored == etag,
+                _ => previous.hash == manifest::content_hash(page),
+            },
+        }
+    }
+
+    /// The content of the page stored for `entry`, decrypted if need be
+    pub async fn read(&self, entry: &ManifestEntry) -> Result<Vec<u8>> {
+        let content = tokio::fs::read(self.path.join(&entry.path)).await?;
+        if !entry.encrypted {
+            return Ok(content);
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.encryption {
+            return key.decrypt(&content);
+        }
+        Err(CrawlerError::Encryption(format!(
+            "{:?} is encrypted and no key is given",
+            entry.path
+        )))
+    }
+
+    /// Upload the files of the storage directory not mirrored yet to
+    /// the object store, if any
+    #[cfg(feature = "object-store")]
+    pub async fn sync_remote(&self) -> Result<()> {
+        if let Some(remote) = &self.remote {
+            let n_files = remote.sync(&self.path).await?;
+            tracing::info!("==> Uploaded {} more files to {}", n_files, remote);
+        }
+        Ok(())
+    }
+
+    /// Append a record as a JSON line to `file_name` in the storage root
+    pub async fn append_record(&self, file_name: &str, record: &impl Serialize) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let _guard = self.records_lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.join(file_name))
+            .await?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+}
+
+/// The record of a page routed to a JSON lines file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutedPage {
+    pub url: url::Url,
+    pub content_type: Option<String>,
+    pub fetched_at: i64,
+    /// The hash of the body
+    pub hash: String,
+    /// The size of the page, if its body is truncated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated_from: Option<usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: SeedMetadata,
+    /// The parsed body of a JSON page, the text of another UTF-8 page,
+    /// or the base64-encoded bytes of a binary page
+    pub body: serde_json::Value,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub base64: bool,
+}
+
+impl TryFrom<&url::Url> for Storage {
+    type Error = CrawlerError;
+
+    fn try_from(url: &url::Url) -> Result<Self> {
+        let ts = chrono::Utc::now().timestamp_millis();
+        let host = url.host_str().ok_or(CrawlerError::NoUrlHost)?;
+        // Crawls of the same host started at once get distinct directories
+        let suffix = uuid::Uuid::new_v4().simple();
+        Ok(Storage::new(
+            format!("webpages/{}_{}_{}", host, ts, suffix).into(),
+        ))
+    }
+}
+
+/// The bytes available to unprivileged users on the filesystem of `path`,
+/// if they can be told
+#[cfg(unix)]
+fn available_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stat` is written on success
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/page.html";
+
+    async fn setup(dir: &Path, configure: impl FnOnce(Storage) -> Storage) -> Storage {
+        let storage = configure(Storage::new(dir.to_path_buf()));
+        storage.setup().await.unwrap();
+        storage
+    }
+
+    async fn store(storage: &Storage, page: &str) -> Result<ManifestEntry> {
+        let annotations = PageAnnotations::new(url::Url::parse(URL).unwrap());
+        storage.serialize(page, &annotations).await
+    }
+
+    async fn stored(dir: &Path, entry: &ManifestEntry) -> String {
+        tokio::fs::read_to_string(dir.join(&entry.path))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn oversized_pages_follow_the_oversize_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let limit = |oversize| move |s: Storage| s.with_max_page_bytes(Some(4), oversize);
+
+        let storage = setup(dir.path(), limit(OversizePolicy::Skip)).await;
+        assert!(matches!(
+            store(&storage, "0123456789").await,
+            Err(CrawlerError::PageTooLarge {
+                size: 10,
+                max: 4,
+                ..
+            })
+        ));
+        let entry = store(&storage, "0123").await.unwrap();
+        assert_eq!(entry.truncated_from, None);
+        assert_eq!(stored(dir.path(), &entry).await, "0123");
+
+        let storage = setup(dir.path(), limit(OversizePolicy::Truncate)).await;
+        let entry = store(&storage, "0123456789").await.unwrap();
+        assert_eq!((entry.size, entry.truncated_from), (4, Some(10)));
+        assert_eq!(stored(dir.path(), &entry).await, "0123");
+
+        let storage = setup(dir.path(), limit(OversizePolicy::HeadersOnly)).await;
+        let entry = store(&storage, "0123456789").await.unwrap();
+        assert_eq!((entry.size, entry.truncated_from), (0, Some(10)));
+        assert_eq!(stored(dir.path(), &entry).await, "");
+    }
+
+    async fn store_tagged(storage: &Storage, page: &str, etag: &str) -> ManifestEntry {
+        let mut headers = HeaderMap::new();
+        headers.insert(reqwest::header::ETAG, etag.parse().unwrap());
+        let annotations = PageAnnotations::new(url::Url::parse(URL).unwrap()).with_headers(headers);
+        storage.serialize(page, &annotations).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn stored_urls_follow_the_overwrite_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = |overwrite| move |s: Storage| s.with_overwrite(overwrite);
+
+        let storage = setup(dir.path(), policy(OverwritePolicy::Overwrite)).await;
+        let first = store(&storage, "first").await.unwrap();
+        let second = store(&storage, "second").await.unwrap();
+        assert_eq!(first.path, second.path);
+        assert_eq!(stored(dir.path(), &second).await, "second");
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = setup(dir.path(), policy(OverwritePolicy::KeepBoth)).await;
+        let first = store(&storage, "first").await.unwrap();
+        let second = store(&storage, "second").await.unwrap();
+        assert_ne!(first.path, second.path);
+        assert_eq!(stored(dir.path(), &first).await, "first");
+        assert_eq!(stored(dir.path(), &second).await, "second");
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = setup(dir.path(), policy(OverwritePolicy::Skip)).await;
+        let first = store(&storage, "first").await.unwrap();
+        let second = store(&storage, "second").await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(stored(dir.path(), &second).await, "first");
+    }
+
+    #[tokio::test]
+    async fn compare_hash_keeps_the_unchanged_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = setup(dir.path(), |s| {
+            s.with_overwrite(OverwritePolicy::CompareHash)
+        })
+        .await;
+        let first = store(&storage, "first").await.unwrap();
+        assert_eq!(store(&storage, "first").await.unwrap(), first);
+        let changed = store(&storage, "changed").await.unwrap();
+        assert_ne!(changed, first);
+        assert_eq!(stored(dir.path(), &changed).await, "changed");
+
+        // The ETag, when both pages have one, decides over the content
+        let tagged = store_tagged(&storage, "tagged", "\"v1\"").await;
+        assert_eq!(store_tagged(&storage, "retagged", "\"v1\"").await, tagged);
+        let updated = store_tagged(&storage, "tagged", "\"v2\"").await;
+        assert_ne!(updated, tagged);
+    }
+}
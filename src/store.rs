@@ -0,0 +1,87 @@
+//! Selection of the pages to store.
+//!
+//! A [`StorePolicy`] decides which of the fetched pages are persisted.
+//! Pages that are not stored are still scraped, so that the traversal
+//! continues through them.
+use std::fmt;
+use std::sync::Arc;
+
+use scraper::Selector;
+
+use crate::config::StoreFilter;
+use crate::error::{CrawlerError, Result};
+use crate::parse::Document;
+
+/// A predicate over the url and the body of a page
+pub type StorePredicate = dyn Fn(&url::Url, &[u8]) -> bool + Send + Sync;
+
+/// Which of the fetched pages are stored
+#[derive(Clone, Default)]
+pub enum StorePolicy {
+    /// Store every page
+    #[default]
+    All,
+    /// Store the pages with an element matching the selector
+    Selector(Selector),
+    /// Store the pages whose body matches the expression
+    Regex(regex::bytes::Regex),
+    /// Store the pages accepted by the predicate
+    Predicate(Arc<StorePredicate>),
+}
+
+impl StorePolicy {
+    /// Store the pages with an element matching the CSS `selector`
+    pub fn selector(selector: &str) -> Result<Self> {
+        Selector::parse(selector)
+            .map(Self::Selector)
+            .map_err(|err| CrawlerError::StorePolicy(format!("`{}`: {:?}", selector, err)))
+    }
+
+    /// Store the pages whose body matches the regular expression `pattern`
+    pub fn regex(pattern: &str) -> Result<Self> {
+        regex::bytes::Regex::new(pattern)
+            .map(Self::Regex)
+            .map_err(|err| CrawlerError::StorePolicy(err.to_string()))
+    }
+
+    /// Store the pages for which `predicate` holds
+    pub fn predicate<F>(predicate: F) -> Self
+    where
+        F: Fn(&url::Url, &[u8]) -> bool + Send + Sync + 'static,
+    {
+        Self::Predicate(Arc::new(predicate))
+    }
+
+    /// The policy described by `filter`
+    pub fn from_filter(filter: &StoreFilter) -> Result<Self> {
+        match filter {
+            StoreFilter::All => Ok(Self::All),
+            StoreFilter::Selector(selector) => Self::selector(selector),
+            StoreFilter::Regex(pattern) => Self::regex(pattern),
+        }
+    }
+
+    /// Whether the page of `url`, parsed into `document` unless
+    /// binary, is stored
+    pub fn stores(&self, url: &url::Url, body: &[u8], document: Option<&Document>) -> bool {
+        match self {
+            Self::All => true,
+            Self::Selector(selector) => {
+                document.is_some_and(|document| document.contains(selector))
+            }
+            Self::Regex(regex) => regex.is_match(body),
+            Self::Predicate(predicate) => predicate(url, body),
+        }
+    }
+}
+
+impl fmt::Debug for StorePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => write!(f, "All"),
+            Self::Selector(selector) => f.debug_tuple("Selector").field(selector).finish(),
+            Self::Regex(regex) => f.debug_tuple("Regex").field(regex).finish(),
+            Self::Predicate(_) => write!(f, "Predicate"),
+        }
+    }
+}
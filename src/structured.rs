@@ -0,0 +1,234 @@
+//! Extraction of the structured data of pages.
+//!
+//! The [`StructuredDataExtractor`] records the `application/ld+json`
+//! blocks and the microdata items of every page. It optionally checks
+//! that the items of common schema.org types have the properties that
+//! search engines require.
+use scraper::{ElementRef, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::extract::Extractor;
+use crate::parse::Document;
+
+/// Name of the structured data report
+pub const STRUCTURED_DATA_FILE: &str = "structured_data.jsonl";
+
+/// The properties required by common schema.org types. A property
+/// listed as `a|b` requires either property.
+const REQUIRED_PROPERTIES: &[(&str, &[&str])] = &[
+    ("Article", &["headline"]),
+    ("BlogPosting", &["headline"]),
+    ("BreadcrumbList", &["itemListElement"]),
+    ("Event", &["name", "startDate", "location"]),
+    ("NewsArticle", &["headline"]),
+    ("Offer", &["price", "priceCurrency"]),
+    ("Organization", &["name"]),
+    ("Person", &["name"]),
+    ("Product", &["name", "offers|review|aggregateRating"]),
+    ("Recipe", &["name", "image"]),
+];
+
+/// A microdata item
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MicrodataItem {
+    /// The `itemtype` urls of the item
+    #[serde(rename = "type")]
+    pub types: Vec<String>,
+    /// The values of each property, strings or nested items
+    pub properties: Map<String, Value>,
+}
+
+/// An item of a schema.org type missing required properties
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaIssue {
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub missing: Vec<String>,
+}
+
+/// The structured data of a page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredData {
+    pub url: url::Url,
+    pub json_ld: Vec<Value>,
+    pub microdata: Vec<MicrodataItem>,
+    /// The issues of the items, when validating
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<SchemaIssue>,
+}
+
+/// Records the JSON-LD and microdata of the pages
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StructuredDataExtractor {
+    validate: bool,
+}
+
+impl StructuredDataExtractor {
+    /// An extractor that also validates the items of common schema.org types
+    pub fn validating() -> Self {
+        Self { validate: true }
+    }
+}
+
+impl Extractor for StructuredDataExtractor {
+    fn report_file(&self) -> &str {
+        STRUCTURED_DATA_FILE
+    }
+
+    fn extract(&self, url: &url::Url, document: &Document) -> Option<Value> {
+        let json_ld = json_ld(document);
+        let microdata = microdata(document);
+        if json_ld.is_empty() && microdata.is_empty() {
+            return None;
+        }
+        let mut issues = Vec::new();
+        if self.validate {
+            for block in &json_ld {
+                validate_json_ld(block, &mut issues);
+            }
+            for item in &microdata {
+                validate_microdata(item, &mut issues);
+            }
+        }
+        let record = StructuredData {
+            url: url.clone(),
+            json_ld,
+            microdata,
+            issues,
+        };
+        serde_json::to_value(record).ok()
+    }
+}
+
+/// The parsed `application/ld+json` blocks of a page, skipping invalid ones
+pub fn json_ld(document: &Document) -> Vec<Value> {
+    let selector = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    document
+        .select(&selector)
+        .filter_map(|script| serde_json::from_str(&script.text().collect::<String>()).ok())
+        .collect()
+}
+
+/// The top-level microdata items of a page
+pub fn microdata(document: &Document) -> Vec<MicrodataItem> {
+    let selector = Selector::parse("[itemscope]:not([itemprop])").unwrap();
+    document.select(&selector).map(item).collect()
+}
+
+fn item(element: ElementRef) -> MicrodataItem {
+    let mut properties = Map::new();
+    collect_properties(element, &mut properties);
+    MicrodataItem {
+        types: element
+            .value()
+            .attr("itemtype")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+        properties,
+    }
+}
+
+/// Collect the properties of the item of `element`, without
+/// descending into nested items
+fn collect_properties(element: ElementRef, properties: &mut Map<String, Value>) {
+    for child in element.children().filter_map(ElementRef::wrap) {
+        let nested = child.value().attr("itemscope").is_some();
+        if let Some(names) = child.value().attr("itemprop") {
+            let value = match nested {
+                true => serde_json::to_value(item(child)).unwrap_or_default(),
+                false => Value::String(property_value(child)),
+            };
+            for name in names.split_whitespace() {
+                let values = properties
+                    .entry(name)
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                if let Value::Array(values) = values {
+                    values.push(value.clone());
+                }
+            }
+        }
+        if !nested {
+            collect_properties(child, properties);
+        }
+    }
+}
+
+/// The value of a property element, after its kind
+fn property_value(element: ElementRef) -> String {
+    let value = element.value();
+    let attr = match value.name() {
+        "meta" => "content",
+        "a" | "area" | "link" => "href",
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => "src",
+        "object" => "data",
+        "time" => "datetime",
+        "data" | "meter" => "value",
+        _ => return element.text().collect::<String>().trim().to_string(),
+    };
+    match value.attr(attr) {
+        Some(value) => value.to_string(),
+        None => element.text().collect::<String>().trim().to_string(),
+    }
+}
+
+/// The required properties of `item_type` missing according to `has`
+fn missing_properties(item_type: &str, has: impl Fn(&str) -> bool) -> Option<SchemaIssue> {
+    let (_, required) = REQUIRED_PROPERTIES
+        .iter()
+        .find(|(schema_type, _)| *schema_type == item_type)?;
+    let missing: Vec<_> = required
+        .iter()
+        .filter(|property| !property.split('|').any(&has))
+        .map(|property| property.replace('|', " or "))
+        .collect();
+    (!missing.is_empty()).then(|| SchemaIssue {
+        item_type: item_type.to_string(),
+        missing,
+    })
+}
+
+fn validate_json_ld(value: &Value, issues: &mut Vec<SchemaIssue>) {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                validate_json_ld(value, issues);
+            }
+        }
+        Value::Object(object) => {
+            let types = match object.get("@type") {
+                Some(Value::String(item_type)) => vec![item_type.as_str()],
+                Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            for item_type in types {
+                let item_type = item_type.rsplit('/').next().unwrap_or(item_type);
+                issues.extend(missing_properties(item_type, |property| {
+                    object.contains_key(property)
+                }));
+            }
+            for value in object.values() {
+                validate_json_ld(value, issues);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn validate_microdata(item: &MicrodataItem, issues: &mut Vec<SchemaIssue>) {
+    for item_type in &item.types {
+        let item_type = item_type.rsplit('/').next().unwrap_or(item_type);
+        issues.extend(missing_properties(item_type, |property| {
+            item.properties.contains_key(property)
+        }));
+    }
+    for values in item.properties.values() {
+        for value in values.as_array().into_iter().flatten() {
+            if let Ok(nested) = serde_json::from_value::<MicrodataItem>(value.clone()) {
+                validate_microdata(&nested, issues);
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Delays between the requests to a host.
+//!
+//! A [`HostThrottle`] spaces the requests of a scraper to every host by a
+//! min delay, whatever the number of concurrent tasks: every request takes
+//! the next free turn of its host, so that the requests to a host queue up
+//! while those to other hosts go on.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// The turns of the requests to every host
+#[derive(Debug)]
+pub struct HostThrottle {
+    delay: Duration,
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostThrottle {
+    /// Space the requests to every host by `delay`
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            next: Mutex::default(),
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Wait for the turn of a request to the host of `url`
+    pub async fn wait(&self, url: &url::Url) {
+        let host = url.host_str().unwrap_or_default().to_string();
+        let turn = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let turn = next
+                .get(&host)
+                .copied()
+                .filter(|turn| *turn > now)
+                .unwrap_or(now);
+            next.insert(host, turn + self.delay);
+            turn
+        };
+        tokio::time::sleep_until(turn).await;
+    }
+}
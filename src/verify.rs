@@ -0,0 +1,107 @@
+//! Integrity check of a storage directory.
+//!
+//! The stored pages are compared against the manifest: every recorded
+//! page must exist and match the recorded content hash, or size if it
+//! is encrypted, while page files recorded by no entry are reported as
+//! extra. Of the entries
+//! sharing a path, as with the flat layout, only the most recent one
+//! describes the file.
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::gc;
+use crate::index::INDEX_FILE;
+use crate::manifest::{self, Manifest, ManifestEntry};
+
+/// A stored page whose content does not match its manifest entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorruptedPage {
+    pub url: url::Url,
+    pub path: PathBuf,
+    /// The hash recorded in the manifest
+    pub expected: String,
+    /// The hash of the stored content
+    pub found: String,
+}
+
+/// The outcome of the integrity check of a storage directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerifyReport {
+    /// Number of stored pages checked
+    pub checked: usize,
+    /// The pages whose file is missing
+    pub missing: Vec<PathBuf>,
+    /// The pages whose content does not match the manifest
+    pub corrupted: Vec<CorruptedPage>,
+    /// The page and temporary files recorded by no manifest entry
+    pub extra: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// Whether the storage directory matches its manifest
+    pub fn is_valid(&self) -> bool {
+        self.missing.is_empty() && self.corrupted.is_empty() && self.extra.is_empty()
+    }
+
+    /// Number of missing, corrupted and extra files
+    pub fn n_issues(&self) -> usize {
+        self.missing.len() + self.corrupted.len() + self.extra.len()
+    }
+}
+
+/// Check the pages stored in `root` against its manifest
+pub async fn verify(root: &Path) -> Result<VerifyReport> {
+    let manifest = Manifest::load(root).await?;
+    let mut latest: HashMap<&Path, &ManifestEntry> = HashMap::new();
+    for entry in manifest.entries() {
+        match latest.get(entry.path.as_path()) {
+            Some(current) if current.fetched_at > entry.fetched_at => {}
+            _ => {
+                latest.insert(&entry.path, entry);
+            }
+        }
+    }
+    let mut report = VerifyReport::default();
+    let mut entries: Vec<_> = latest.into_values().collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    for entry in entries {
+        let content = match tokio::fs::read(root.join(&entry.path)).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                report.missing.push(entry.path.clone());
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        report.checked += 1;
+        let found = manifest::content_hash(&content);
+        // Without the key, an encrypted page can only be checked by size
+        let corrupted = match entry.encrypted {
+            true => content.len() != entry.stored_size(),
+            false => found != entry.hash,
+        };
+        if corrupted {
+            report.corrupted.push(CorruptedPage {
+                url: entry.url.clone(),
+                path: entry.path.clone(),
+                expected: entry.hash.clone(),
+                found,
+            });
+        }
+    }
+    let recorded: HashSet<&Path> = manifest
+        .entries()
+        .iter()
+        .map(|e| e.path.as_path())
+        .collect();
+    report.extra = gc::files(root)
+        .await?
+        .into_iter()
+        .filter(|path| !recorded.contains(path.as_path()) && path != Path::new(INDEX_FILE))
+        .collect();
+    report.extra.sort();
+    Ok(report)
+}
@@ -0,0 +1,110 @@
+//! Notification of the lifecycle of a crawl to webhooks.
+//!
+//! Every configured url receives a `POST` with a JSON [`WebhookPayload`]
+//! when the crawl starts, when it visits a milestone share of its page
+//! budget, and when it finishes or fails. The payloads of the start and
+//! the end of the crawl carry its [run metadata](RunMetadata), with the
+//! final stats once finished.
+//!
+//! Notifications are best effort: a webhook that fails or times out is
+//! logged, and never interrupts the crawl.
+use std::time::Duration;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::config::WebhookConfig;
+use crate::run::RunMetadata;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A stage of the crawl lifecycle
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Started,
+    /// The crawl visited `percent` of its max number of pages
+    Milestone {
+        percent: u8,
+        visited: usize,
+        max_pages: usize,
+    },
+    Finished,
+    Failed {
+        error: String,
+    },
+}
+
+/// The body of a webhook notification
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    pub run_id: Uuid,
+    #[serde(flatten)]
+    pub event: WebhookEvent,
+    /// Time of the event in milliseconds since the epoch
+    pub sent_at: i64,
+    pub run: Option<RunMetadata>,
+}
+
+/// The webhooks notified of a crawl
+#[derive(Debug, Clone, Default)]
+pub struct Webhooks {
+    client: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self { client, config }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.config.urls.is_empty()
+    }
+
+    /// The milestone reached by visiting the `visited`-th of `max_pages`,
+    /// if any
+    pub fn milestone(&self, visited: usize, max_pages: usize) -> Option<u8> {
+        let percent = |n: usize| n * 100 / max_pages.max(1);
+        self.config
+            .milestones
+            .iter()
+            .copied()
+            .filter(|milestone| {
+                let milestone = *milestone as usize;
+                percent(visited) >= milestone && percent(visited.saturating_sub(1)) < milestone
+            })
+            .max()
+    }
+
+    /// Post `event` of run `run_id` to every webhook
+    pub async fn notify(&self, run_id: Uuid, event: WebhookEvent, run: Option<RunMetadata>) {
+        let payload = WebhookPayload {
+            run_id,
+            event,
+            sent_at: chrono::Utc::now().timestamp_millis(),
+            run,
+        };
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(err) => return tracing::warn!("cannot serialize webhook payload: {}", err),
+        };
+        for url in &self.config.urls {
+            let response = self
+                .client
+                .post(url.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(err) = response {
+                tracing::warn!("webhook {} failed: {}", url, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Pool of tasks persisting pages off the fetch path.
+//!
+//! Crawl tasks hand the fetched bodies to a bounded queue served by
+//! a configurable number of writers, so that slow disks hold back
+//! the queue rather than the network task slots. A full queue still
+//! makes the crawl tasks wait, which bounds the memory held by the
+//! pending writes.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::sync::{mpsc, Mutex, Notify};
+use tracing::Instrument;
+
+use crate::budget::Reservation;
+pub use crate::config::DEFAULT_WRITERS;
+use crate::error::{CrawlerError, Result};
+use crate::executor::{self, Executor, TaskHandle};
+use crate::storage::PageAnnotations;
+use crate::Storage;
+
+/// Number of pending writes allowed per writer
+const QUEUE_CAPACITY_PER_WRITER: usize = 16;
+
+/// A page pending to be written
+#[derive(Debug)]
+struct WriteJob {
+    body: Bytes,
+    annotations: PageAnnotations,
+    _reservation: Reservation,
+}
+
+/// Counters of the writer pool
+#[derive(Debug, Default)]
+struct Metrics {
+    queued: AtomicUsize,
+    max_queued: AtomicUsize,
+    written: AtomicUsize,
+    failed: AtomicUsize,
+    /// The bytes available on the disk when a write was refused for lack of space
+    disk_full: std::sync::Mutex<Option<u64>>,
+    refused: std::sync::Mutex<Vec<url::Url>>,
+    /// The pages queued or being written
+    pending: std::sync::Mutex<Vec<url::Url>>,
+    /// Notified whenever a pending page is written or failed
+    done: Notify,
+}
+
+impl Metrics {
+    fn finish(&self, url: &url::Url) {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(i) = pending.iter().position(|pending| pending == url) {
+            pending.swap_remove(i);
+        }
+        self.done.notify_waiters();
+    }
+}
+
+/// Summary of the writes of a pool
+#[derive(Debug, Clone, Default)]
+pub struct WriterStats {
+    pub written: usize,
+    pub failed: usize,
+    /// The max number of pages found waiting in the queue
+    pub max_queue_depth: usize,
+    /// The bytes available on the disk when a write was refused for
+    /// lack of space, if any
+    pub disk_full: Option<u64>,
+    /// The pages refused for lack of disk space
+    pub refused: Vec<url::Url>,
+}
+
+/// A handle for queueing pages to a [`WriterPool`]
+#[derive(Debug, Clone)]
+pub struct StorageWriter {
+    tx: mpsc::Sender<WriteJob>,
+    metrics: Arc<Metrics>,
+}
+
+impl StorageWriter {
+    /// Queue `body` to be stored as the page of its `annotations`,
+    /// waiting if the queue is full. The reservation of the body in the
+    /// in-flight budget is released once it is written.
+    pub async fn write(
+        &self,
+        body: Bytes,
+        annotations: PageAnnotations,
+        reservation: Reservation,
+    ) -> Result<()> {
+        let url = &annotations.url;
+        let depth = self.metrics.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        self.metrics.max_queued.fetch_max(depth, Ordering::Relaxed);
+        tracing::trace!("storage queue depth: {}", depth);
+        self.metrics.pending.lock().unwrap().push(url.clone());
+        let job = WriteJob {
+            body,
+            annotations,
+            _reservation: reservation,
+        };
+        self.tx.send(job).await.map_err(|err| {
+            self.metrics.queued.fetch_sub(1, Ordering::Relaxed);
+            self.metrics.finish(&err.0.annotations.url);
+            CrawlerError::WriterClosed
+        })
+    }
+
+    /// The number of pages waiting to be written
+    pub fn queue_depth(&self) -> usize {
+        self.metrics.queued.load(Ordering::Relaxed)
+    }
+}
+
+/// A bounded pool of writer tasks sharing a queue
+pub struct WriterPool {
+    writer: StorageWriter,
+    workers: Vec<TaskHandle<()>>,
+}
+
+impl WriterPool {
+    /// Spawn on `executor` `n_writers` tasks persisting pages to `storage`
+    pub fn new(storage: Arc<Storage>, n_writers: usize, executor: &dyn Executor) -> Self {
+        let n_writers = n_writers.max(1);
+        let (tx, rx) = mpsc::channel::<WriteJob>(n_writers * QUEUE_CAPACITY_PER_WRITER);
+        let rx = Arc::new(Mutex::new(rx));
+        let metrics = Arc::new(Metrics::default());
+        let workers = (0..n_writers)
+            .map(|_| {
+                let rx = Arc::clone(&rx);
+                let storage = Arc::clone(&storage);
+                let metrics = Arc::clone(&metrics);
+                let worker = async move {
+                    loop {
+                        let job = rx.lock().await.recv().await;
+                        let Some(job) = job else { break };
+                        metrics.queued.fetch_sub(1, Ordering::Relaxed);
+                        let url = &job.annotations.url;
+                        match storage.serialize(&job.body, &job.annotations).await {
+                            Ok(_) => metrics.written.fetch_add(1, Ordering::Relaxed),
+                            Err(CrawlerError::DiskFull { available, .. }) => {
+                                tracing::warn!("not enough disk space to store page {}", url);
+                                metrics.disk_full.lock().unwrap().get_or_insert(available);
+                                metrics.refused.lock().unwrap().push(url.clone());
+                                metrics.failed.fetch_add(1, Ordering::Relaxed)
+                            }
+                            Err(err @ CrawlerError::PageTooLarge { .. }) => {
+                                tracing::warn!("not storing page: {}", err);
+                                metrics.failed.fetch_add(1, Ordering::Relaxed)
+                            }
+                            Err(err) => {
+                                tracing::warn!("error storing page {}: {:?}", url, err);
+                                metrics.failed.fetch_add(1, Ordering::Relaxed)
+                            }
+                        };
+                        metrics.finish(url);
+                    }
+                };
+                executor::spawn(executor, worker.in_current_span())
+            })
+            .collect();
+        Self {
+            writer: StorageWriter { tx, metrics },
+            workers,
+        }
+    }
+
+    /// A handle for queueing pages to the pool
+    pub fn writer(&self) -> StorageWriter {
+        self.writer.clone()
+    }
+
+    /// Whether a write was refused for lack of disk space
+    pub fn is_disk_full(&self) -> bool {
+        self.writer.metrics.disk_full.lock().unwrap().is_some()
+    }
+
+    /// Stop queueing pages through the pool handle and wait at most
+    /// `timeout` for the pages already queued to be written, returning
+    /// those still not written by then.
+    ///
+    /// Unlike [`close`](Self::close), the writers are not waited for, so
+    /// that outstanding handles, e.g. of stalled visits, cannot hold the
+    /// pool open.
+    pub async fn flush(self, timeout: Duration) -> Vec<url::Url> {
+        let metrics = Arc::clone(&self.writer.metrics);
+        drop(self.writer);
+        let drained = async {
+            loop {
+                let done = metrics.done.notified();
+                tokio::pin!(done);
+                done.as_mut().enable();
+                if metrics.pending.lock().unwrap().is_empty() {
+                    break;
+                }
+                done.await;
+            }
+        };
+        let _ = tokio::time::timeout(timeout, drained).await;
+        let pending = metrics.pending.lock().unwrap().clone();
+        pending
+    }
+
+    /// Wait for the queued pages to be written and stop the writers.
+    ///
+    /// Pages queued through outstanding handles are still written
+    /// before the writers stop.
+    pub async fn close(self) -> Result<WriterStats> {
+        let metrics = Arc::clone(&self.writer.metrics);
+        drop(self.writer);
+        for worker in self.workers {
+            worker.await.map_err(|_| CrawlerError::TaskPanicked)?;
+        }
+        let disk_full = *metrics.disk_full.lock().unwrap();
+        let refused = std::mem::take(&mut *metrics.refused.lock().unwrap());
+        Ok(WriterStats {
+            written: metrics.written.load(Ordering::Relaxed),
+            failed: metrics.failed.load(Ordering::Relaxed),
+            max_queue_depth: metrics.max_queued.load(Ordering::Relaxed),
+            disk_full,
+            refused,
+        })
+    }
+}
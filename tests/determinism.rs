@@ -0,0 +1,40 @@
+//! Two deterministic crawls of the same site record identical manifests.
+#[path = "../benches/support/mod.rs"]
+mod support;
+
+use std::path::Path;
+
+use support::SyntheticSite;
+use webcrawler::config::CrawlConfig;
+use webcrawler::manifest::MANIFEST_FILE;
+use webcrawler::Crawler;
+
+const SITE: SyntheticSite = SyntheticSite {
+    n_pages: 50,
+    links_per_page: 4,
+    text_size: 2_000,
+};
+
+async fn crawl(base: &str, dir: &Path) -> Vec<u8> {
+    let mut config = CrawlConfig {
+        root_url: format!("{}/page/0", base),
+        deterministic: true,
+        ..CrawlConfig::default()
+    };
+    config.storage.dir = Some(dir.to_path_buf());
+    Crawler::from_config(config).unwrap().run().await.unwrap();
+    std::fs::read(dir.join(MANIFEST_FILE)).unwrap()
+}
+
+#[tokio::test]
+async fn deterministic_crawls_record_identical_manifests() {
+    let base = SITE.serve().await;
+    let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+    let first = crawl(&base, dirs[0].path()).await;
+    let second = crawl(&base, dirs[1].path()).await;
+    assert_eq!(
+        String::from_utf8_lossy(&first).lines().count(),
+        SITE.n_pages
+    );
+    assert!(first == second, "the manifests differ");
+}
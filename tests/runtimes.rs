@@ -0,0 +1,70 @@
+//! Crawls run to completion on the smol and async-std runtimes, without
+//! an ambient tokio runtime.
+#![cfg(any(feature = "smol", feature = "async-std"))]
+
+#[path = "../benches/support/mod.rs"]
+mod support;
+
+use std::path::Path;
+use std::sync::Arc;
+
+use support::SyntheticSite;
+use webcrawler::config::CrawlConfig;
+use webcrawler::executor::Executor;
+use webcrawler::manifest::MANIFEST_FILE;
+use webcrawler::Crawler;
+
+const SITE: SyntheticSite = SyntheticSite {
+    n_pages: 20,
+    links_per_page: 3,
+    text_size: 500,
+};
+
+/// Serve the site from a tokio runtime of its own, returning its base url
+fn serve() -> String {
+    let (base, served) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            base.send(SITE.serve().await).unwrap();
+            std::future::pending::<()>().await
+        })
+    });
+    served.recv().unwrap()
+}
+
+async fn crawl(executor: Arc<dyn Executor>, dir: &Path) -> usize {
+    let mut config = CrawlConfig {
+        root_url: format!("{}/page/0", serve()),
+        ..CrawlConfig::default()
+    };
+    config.storage.dir = Some(dir.to_path_buf());
+    Crawler::from_config(config)
+        .unwrap()
+        .with_executor(executor)
+        .run()
+        .await
+        .unwrap();
+    let manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+    manifest.lines().count()
+}
+
+#[cfg(feature = "smol")]
+#[test]
+fn crawls_on_smol() {
+    let dir = tempfile::tempdir().unwrap();
+    let executor = Arc::new(webcrawler::executor::SmolExecutor);
+    assert_eq!(smol::block_on(crawl(executor, dir.path())), SITE.n_pages);
+}
+
+#[cfg(feature = "async-std")]
+#[test]
+fn crawls_on_async_std() {
+    let dir = tempfile::tempdir().unwrap();
+    let executor = Arc::new(webcrawler::executor::AsyncStdExecutor);
+    let stored = async_std::task::block_on(crawl(executor, dir.path()));
+    assert_eq!(stored, SITE.n_pages);
+}
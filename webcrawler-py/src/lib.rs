@@ -0,0 +1,197 @@
+//! Python bindings of the crawler.
+//!
+//! ```python
+//! import webcrawler_py
+//!
+//! crawler = webcrawler_py.Crawler("https://example.com", max_pages=10)
+//! crawler.add_extractor("titles.jsonl", lambda url, html: {"url": url, "size": len(html)})
+//! for page in crawler.run():
+//!     print(page["status"], page["url"], len(page["links"]))
+//! ```
+//!
+//! The crawl runs in a background thread, while the visited pages are
+//! streamed to the iterator returned by [`Crawler::run`].
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use futures::StreamExt;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use webcrawler::config::Limits;
+use webcrawler::error::CrawlerError;
+use webcrawler::extract::Extractor;
+use webcrawler::page::CrawledPage;
+use webcrawler::parse::Document;
+use webcrawler::{Scraper, Storage, StorageLayout};
+
+fn to_py_err(err: CrawlerError) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+/// An extractor calling a Python function with the url and
+/// HTML of every page, reporting the JSON-serializable result
+struct PyExtractor {
+    report_file: String,
+    callback: PyObject,
+}
+
+impl Extractor for PyExtractor {
+    fn report_file(&self) -> &str {
+        &self.report_file
+    }
+
+    fn extract(&self, url: &url::Url, document: &Document) -> Option<serde_json::Value> {
+        let html = document.html();
+        Python::with_gil(|py| {
+            let result = self
+                .callback
+                .call1(py, (url.as_str(), html))
+                .and_then(|record| {
+                    if record.is_none(py) {
+                        return Ok(None);
+                    }
+                    let json: String = py
+                        .import("json")?
+                        .call_method1("dumps", (record,))?
+                        .extract()?;
+                    Ok(Some(json))
+                });
+            match result {
+                Ok(json) => json.and_then(|json| serde_json::from_str(&json).ok()),
+                Err(err) => {
+                    err.print(py);
+                    None
+                }
+            }
+        })
+    }
+}
+
+/// A crawler starting from a root url
+#[pyclass]
+struct Crawler {
+    root_url: String,
+    storage_dir: Option<PathBuf>,
+    versioned: bool,
+    limits: Limits,
+    extractors: Vec<PyExtractor>,
+}
+
+#[pymethods]
+impl Crawler {
+    #[new]
+    #[pyo3(signature = (root_url, storage_dir=None, max_pages=100, max_tasks=5, versioned=false))]
+    fn new(
+        root_url: String,
+        storage_dir: Option<PathBuf>,
+        max_pages: usize,
+        max_tasks: usize,
+        versioned: bool,
+    ) -> Self {
+        Self {
+            root_url,
+            storage_dir,
+            versioned,
+            limits: Limits {
+                max_tasks,
+                max_pages,
+                ..Limits::default()
+            },
+            extractors: Vec::new(),
+        }
+    }
+
+    /// Call `callback(url, html)` for every visited page, recording
+    /// its results other than `None` in `report_file`
+    fn add_extractor(&mut self, report_file: String, callback: PyObject) {
+        self.extractors.push(PyExtractor {
+            report_file,
+            callback,
+        });
+    }
+
+    /// Start the crawl, returning an iterator over the visited pages.
+    ///
+    /// The extractors added so far are moved to the crawl.
+    fn run(&mut self) -> PyResult<PageStream> {
+        let layout = if self.versioned {
+            StorageLayout::Versioned
+        } else {
+            StorageLayout::Flat
+        };
+        let storage = self
+            .storage_dir
+            .clone()
+            .map(|dir| Storage::new(dir).with_layout(layout));
+        let mut scraper = Scraper::default();
+        for extractor in self.extractors.drain(..) {
+            scraper = scraper.with_extractor(extractor);
+        }
+        let (tx, rx) = mpsc::unbounded();
+        let crawler = webcrawler::Crawler::new(self.root_url.clone(), storage, Some(scraper))
+            .map_err(to_py_err)?
+            .with_limits(self.limits.clone())
+            .with_pages(tx);
+        let handle = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?
+                .block_on(crawler.run())
+        });
+        Ok(PageStream {
+            pages: Mutex::new(rx),
+            handle: Mutex::new(Some(handle)),
+        })
+    }
+}
+
+/// An iterator over the pages visited by a running crawl
+#[pyclass]
+struct PageStream {
+    pages: Mutex<UnboundedReceiver<CrawledPage>>,
+    handle: Mutex<Option<JoinHandle<webcrawler::error::Result<()>>>>,
+}
+
+#[pymethods]
+impl PageStream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// The next visited page, as a dict. Errors of the crawl
+    /// are raised once all the visited pages are consumed.
+    fn __next__<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let page = py.allow_threads(|| {
+            let mut pages = self.pages.lock().unwrap();
+            futures::executor::block_on(pages.next())
+        });
+        let Some(page) = page else {
+            if let Some(handle) = self.handle.lock().unwrap().take() {
+                py.allow_threads(|| handle.join())
+                    .map_err(|_| PyRuntimeError::new_err("crawl panicked"))?
+                    .map_err(to_py_err)?;
+            }
+            return Ok(None);
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("url", page.url.as_str())?;
+        dict.set_item("final_url", page.final_url.as_str())?;
+        dict.set_item("status", page.status.as_u16())?;
+        dict.set_item("depth", page.depth)?;
+        dict.set_item("fetched_at", page.fetched_at)?;
+        let links: Vec<_> = page.discovered_links.iter().map(url::Url::as_str).collect();
+        dict.set_item("links", links)?;
+        dict.set_item("body", PyBytes::new(py, &page.body))?;
+        Ok(Some(dict))
+    }
+}
+
+#[pymodule]
+fn webcrawler_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Crawler>()?;
+    m.add_class::<PageStream>()?;
+    Ok(())
+}